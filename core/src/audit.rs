@@ -0,0 +1,150 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+
+use crate::{
+    guild_config,
+    models::{
+        AuditEvent,
+        RoleAssociation,
+    },
+    status::now,
+    storage::Storage,
+    util::Mentionable,
+};
+
+/// An event for the command in the message, changing nothing but the roles until `old` or `new` are set.
+pub fn event(msg: &Message, guild: GuildId, command: &str, roles: Vec<RoleId>) -> AuditEvent {
+    AuditEvent {
+        id: None,
+        server: guild,
+        user: msg.author.id,
+        member: None,
+        channel: msg.channel_id,
+        command: command.to_string(),
+        roles,
+        old: None,
+        new: None,
+        at: now(),
+    }
+}
+
+/// Keeps the event, then posts it to the server's audit channel if one is set up.
+///
+/// The change is already made by then, so a failed post is only reported to the operator.
+pub async fn record(ctx: &Context, storage: &dyn Storage, mut event: AuditEvent) -> CommandResult {
+    storage.save_audit_event(&mut event).await?;
+    if let Some(channel) = guild_config::load(ctx, event.server).await.audit_channel {
+        let posted = channel.send_message(ctx, |message| message
+            .embed(|e| e
+                .title("Audit log:")
+                .description(EventDisplay(&event))
+            )
+        ).await;
+        crate::errors::report(ctx, "Audit_Post", posted.map(drop).map_err(Into::into)).await;
+    }
+    Ok(())
+}
+
+/// The association as its values read in the audit log.
+pub fn describe(association: &RoleAssociation) -> String {
+    let mut description = Mentionable::from(association.role).to_string();
+    match (association.channel, association.server) {
+        (Some(channel), _) => description.push_str(&format!(" in {}", Mentionable::from(channel))),
+        (None, Some(_)) => description.push_str(" as the server's generic role"),
+        (None, None) => {},
+    }
+    if let Some(alias) = &association.alias {
+        description.push_str(&format!(", alias `{}`", alias));
+    }
+    if association.approval {
+        description.push_str(", needs approval");
+    }
+    description
+}
+
+pub struct EventDisplay<'a>(pub &'a AuditEvent);
+
+impl Display for EventDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let event = self.0;
+        write!(
+            f,
+            "{} used `{}` in {}",
+            Mentionable::from(event.user),
+            event.command,
+            Mentionable::from(event.channel),
+        )?;
+        if let Some(member) = event.member {
+            write!(f, " for {}", Mentionable::from(member))?;
+        }
+        for (ix, role) in event.roles.iter().enumerate() {
+            f.write_str(if ix == 0 { ": " } else { ", " })?;
+            write!(f, "{}", Mentionable::from(*role))?;
+        }
+        match (&event.old, &event.new) {
+            (Some(old), Some(new)) => write!(f, "\nFrom {}\nTo {}", old, new),
+            (Some(old), None) => write!(f, "\nWas {}", old),
+            (None, Some(new)) => write!(f, "\nNow {}", new),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn association(channel: Option<u64>, alias: Option<&str>, approval: bool) -> RoleAssociation {
+        RoleAssociation {
+            id: None,
+            channel: channel.map(ChannelId),
+            server: Some(GuildId(1)),
+            role: RoleId(2),
+            alias: alias.map(str::to_string),
+            approval,
+        }
+    }
+
+    #[test]
+    fn associations_read_as_their_values() {
+        assert_eq!(describe(&association(Some(3), None, false)), "<@&2> in <#3>");
+        assert_eq!(
+            describe(&association(None, Some("artists"), true)),
+            "<@&2> as the server's generic role, alias `artists`, needs approval",
+        );
+    }
+
+    #[test]
+    fn events_read_as_who_did_what() {
+        let mut event = AuditEvent {
+            id: None,
+            server: GuildId(1),
+            user: UserId(4),
+            member: None,
+            channel: ChannelId(3),
+            command: "join".to_string(),
+            roles: vec![RoleId(2), RoleId(5)],
+            old: None,
+            new: None,
+            at: 0,
+        };
+        assert_eq!(EventDisplay(&event).to_string(), "<@4> used `join` in <#3>: <@&2>, <@&5>");
+
+        event.member = Some(UserId(6));
+        event.roles = vec![RoleId(2)];
+        event.old = Some("before".to_string());
+        event.new = Some("after".to_string());
+        assert_eq!(
+            EventDisplay(&event).to_string(),
+            "<@4> used `join` in <#3> for <@6>: <@&2>\nFrom before\nTo after",
+        );
+    }
+}
@@ -1,37 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    fs::write,
+    time::{
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
 use ohg_bot_core::{
-    models::{
-        RoleAssociation,
-        RoleStatus,
+    config::{
+        self,
+        DatabaseConfig,
     },
     connect_db,
+    models::DiscordCredentials,
+};
+use futures::TryStreamExt;
+use serde::{
+    Deserialize,
+    Serialize,
 };
+use serde_json::Value;
 use wither::{
+    bson::{
+        Bson,
+        Document,
+    },
+    mongodb::{
+        error::Error,
+        Database,
+    },
     Model,
-    ModelCursor,
 };
-use futures::StreamExt;
+
+/// Bumped whenever the layout of an export changes incompatibly.
+pub const EXPORT_VERSION: u32 = 1;
+pub const REDACTED: &str = "<redacted>";
+const SECRETS: &[&str] = &["token", "private"];
+
+#[derive(Serialize, Deserialize)]
+pub struct Export {
+    pub version: u32,
+    pub exported_at: u64,
+    /// Whether credential secrets were replaced with `REDACTED`.
+    pub redacted: bool,
+    /// Documents as canonical extended JSON, by collection, by database ("base" or "rpg").
+    pub databases: BTreeMap<String, BTreeMap<String, Vec<Value>>>,
+}
 
 pub async fn main() {
-    let db = connect_db().await.base;
-    dump(
-        RoleAssociation::find(&db, None, None)
+    let mut args = std::env::args().skip_while(|arg| arg != "--dump").skip(1);
+    let mut output = None;
+    let mut redact = true;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = args.next(),
+            "--include-secrets" => redact = false,
+            _ => {},
+        }
+    }
+
+    let config = DatabaseConfig::load().unwrap_or_else(|errors| config::report(errors));
+    let handle = match connect_db(&config).await {
+        Ok(handle) => handle,
+        Err(e) => fail(format_args!("Failed to connect to the database: {}", e)),
+    };
+    let mut databases = BTreeMap::new();
+    databases.insert(
+        "base".to_string(),
+        export(&handle.base, redact)
             .await
-            .expect("Failed to search RoleAssociation")
-    )
-        .await
-        .expect("Failed to dump RoleAssociation");
-    dump(
-        RoleStatus::find(&db, None, None)
+            .unwrap_or_else(|e| fail(format_args!("Failed to export base database: {}", e))),
+    );
+    #[cfg(feature = "rpg")]
+    databases.insert(
+        "rpg".to_string(),
+        export(&handle.rpg, redact)
             .await
-            .expect("Failed to search RoleStatus")
-    )
-        .await
-        .expect("Failed to dump RoleStatus");
+            .unwrap_or_else(|e| fail(format_args!("Failed to export rpg database: {}", e))),
+    );
+
+    let export = Export {
+        version: EXPORT_VERSION,
+        exported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("<1970 not supported")
+            .as_secs(),
+        redacted: redact,
+        databases,
+    };
+    let json = serde_json::to_string_pretty(&export)
+        .unwrap_or_else(|e| fail(format_args!("Failed to serialize export: {}", e)));
+    match output {
+        Some(path) => write(&path, json)
+            .unwrap_or_else(|e| fail(format_args!("Failed to write {}: {}", path, e))),
+        None => println!("{}", json),
+    }
+}
+
+/// Prints why the export or restore failed, and exits.
+pub fn fail(problem: impl Display) -> ! {
+    eprintln!("{}", problem);
+    std::process::exit(1)
 }
 
-async fn dump<T: Model + std::fmt::Debug>(mut models: ModelCursor<T>) -> Result<(), wither::WitherError> {
-    while let Some(model) = models.next().await {
-        println!("{:?}", model?);
+async fn export(db: &Database, redact: bool) -> Result<BTreeMap<String, Vec<Value>>, Error> {
+    let mut collections = BTreeMap::new();
+    for name in db.list_collection_names(None).await? {
+        let mut documents: Vec<Document> = db.collection(&name)
+            .find(None, None)
+            .await?
+            .try_collect()
+            .await?;
+        if redact && name == DiscordCredentials::COLLECTION_NAME {
+            for document in &mut documents {
+                for secret in SECRETS {
+                    if document.contains_key(secret) {
+                        document.insert(*secret, REDACTED);
+                    }
+                }
+            }
+        }
+        collections.insert(
+            name,
+            documents
+                .into_iter()
+                .map(|document| Bson::Document(document).into_canonical_extjson())
+                .collect(),
+        );
     }
-    Ok(())
+    Ok(collections)
 }
@@ -1,81 +1,136 @@
-use ohg_bot_core::{models, connect_db};
-use wither::Model;
+use std::str::FromStr;
+
+use ohg_bot_core::{
+    config::{
+        self,
+        DatabaseConfig,
+    },
+    models,
+    connect_db,
+};
+use wither::{
+    mongodb::Database,
+    Model,
+};
 use serenity::model::prelude::*;
 
-pub async fn main() {
-    let db = connect_db().await.base;
+use crate::seed;
 
-    if models::DiscordCredentials::find_one(&db, None, None)
-        .await
-        .expect("Failed to search discord credentials")
-        .is_none()
-    {
-        models::DiscordCredentials {
-            id: None,
-            private: input("Private key:"),
-            public: input("Public key:"),
-            token: input("Bot token:"),
-            bot_id: input("Bot id:"),
-            prefix: input("Command prefix:"),
-            operator: input("Operator id:")
-                .parse()
-                .map(UserId)
-                .expect("Bad operator id"),
+const USAGE: &str = "Usage: --init [--from <seed file> [--dry-run]]";
+
+// Credentials are no longer stored in the database; see ohg.example.toml.
+pub async fn main() {
+    let mut args = std::env::args().skip_while(|arg| arg != "--init").skip(1);
+    let mut from = None;
+    let mut dry_run = false;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => match args.next() {
+                Some(path) if !path.starts_with("--") => from = Some(path),
+                _ => usage("--from requires a seed file"),
+            },
+            "--dry-run" => dry_run = true,
+            _ => usage(format_args!("Unknown argument {}", arg)),
         }
-            .save(&db, None)
-            .await
-            .expect("Failed to save");
     }
-    while input("Insert RoleAssociation (true/false)?").parse().expect("Not true/false") {
-        models::RoleAssociation {
+    if let Some(from) = from {
+        if let Err(errors) = seed::run(&from, dry_run).await {
+            eprintln!("Failed to apply {}:", from);
+            for error in errors {
+                eprintln!("  {}", error);
+            }
+            std::process::exit(1);
+        }
+        return;
+    }
+    if dry_run {
+        usage("--dry-run requires --from <seed file>");
+    }
+
+    let config = DatabaseConfig::load().unwrap_or_else(|errors| config::report(errors));
+    let db = match connect_db(&config).await {
+        Ok(handle) => handle.base,
+        Err(e) => {
+            eprintln!("Failed to connect to the database: {}", e);
+            std::process::exit(1);
+        },
+    };
+
+    while ask::<bool>("Insert RoleAssociation (true/false)?") {
+        let mut association = models::RoleAssociation {
             id: None,
-            channel: if input("Add channel?").parse().expect("Not true/false") {
-                input("Channel id:")
-                    .parse()
-                    .map(ChannelId)
-                    .map(Some)
-                    .expect("Bad channel id")
+            channel: if ask("Add channel (true/false)?") {
+                Some(ChannelId(ask("Channel id:")))
             } else {
                 None
             },
-            server: if input("Add server?").parse().expect("Not true/false") {
-                input("Server id:")
-                    .parse()
-                    .map(GuildId)
-                    .map(Some)
-                    .expect("Bad server id")
+            server: if ask("Add server (true/false)?") {
+                Some(GuildId(ask("Server id:")))
             } else {
                 None
             },
-            role: input("Role id:")
-                .parse()
-                .map(RoleId)
-                .expect("Bad role id"),
-        }
-            .save(&db, None)
-            .await
-            .expect("Failed to save");
+            role: RoleId(ask("Role id:")),
+            alias: Some(input("Alias (blank for none):"))
+                .filter(|alias| !alias.is_empty())
+                .map(|alias| alias.to_lowercase()),
+            approval: ask("Require approval to join (true/false)?"),
+        };
+        save(&db, &mut association).await;
     }
-    while input("Insert RoleStatus (true/false)?").parse().expect("Not true/false") {
-        models::RoleStatus {
+    while ask::<bool>("Insert RoleStatus (true/false)?") {
+        let mut status = models::RoleStatus {
             id: None,
-            role: input("Role id:")
-                .parse()
-                .map(RoleId)
-                .expect("Bad role id"),
+            role: RoleId(ask("Role id:")),
+            server: if ask("Add server (true/false)?") {
+                Some(GuildId(ask("Server id:")))
+            } else {
+                None
+            },
+            rules: Vec::new(),
+        };
+        save(&db, &mut status).await;
+    }
+}
+
+fn usage(problem: impl std::fmt::Display) -> ! {
+    eprintln!("{}", problem);
+    eprintln!("{}", USAGE);
+    std::process::exit(2);
+}
+
+async fn save(db: &Database, model: &mut impl Model) {
+    if let Err(e) = model.save(db, None).await {
+        eprintln!("Failed to save: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Asks again until the answer parses, so a typo doesn't lose what was entered before it.
+fn ask<T: FromStr>(msg: &str) -> T {
+    loop {
+        match input(msg).parse() {
+            Ok(value) => return value,
+            Err(_) => println!("Not understood; try again."),
         }
-            .save(&db, None)
-            .await
-            .expect("Failed to save");
     }
 }
 
 fn input<T: std::fmt::Display>(msg: T) -> String {
     println!("{}", msg);
-    let mut ret = "".into();
-    std::io::stdin().read_line(&mut ret).expect("Failed to get input");
+    let mut ret = String::new();
+    match std::io::stdin().read_line(&mut ret) {
+        Ok(0) => {
+            eprintln!("Input ended");
+            std::process::exit(1);
+        },
+        Ok(_) => {},
+        Err(e) => {
+            eprintln!("Failed to get input: {}", e);
+            std::process::exit(1);
+        },
+    }
     while ret.ends_with(|c: char| c.is_whitespace()) {
         ret.remove(ret.len() - 1);
     }
     ret
-}
\ No newline at end of file
+}
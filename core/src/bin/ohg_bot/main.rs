@@ -1,6 +1,9 @@
 mod init;
 mod runtime;
 mod dump;
+mod restore;
+mod sign;
+mod seed;
 
 // This is done to prevent compile time from exploding with every new command
 
@@ -16,6 +19,14 @@ async fn main() {
                 dump::main().await;
                 return;
             },
+            "--restore" => {
+                restore::main().await;
+                return;
+            },
+            "--sign-interaction" => {
+                sign::main();
+                return;
+            },
             #[cfg(feature = "rpg")]
             "--rpg" => {
                 ohg_bot_rpg::main().await;
@@ -24,5 +35,6 @@ async fn main() {
             _ => {},
         }
     }
-    runtime::main().await;
+    let status = runtime::main().await;
+    std::process::exit(status);
 }
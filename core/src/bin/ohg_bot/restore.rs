@@ -0,0 +1,116 @@
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fs::read_to_string,
+};
+
+use ohg_bot_core::{
+    config::{
+        self,
+        DatabaseConfig,
+    },
+    connect_db,
+    models::DiscordCredentials,
+};
+use serde_json::Value;
+use wither::{
+    bson::{
+        doc,
+        Bson,
+    },
+    mongodb::{
+        options::ReplaceOptions,
+        Database,
+    },
+    Model,
+};
+
+use crate::dump::{
+    fail,
+    Export,
+    EXPORT_VERSION,
+};
+
+pub async fn main() {
+    let path = if let Some(path) = std::env::args()
+        .skip_while(|arg| arg != "--restore")
+        .nth(1)
+    {
+        path
+    } else {
+        eprintln!("Usage: --restore <export.json>");
+        std::process::exit(2);
+    };
+    let contents = read_to_string(&path)
+        .unwrap_or_else(|e| fail(format_args!("Failed to read {}: {}", path, e)));
+    let export: Export = serde_json::from_str(&contents)
+        .unwrap_or_else(|e| fail(format_args!("Failed to parse {}: {}", path, e)));
+    if export.version > EXPORT_VERSION {
+        fail(format_args!(
+            "{} is export version {}, but this build only understands up to {}",
+            path,
+            export.version,
+            EXPORT_VERSION,
+        ));
+    }
+
+    let config = DatabaseConfig::load().unwrap_or_else(|errors| config::report(errors));
+    let handle = match connect_db(&config).await {
+        Ok(handle) => handle,
+        Err(e) => fail(format_args!("Failed to connect to the database: {}", e)),
+    };
+    for (database, collections) in export.databases {
+        let db = match database.as_str() {
+            "base" => &handle.base,
+            #[cfg(feature = "rpg")]
+            "rpg" => &handle.rpg,
+            _ => {
+                println!("Skipping database {}, which this build does not use", database);
+                continue;
+            },
+        };
+        if let Err(e) = restore(db, export.redacted, collections).await {
+            fail(format_args!("Failed to restore {}: {}", database, e));
+        }
+    }
+}
+
+/// Upserts every document by `_id`, so restoring the same export twice changes nothing.
+async fn restore(
+    db: &Database,
+    redacted: bool,
+    collections: BTreeMap<String, Vec<Value>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (name, documents) in collections {
+        if redacted && name == DiscordCredentials::COLLECTION_NAME {
+            println!("{}: skipped {} documents with redacted secrets", name, documents.len());
+            continue;
+        }
+        let collection = db.collection(&name);
+        let (mut inserted, mut updated, mut unchanged) = (0, 0, 0);
+        for document in documents {
+            let document = match Bson::try_from(document)? {
+                Bson::Document(document) => document,
+                other => return Err(format!("{}: expected a document, found {}", name, other).into()),
+            };
+            let id = document
+                .get("_id")
+                .cloned()
+                .ok_or_else(|| format!("{}: document without _id", name))?;
+            let mut options = ReplaceOptions::default();
+            options.upsert = Some(true);
+            let result = collection
+                .replace_one(doc!{ "_id": id }, document, options)
+                .await?;
+            if result.upserted_id.is_some() {
+                inserted += 1;
+            } else if result.modified_count > 0 {
+                updated += 1;
+            } else {
+                unchanged += 1;
+            }
+        }
+        println!("{}: {} inserted, {} updated, {} unchanged", name, inserted, updated, unchanged);
+    }
+    Ok(())
+}
@@ -0,0 +1,388 @@
+use std::{
+    fmt::Display,
+    fs::{
+        read_to_string,
+        OpenOptions,
+    },
+    io::Write as _,
+    path::Path,
+};
+
+use futures::TryStreamExt;
+use ohg_bot_core::{
+    config::{
+        self,
+        Config,
+        DatabaseConfig,
+    },
+    connect_db,
+    models::{
+        RoleAssociation,
+        RoleStatus,
+        StatusRule,
+    },
+};
+use serde::Deserialize;
+use serenity::model::prelude::*;
+use wither::{
+    mongodb::Database,
+    Model,
+};
+
+/// Everything `--init --from` provisions, read from TOML or, by extension, JSON.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Seed {
+    /// Written as the config file, unless one already exists.
+    #[serde(default)]
+    config: Option<toml::Value>,
+    #[serde(default)]
+    role_associations: Vec<AssociationSeed>,
+    #[serde(default)]
+    role_statuses: Vec<StatusSeed>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AssociationSeed {
+    #[serde(default)]
+    channel: Option<Id>,
+    #[serde(default)]
+    server: Option<Id>,
+    role: Id,
+    #[serde(default)]
+    alias: Option<String>,
+    #[serde(default)]
+    approval: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StatusSeed {
+    role: Id,
+    #[serde(default)]
+    server: Option<Id>,
+    #[serde(default)]
+    rules: Vec<StatusRule>,
+}
+
+/// Discord ids may be written as numbers or, to survive JSON tooling, strings.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Id {
+    Number(u64),
+    Text(String),
+}
+
+impl Id {
+    fn parse(&self, field: &str, errors: &mut Vec<String>) -> Option<u64> {
+        let id = match self {
+            Id::Number(id) => Some(*id),
+            Id::Text(text) => text.trim().parse().ok(),
+        };
+        match id {
+            Some(id) if id != 0 => Some(id),
+            _ => {
+                errors.push(format!("{}: not a Discord id: {}", field, self));
+                None
+            },
+        }
+    }
+}
+
+/// Resolves an optional id: absent is fine, but present and invalid rejects the entry.
+fn optional<T>(id: Option<Option<T>>) -> Option<Option<T>> {
+    match id {
+        None => Some(None),
+        Some(Some(id)) => Some(Some(id)),
+        Some(None) => None,
+    }
+}
+
+impl Display for Id {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Id::Number(id) => write!(f, "{}", id),
+            Id::Text(text) => write!(f, "{:?}", text),
+        }
+    }
+}
+
+/// Applies a seed file, skipping anything already present.
+///
+/// Nothing is written when `dry_run` is set; the intended changes are printed either way.
+pub async fn run(path: &str, dry_run: bool) -> Result<(), Vec<String>> {
+    let seed = read(path).map_err(|e| vec![e])?;
+    let mut errors = Vec::new();
+    let (associations, statuses) = entries(&seed, &mut errors);
+
+    let config_path = config::path();
+    let config_exists = Path::new(&config_path).exists();
+    let config_text = match &seed.config {
+        Some(value) if !config_exists => match toml::to_string(value) {
+            Ok(text) => Some(text),
+            Err(e) => {
+                errors.push(format!("config: {}", e));
+                None
+            },
+        },
+        _ => None,
+    };
+    // The database comes from the seed's config when that is what will be written
+    let database = match &config_text {
+        Some(text) => Config::parse(text).map(|config| config.database),
+        None => DatabaseConfig::load(),
+    };
+    let database = match database {
+        Ok(database) if errors.is_empty() => database,
+        Ok(_) => return Err(errors),
+        Err(config_errors) => {
+            errors.extend(config_errors.into_iter().map(|e| format!("config: {}", e)));
+            return Err(errors);
+        },
+    };
+
+    if let Some(text) = config_text {
+        plan(dry_run, "write", format_args!("config {}", config_path));
+        if !dry_run {
+            write_config(&config_path, &text).map_err(|e| vec![format!("{}: {}", config_path, e)])?;
+        }
+    } else if seed.config.is_some() {
+        plan(dry_run, "keep", format_args!("existing config {}", config_path));
+    }
+
+    let db = connect_db(&database)
+        .await
+        .map_err(|e| vec![format!("database.url: {}", e)])?
+        .base;
+    apply(&db, dry_run, associations, statuses)
+        .await
+        .map_err(|e| vec![e.to_string()])
+}
+
+/// The models the seed describes, with every bad id as an error.
+fn entries(seed: &Seed, errors: &mut Vec<String>) -> (Vec<RoleAssociation>, Vec<RoleStatus>) {
+    let associations: Vec<RoleAssociation> = seed.role_associations
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, association)| {
+            let field = |name| format!("role_associations[{}].{}", ix, name);
+            let channel = association.channel
+                .as_ref()
+                .map(|id| id.parse(&field("channel"), errors).map(ChannelId));
+            let server = association.server
+                .as_ref()
+                .map(|id| id.parse(&field("server"), errors).map(GuildId));
+            let role = association.role.parse(&field("role"), errors).map(RoleId);
+            if channel.is_none() && server.is_none() {
+                errors.push(format!("{}: needs a channel, a server, or both", field("role")));
+            }
+            let alias = association.alias
+                .as_ref()
+                .map(|alias| alias.trim().to_lowercase())
+                .filter(|alias| !alias.is_empty());
+            Some(RoleAssociation {
+                id: None,
+                channel: optional(channel)?,
+                server: optional(server)?,
+                role: role?,
+                alias,
+                approval: association.approval,
+            })
+        })
+        .collect();
+    let statuses: Vec<RoleStatus> = seed.role_statuses
+        .iter()
+        .enumerate()
+        .filter_map(|(ix, status)| {
+            let field = |name| format!("role_statuses[{}].{}", ix, name);
+            let server = status.server
+                .as_ref()
+                .map(|id| id.parse(&field("server"), errors).map(GuildId));
+            let role = status.role.parse(&field("role"), errors).map(RoleId);
+            Some(RoleStatus {
+                id: None,
+                role: role?,
+                server: optional(server)?,
+                rules: status.rules.clone(),
+            })
+        })
+        .collect();
+    (associations, statuses)
+}
+
+/// Creates the config readable only by its owner, as it holds the bot's token.
+fn write_config(path: &str, text: &str) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(text.as_bytes())
+}
+
+fn read(path: &str) -> Result<Seed, String> {
+    let contents = read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let json = Path::new(path)
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("json"));
+    if json {
+        serde_json::from_str(&contents).map_err(|e| format!("{}: {}", path, e))
+    } else {
+        toml::from_str(&contents).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+async fn apply(
+    db: &Database,
+    dry_run: bool,
+    associations: Vec<RoleAssociation>,
+    statuses: Vec<RoleStatus>,
+) -> Result<(), wither::WitherError> {
+    let mut existing: Vec<RoleAssociation> = RoleAssociation::find(db, None, None)
+        .await?
+        .try_collect()
+        .await?;
+    for mut association in associations {
+        let description = format!(
+            "role association {} (channel {}, server {})",
+            association.role,
+            association.channel.map_or("-".to_string(), |channel| channel.to_string()),
+            association.server.map_or("-".to_string(), |server| server.to_string()),
+        );
+        let found = existing
+            .iter_mut()
+            .find(|found|
+                found.role == association.role
+                && found.channel == association.channel
+                && found.server == association.server
+            );
+        match found {
+            Some(found) if found.alias == association.alias && found.approval == association.approval =>
+                plan(dry_run, "keep", &description),
+            Some(found) => {
+                plan(dry_run, "update", format_args!(
+                    "{} alias to {:?}, approval to {}",
+                    description,
+                    association.alias,
+                    association.approval,
+                ));
+                found.alias = association.alias;
+                found.approval = association.approval;
+                if !dry_run {
+                    found.save(db, None).await?;
+                }
+            },
+            None => {
+                plan(dry_run, "create", &description);
+                if !dry_run {
+                    association.save(db, None).await?;
+                }
+                existing.push(association);
+            },
+        }
+    }
+
+    let mut existing: Vec<RoleStatus> = RoleStatus::find(db, None, None)
+        .await?
+        .try_collect()
+        .await?;
+    for mut status in statuses {
+        let description = format!("role status {}", status.role);
+        let found = existing
+            .iter_mut()
+            .find(|found| found.role == status.role);
+        match found {
+            Some(found) if found.rules == status.rules && found.server == status.server =>
+                plan(dry_run, "keep", &description),
+            Some(found) => {
+                plan(dry_run, "update", format_args!("{} to {} rules", description, status.rules.len()));
+                found.server = status.server;
+                found.rules = status.rules;
+                if !dry_run {
+                    found.save(db, None).await?;
+                }
+            },
+            None => {
+                plan(dry_run, "create", &description);
+                if !dry_run {
+                    status.save(db, None).await?;
+                }
+                existing.push(status);
+            },
+        }
+    }
+
+    Ok(())
+}
+
+fn plan(dry_run: bool, action: &str, description: impl Display) {
+    if dry_run {
+        println!("[dry run] {}: {}", action, description);
+    } else {
+        println!("{}: {}", action, description);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(seed: &str) -> (Vec<RoleAssociation>, Vec<RoleStatus>, Vec<String>) {
+        let seed: Seed = toml::from_str(seed).expect("Seed should parse");
+        let mut errors = Vec::new();
+        let (associations, statuses) = entries(&seed, &mut errors);
+        (associations, statuses, errors)
+    }
+
+    #[test]
+    fn ids_may_be_numbers_or_strings() {
+        let (associations, statuses, errors) = parse(r#"
+            [[role_associations]]
+            channel = 10
+            role = "20"
+            alias = " Artists "
+
+            [[role_associations]]
+            server = "1"
+            role = 21
+            approval = true
+
+            [[role_statuses]]
+            role = 22
+            server = 1
+        "#);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(associations.len(), 2);
+        assert_eq!((associations[0].channel, associations[0].server), (Some(ChannelId(10)), None));
+        assert_eq!(associations[0].role, RoleId(20));
+        assert_eq!(associations[0].alias.as_deref(), Some("artists"));
+        assert_eq!((associations[1].server, associations[1].approval), (Some(GuildId(1)), true));
+        assert_eq!((statuses[0].role, statuses[0].server), (RoleId(22), Some(GuildId(1))));
+    }
+
+    #[test]
+    fn bad_entries_are_named() {
+        let (associations, statuses, errors) = parse(r#"
+            [[role_associations]]
+            channel = "general"
+            role = 20
+
+            [[role_associations]]
+            role = 21
+
+            [[role_statuses]]
+            role = 0
+        "#);
+        // Only entries with bad ids are skipped, but any error stops the seed from being applied
+        assert_eq!(associations.len(), 1);
+        assert!(statuses.is_empty());
+        assert_eq!(errors, vec![
+            "role_associations[0].channel: not a Discord id: \"general\"",
+            "role_associations[1].role: needs a channel, a server, or both",
+            "role_statuses[0].role: not a Discord id: 0",
+        ]);
+    }
+}
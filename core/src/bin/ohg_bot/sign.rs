@@ -0,0 +1,42 @@
+use std::time::{
+    SystemTime,
+    UNIX_EPOCH,
+};
+
+use ohg_bot_core::interactions::{
+    sign,
+    SIGNATURE_HEADER,
+    TIMESTAMP_HEADER,
+};
+
+// Set discord.public in the config to the printed key to have the bot accept fixture requests.
+pub fn main() {
+    let args: Vec<String> = std::env::args()
+        .skip_while(|arg| arg != "--sign-interaction")
+        .skip(1)
+        .collect();
+    let (secret, fixture) = if let [secret, fixture, ..] = args.as_slice() {
+        (secret, fixture)
+    } else {
+        println!("Usage: --sign-interaction <secret key hex> <fixture.json>");
+        return;
+    };
+
+    let body = std::fs::read(fixture).expect("Failed to read fixture");
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("<1970 not supported")
+        .as_secs()
+        .to_string();
+    let (public, signature) = sign(secret, &timestamp, &body).expect("Failed to sign fixture");
+
+    println!("Public key: {}", public);
+    println!(
+        "curl -i -H 'Content-Type: application/json' -H '{}: {}' -H '{}: {}' --data-binary @{} http://<interactions_address>/",
+        SIGNATURE_HEADER,
+        signature,
+        TIMESTAMP_HEADER,
+        timestamp,
+        fixture,
+    );
+}
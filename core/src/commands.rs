@@ -15,10 +15,41 @@ use serenity::{
         },
    },
 };
+use tracing::{
+    field,
+    info_span,
+};
 
 mod roles;
 pub use roles::ROLES_GROUP;
 
+mod system;
+pub use system::SYSTEMS_GROUP;
+
+mod status_roles;
+pub use status_roles::STATUS_ROLES_GROUP;
+
+mod role_menu;
+pub use role_menu::ROLE_MENUS_GROUP;
+
+mod approvals;
+pub use approvals::APPROVALS_GROUP;
+
+mod audit;
+pub use audit::AUDIT_GROUP;
+
+mod exclusive_groups;
+pub use exclusive_groups::EXCLUSIVE_GROUPS_GROUP;
+
+mod guild_config;
+pub use guild_config::CONFIG_GROUP;
+
+mod help;
+pub use help::HELP;
+
+mod operator;
+pub use operator::OPERATOR_GROUP;
+
 #[cfg(feature = "rpg")]
 #[path = "commands/rpg_enabled.rs"]
 mod rpg;
@@ -27,8 +58,11 @@ mod rpg;
 mod rpg;
 
 pub use rpg::RPG_GROUP;
+#[cfg(feature = "rpg")]
+pub(crate) use rpg::action as rpg_action;
 
 #[group]
+#[description("Checks on the bot itself.")]
 #[commands(ping, parrot)]
 pub struct General;
 
@@ -36,16 +70,95 @@ pub struct Handler;
 
 #[async_trait]
 impl EventHandler for Handler {
-    #[cfg(feature = "rpg")]
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        crate::errors::report(
+            &ctx,
+            "Interactions_Ready",
+            crate::interactions::ready(&ctx, &ready).await,
+        ).await;
+        crate::errors::report(
+            &ctx,
+            "Status_Ready",
+            crate::status::ready(&ctx).await,
+        ).await;
+        crate::errors::report(
+            &ctx,
+            "Expiry_Ready",
+            crate::expiry::ready(&ctx).await,
+        ).await;
+        crate::errors::report(
+            &ctx,
+            "Errors_Ready",
+            crate::errors::ready(&ctx).await,
+        ).await;
+    }
+
+    async fn message(&self, ctx: Context, msg: Message) {
+        crate::errors::report(
+            &ctx,
+            "Status_Message",
+            crate::status::message(&ctx, &msg).await,
+        ).await;
+    }
+
+    async fn guild_member_update(&self, ctx: Context, _old: Option<Member>, new: Member) {
+        crate::errors::report(
+            &ctx,
+            "Status_Member_Update",
+            crate::status::member_update(&ctx, &new).await,
+        ).await;
+    }
+
     async fn reaction_add(&self, ctx: Context, reaction: Reaction) {
-        crate::print_errors_impl(
-            "RPG_Reaction_Add",
-            rpg::reaction_add(&ctx, reaction).await,
-        )
+        let span = info_span!(
+            "reaction_add",
+            guild = field::Empty,
+            channel = reaction.channel_id.0,
+            user = field::Empty,
+            message = reaction.message_id.0,
+            latency_ms = field::Empty,
+        );
+        if let Some(guild) = reaction.guild_id {
+            span.record("guild", &guild.0);
+        }
+        if let Some(user) = reaction.user_id {
+            span.record("user", &user.0);
+        }
+        let ((), elapsed) = crate::logging::timed(span, async {
+            crate::errors::report(
+                &ctx,
+                "Role_Menu_Reaction_Add",
+                role_menu::reaction_add(&ctx, &reaction).await,
+            ).await;
+            crate::errors::report(
+                &ctx,
+                "Approval_Reaction_Add",
+                approvals::reaction_add(&ctx, &reaction).await,
+            ).await;
+            #[cfg(feature = "rpg")]
+            crate::errors::report(
+                &ctx,
+                "RPG_Reaction_Add",
+                rpg::reaction_add(&ctx, reaction).await,
+            ).await;
+        }).await;
+        let metrics = ctx.data.read().await.get::<crate::metrics::Metrics>().cloned();
+        if let Some(metrics) = metrics {
+            metrics.reaction_add(elapsed);
+        }
+    }
+
+    async fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        crate::errors::report(
+            &ctx,
+            "Role_Menu_Reaction_Remove",
+            role_menu::reaction_remove(&ctx, &reaction).await,
+        ).await;
     }
 }
 
 #[command]
+#[description("Checks that the bot is responding.")]
 async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
     msg.reply(ctx, "Pong!").await?;
 
@@ -53,6 +166,9 @@ async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
 }
 
 #[command]
+#[description("Repeats the parameters back, one per line, as the bot reads them.")]
+#[usage("<parameters...>")]
+#[example("one \"two words\" three")]
 async fn parrot(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
     if msg.guild_id.is_none() {
         return Ok(());
@@ -0,0 +1,508 @@
+use std::{
+    convert::TryFrom,
+    fmt::Write as _,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::join;
+use ohg_bot_emoji::symbols::other_symbol::{
+    CHECK_MARK_BUTTON,
+    CROSS_MARK,
+};
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    audit,
+    guild_config,
+    models::{
+        AuditEvent,
+        RoleRequest,
+    },
+    status::now,
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::{
+        DateDisplay,
+        DurationDisplay,
+        Mentionable,
+    },
+};
+use super::roles::{
+    find_named_associations,
+    grant,
+    RoleList,
+};
+
+const APPROVE: &str = CHECK_MARK_BUTTON;
+const DENY: &str = CROSS_MARK;
+/// Requests listed by `pending` before the rest are only counted.
+const PENDING_LISTED: usize = 20;
+
+#[group]
+#[description("Groups that need a moderator's approval to join.")]
+#[prefixes("approval")]
+#[commands(channel, require, waive, pending)]
+pub struct Approvals;
+
+#[command]
+#[description("Sets the channel where requests to join gated groups are posted, or stops taking requests.")]
+#[usage("<channel | off>")]
+#[example("#moderators")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One parameter.\
+            \nIt must be either a reference to the moderator channel, or `off` to stop taking requests.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let arg: String = match args.single() {
+        Ok(arg) if args.is_empty() => arg,
+        _ => return bad_message(ctx, msg).await,
+    };
+    let channel: Option<ChannelId> = if arg.eq_ignore_ascii_case("off") {
+        None
+    } else {
+        match arg.parse() {
+            Ok(channel) if ctx.cache.guild_channel(channel).await.map_or(false, |channel| channel.guild_id == guild) =>
+                Some(channel),
+            _ => return bad_message(ctx, msg).await,
+        }
+    };
+
+    let storage = load_storage(ctx, msg).await?;
+    let mut config = guild_config::load(ctx, guild).await;
+    config.moderator_channel = channel;
+    guild_config::save(ctx, &*storage, &mut config).await?;
+
+    match channel {
+        Some(channel) => send_message(ctx, msg, format_args!("Requests will be posted in {}", Mentionable::from(channel))).await,
+        None => send_message(ctx, msg, "Requests are no longer taken; gated groups cannot be joined").await,
+    }
+}
+
+#[command]
+#[description("Requires a moderator's approval to join the group.")]
+#[usage("<group>")]
+#[example("artists")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn require(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_approval(ctx, msg, args, true).await
+}
+
+#[command]
+#[description("Lets members join the group without approval again.")]
+#[usage("<group>")]
+#[example("artists")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn waive(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    set_approval(ctx, msg, args, false).await
+}
+
+async fn set_approval(ctx: &Context, msg: &Message, args: Args, approval: bool) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let name = args.rest().trim();
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        msg.reply(ctx, "The only parameter must be the name, alias, or channel of the group.").await?;
+        return Ok(());
+    }
+
+    let storage = load_storage(ctx, msg).await?;
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let associations = storage.guild_role_associations(cached.channels.keys().copied().collect(), guild).await?;
+    let role = match find_named_associations(&cached, associations.clone(), name).as_slice() {
+        [association] => association.role,
+        [] => return send_message(ctx, msg, format_args!("No group found for `{}`", name)).await,
+        _ => return send_message(ctx, msg, format_args!("`{}` matches several groups", name)).await,
+    };
+    // Every association of the role agrees, so no channel or alias bypasses the approval
+    for mut association in associations {
+        if association.role == role && association.approval != approval {
+            association.approval = approval;
+            storage.save_role_association(&mut association).await?;
+        }
+    }
+
+    if approval {
+        send_message(ctx, msg, format_args!("Joining {} now needs a moderator's approval", Mentionable::from(role))).await
+    } else {
+        send_message(ctx, msg, format_args!("{} can be joined freely again", Mentionable::from(role))).await
+    }
+}
+
+#[command]
+#[description("Lists the requests still awaiting a decision.")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn pending(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let storage = load_storage(ctx, msg).await?;
+    let mut requests = storage.role_requests(Some(guild)).await?;
+    requests.sort_by_key(|request| request.requested_at);
+
+    let locale = guild_config::load(ctx, guild).await.locale;
+    let now = now();
+    let mut description = String::new();
+    for request in requests.iter().take(PENDING_LISTED) {
+        writeln!(
+            &mut description,
+            "{} asked for {} in {} on {}, {} ago ([request](https://discord.com/channels/{}/{}/{}))",
+            Mentionable::from(request.user),
+            RoleList(&request.roles),
+            Mentionable::from(request.channel),
+            DateDisplay { at: request.requested_at, locale: locale.as_deref() },
+            DurationDisplay(Duration::from_secs((now - request.requested_at).max(0) as u64)),
+            guild,
+            request.moderator_channel,
+            request.message,
+        )?;
+    }
+    if requests.len() > PENDING_LISTED {
+        writeln!(&mut description, "…and {} more", requests.len() - PENDING_LISTED)?;
+    }
+    if requests.is_empty() {
+        description.push_str("No pending requests.");
+    }
+    send_message(ctx, msg, description).await
+}
+
+/// Posts a request for the roles to the moderator channel instead of joining them.
+pub(super) async fn request(
+    ctx: &Context,
+    msg: &Message,
+    db: &dyn Storage,
+    member: &Member,
+    roles: &[RoleId],
+    duration: Option<Duration>,
+) -> CommandResult {
+    if submit(ctx, db, member, roles, msg.channel_id, duration).await? {
+        send_message(
+            ctx,
+            msg,
+            format_args!("{} needs a moderator's approval; your request was sent", RoleList(roles)),
+        ).await
+    } else {
+        send_message(
+            ctx,
+            msg,
+            format_args!("{} needs a moderator's approval, but no moderator channel is set up", RoleList(roles)),
+        ).await
+    }
+}
+
+/// Whether joining any of the roles needs a moderator's approval, through any of their associations.
+pub(super) async fn gated(db: &dyn Storage, roles: &[RoleId]) -> CommandResult<bool> {
+    for role in roles {
+        if db.role_associations_of(*role).await?.iter().any(|association| association.approval) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// Posts a request for the roles, asked for in the channel, to the moderator channel.
+///
+/// Returns false if the server has no moderator channel to post it in.
+pub(super) async fn submit(
+    ctx: &Context,
+    db: &dyn Storage,
+    member: &Member,
+    roles: &[RoleId],
+    channel: ChannelId,
+    duration: Option<Duration>,
+) -> CommandResult<bool> {
+    let guild = member.guild_id;
+    let seconds = duration
+        .map(|duration| i64::try_from(duration.as_secs()))
+        .transpose()
+        .map_err(|_| "Duration too long")?;
+    let moderator_channel = match guild_config::load(ctx, guild).await.moderator_channel {
+        Some(channel) => channel,
+        None => return Ok(false),
+    };
+
+    let posted = moderator_channel.send_message(ctx, |message| message
+        .embed(|e| {
+            e.title("Role request:");
+            e.description(format_args!(
+                "{} asks to join {} in {}",
+                Mentionable::from(member.user.id),
+                RoleList(roles),
+                Mentionable::from(channel),
+            ));
+            if let Some(duration) = duration {
+                e.field("For:", DurationDisplay(duration), false);
+            }
+            e.footer(|f| f
+                .text(format_args!("React {} to approve or {} to deny.", APPROVE, DENY))
+            )
+        })
+    ).await?;
+    let mut request = RoleRequest {
+        id: None,
+        server: guild,
+        user: member.user.id,
+        roles: roles.to_vec(),
+        channel,
+        moderator_channel,
+        message: posted.id,
+        duration: seconds,
+        requested_at: now(),
+    };
+    db.save_role_request(&mut request).await?;
+    ctx.data
+        .write()
+        .await
+        .get_mut::<RoleRequest>()
+        .ok_or("Role requests not present")?
+        .insert(posted.id);
+    posted.react(ctx, ReactionType::Unicode(APPROVE.to_string())).await?;
+    posted.react(ctx, ReactionType::Unicode(DENY.to_string())).await?;
+    Ok(true)
+}
+
+/// Decides a request when a moderator reacts to it.
+pub async fn reaction_add(ctx: &Context, reaction: &Reaction) -> CommandResult {
+    let approve = match &reaction.emoji {
+        ReactionType::Unicode(emoji) if emoji == APPROVE => true,
+        ReactionType::Unicode(emoji) if emoji == DENY => false,
+        _ => return Ok(()),
+    };
+    let moderator = if let Some(user) = reaction.user_id {
+        user
+    } else {
+        return Ok(());
+    };
+    if moderator == ctx.cache.current_user_id().await {
+        return Ok(());
+    }
+    let storage = {
+        let data = ctx.data.read().await;
+        let is_request = data
+            .get::<RoleRequest>()
+            .map_or(false, |requests| requests.contains(&reaction.message_id));
+        if !is_request {
+            return Ok(());
+        }
+        data.get::<StorageKey>().ok_or("Storage not present")?.clone()
+    };
+    let request = if let Some(request) = storage.role_request(reaction.message_id).await? {
+        request
+    } else {
+        return Ok(());
+    };
+    let cached = ctx.cache.guild(request.server).await.ok_or("Guild not cached")?;
+    let moderator_role = guild_config::load(ctx, request.server).await.moderator_role;
+    let holds_moderator_role = moderator_role.map_or(false, |role| cached.members
+        .get(&moderator)
+        .map_or(false, |member| member.roles.contains(&role))
+    );
+    if !holds_moderator_role && !cached.member_permissions(moderator).manage_roles() {
+        return Ok(());
+    }
+    // Claimed before deciding, so two moderators reacting at once decide it only once
+    let claimed = ctx.data
+        .write()
+        .await
+        .get_mut::<RoleRequest>()
+        .map_or(false, |requests| requests.remove(&reaction.message_id));
+    if !claimed {
+        return Ok(());
+    }
+
+    let role_names = request.roles
+        .iter()
+        .map(|role| cached.roles.get(role).map_or_else(|| "a deleted role".to_string(), |role| format!("**{}**", role.name)))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let decided = async {
+        let (decision, notice) = if !approve {
+            (
+                format!("Denied by {}", Mentionable::from(moderator)),
+                Some(format!("Your request to join {} in **{}** was denied.", role_names, cached.name)),
+            )
+        } else if let Ok(member) = request.server.member(ctx, request.user).await {
+            let duration = request.duration.map(|seconds| Duration::from_secs(seconds.max(0) as u64));
+            match grant(ctx, &*storage, &member, &request.roles, duration).await? {
+                Ok(swapped) if swapped.is_empty() => (
+                    format!("Approved by {}", Mentionable::from(moderator)),
+                    Some(format!("Your request to join {} in **{}** was approved.", role_names, cached.name)),
+                ),
+                Ok(swapped) => (
+                    format!("Approved by {}, swapping out {}", Mentionable::from(moderator), RoleList(&swapped)),
+                    Some(format!("Your request to join {} in **{}** was approved.", role_names, cached.name)),
+                ),
+                Err(refusal) => (
+                    format!("Approved by {}, but not applied: {}", Mentionable::from(moderator), refusal),
+                    Some(format!("Your request to join {} in **{}** was approved, but could not be applied.", role_names, cached.name)),
+                ),
+            }
+        } else {
+            (format!("Approved by {}, but the member left", Mentionable::from(moderator)), None)
+        };
+        storage.delete_role_request(&request).await?;
+        Ok((decision, notice)) as CommandResult<(String, Option<String>)>
+    }.await;
+    // Until the request is deleted it can still be decided, so a failure hands it back
+    let (decision, notice) = match decided {
+        Ok(decided) => decided,
+        Err(e) => {
+            if let Some(requests) = ctx.data.write().await.get_mut::<RoleRequest>() {
+                requests.insert(reaction.message_id);
+            }
+            return Err(e);
+        },
+    };
+    mark_decided(ctx, &request, decision).await?;
+    let notice = match notice {
+        Some(notice) => notice,
+        None => return Ok(()),
+    };
+    audit::record(ctx, &*storage, AuditEvent {
+        id: None,
+        server: request.server,
+        user: moderator,
+        member: Some(request.user),
+        channel: request.moderator_channel,
+        command: if approve { "approve" } else { "deny" }.to_string(),
+        roles: request.roles.clone(),
+        old: None,
+        new: None,
+        at: now(),
+    }).await?;
+
+    request.user
+        .create_dm_channel(ctx)
+        .await?
+        .send_message(ctx, |message| message
+            .embed(|e| e
+                .title("Role request:")
+                .description(notice)
+            )
+        ).await?;
+    Ok(())
+}
+
+async fn mark_decided(ctx: &Context, request: &RoleRequest, decision: String) -> CommandResult {
+    request.moderator_channel.edit_message(ctx, request.message, |message| message
+        .embed(|e| e
+            .title("Role request:")
+            .description(format_args!(
+                "{} asked to join {} in {}",
+                Mentionable::from(request.user),
+                RoleList(&request.roles),
+                Mentionable::from(request.channel),
+            ))
+            .field("Decision:", decision, false)
+        )
+    ).await?;
+    Ok(())
+}
+
+async fn send_message(ctx: &Context, msg: &Message, description: impl std::fmt::Display) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Role approval:")
+            .description(description)
+        )
+    ).await?;
+    Ok(())
+}
+
+async fn load_storage(ctx: &Context, msg: &Message) -> CommandResult<Arc<dyn Storage>> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    Ok(data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::{
+        models::RoleAssociation,
+        storage::MemoryStorage,
+    };
+    use super::*;
+
+    fn association(channel: u64, role: u64, approval: bool) -> RoleAssociation {
+        RoleAssociation {
+            id: None,
+            channel: Some(ChannelId(channel)),
+            server: None,
+            role: RoleId(role),
+            alias: None,
+            approval,
+        }
+    }
+
+    #[test]
+    fn roles_are_gated_through_any_association() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            storage.save_role_association(&mut association(10, 1, false)).await?;
+            storage.save_role_association(&mut association(11, 1, true)).await?;
+            storage.save_role_association(&mut association(10, 2, false)).await?;
+
+            assert!(!gated(&storage, &[RoleId(2)]).await?);
+            assert!(gated(&storage, &[RoleId(2), RoleId(1)]).await?);
+            assert!(!gated(&storage, &[RoleId(3)]).await?);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn requests_are_pending_until_deleted() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            let mut request = RoleRequest {
+                id: None,
+                server: GuildId(1),
+                user: UserId(2),
+                roles: vec![RoleId(3)],
+                channel: ChannelId(4),
+                moderator_channel: ChannelId(5),
+                message: MessageId(6),
+                duration: None,
+                requested_at: 0,
+            };
+            storage.save_role_request(&mut request).await?;
+
+            assert_eq!(storage.role_requests(Some(GuildId(1))).await?.len(), 1);
+            assert!(storage.role_requests(Some(GuildId(7))).await?.is_empty());
+            let found = storage.role_request(MessageId(6)).await?.ok_or("Request not saved")?;
+            assert_eq!((found.user, found.roles), (UserId(2), vec![RoleId(3)]));
+
+            storage.delete_role_request(&request).await?;
+            assert!(storage.role_request(MessageId(6)).await?.is_none());
+            assert!(storage.role_requests(None).await?.is_empty());
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,229 @@
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+    time::Duration,
+};
+
+use futures::join;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    audit::EventDisplay,
+    guild_config,
+    models::AuditEvent,
+    status::now,
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::{
+        DateDisplay,
+        DurationDisplay,
+        Mentionable,
+    },
+};
+
+/// Events shown per page, newest first.
+const AUDIT_PAGE: usize = 8;
+
+#[group]
+#[description("The record of role changes.")]
+#[prefixes("audit")]
+#[commands(user, role, channel)]
+pub struct Audit;
+
+#[command]
+#[description("Shows the role changes made by or to a member.")]
+#[usage("<member> [page]")]
+#[example("@Someone 2")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn user(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be either a reference to the member, or the member ID.\
+            \nThe second may be the page, starting at 1.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let user: UserId = match args.single() {
+        Ok(user) => user,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let page = match parse_page(&mut args) {
+        Some(page) => page,
+        None => return bad_message(ctx, msg).await,
+    };
+
+    let storage = load_storage(ctx, msg).await?;
+    let (events, total) = storage.user_audit_events(guild, user, page.saturating_mul(AUDIT_PAGE), AUDIT_PAGE).await?;
+    send_events(ctx, msg, format_args!("Changes by or to {}", Mentionable::from(user)), &events, total, page).await
+}
+
+#[command]
+#[description("Shows the changes to a role.")]
+#[usage("<role> [page]")]
+#[example("@Artists")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be either a reference to the role, or the role ID.\
+            \nThe second may be the page, starting at 1.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let role: RoleId = match args.single() {
+        Ok(role) => role,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let page = match parse_page(&mut args) {
+        Some(page) => page,
+        None => return bad_message(ctx, msg).await,
+    };
+
+    let storage = load_storage(ctx, msg).await?;
+    let (events, total) = storage.role_audit_events(guild, role, page.saturating_mul(AUDIT_PAGE), AUDIT_PAGE).await?;
+    send_events(ctx, msg, format_args!("Changes to {}", Mentionable::from(role)), &events, total, page).await
+}
+
+#[command]
+#[description("Sets the channel role changes are logged to, or stops logging there.")]
+#[usage("<channel | off>")]
+#[example("#audit-log")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One parameter.\
+            \nIt must be either a reference to the audit log channel, or `off` to stop logging there.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let arg: String = match args.single() {
+        Ok(arg) if args.is_empty() => arg,
+        _ => return bad_message(ctx, msg).await,
+    };
+    let channel: Option<ChannelId> = if arg.eq_ignore_ascii_case("off") {
+        None
+    } else {
+        match arg.parse() {
+            Ok(channel) if ctx.cache.guild_channel(channel).await.map_or(false, |channel| channel.guild_id == guild) =>
+                Some(channel),
+            _ => return bad_message(ctx, msg).await,
+        }
+    };
+
+    let storage = load_storage(ctx, msg).await?;
+    let mut config = guild_config::load(ctx, guild).await;
+    config.audit_channel = channel;
+    guild_config::save(ctx, &*storage, &mut config).await?;
+
+    let description = match channel {
+        Some(channel) => format!("Role changes will be logged in {}", Mentionable::from(channel)),
+        None => "Role changes are no longer logged to a channel, but can still be searched".to_string(),
+    };
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Audit log:")
+            .description(description)
+        )
+    ).await?;
+
+    Ok(())
+}
+
+/// The optional, last parameter, as a zero-based page.
+fn parse_page(args: &mut Args) -> Option<usize> {
+    let page = if args.is_empty() {
+        0
+    } else {
+        args.single::<usize>().ok()?.checked_sub(1)?
+    };
+    if args.is_empty() {
+        Some(page)
+    } else {
+        None
+    }
+}
+
+async fn send_events(
+    ctx: &Context,
+    msg: &Message,
+    title: impl std::fmt::Display,
+    events: &[AuditEvent],
+    total: usize,
+    page: usize,
+) -> CommandResult {
+    let pages = (total + AUDIT_PAGE - 1) / AUDIT_PAGE;
+
+    let locale = match msg.guild_id {
+        Some(guild) => guild_config::load(ctx, guild).await.locale,
+        None => None,
+    };
+    let now = now();
+    let mut description = format!("{}:\n", title);
+    for event in events {
+        writeln!(
+            &mut description,
+            "\n`{}, {} ago` {}",
+            DateDisplay { at: event.at, locale: locale.as_deref() },
+            DurationDisplay(Duration::from_secs((now - event.at).max(0) as u64)),
+            EventDisplay(event),
+        )?;
+    }
+    if total == 0 {
+        description.push_str("Nothing recorded.");
+    } else if page >= pages {
+        write!(&mut description, "There are only {} pages.", pages)?;
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("Audit log:");
+            e.description(description);
+            if pages > 1 {
+                e.footer(|f| f.text(format_args!("Page {} of {}", page + 1, pages)));
+            }
+            e
+        })
+    ).await?;
+
+    Ok(())
+}
+
+async fn load_storage(ctx: &Context, msg: &Message) -> CommandResult<Arc<dyn Storage>> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    Ok(data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone())
+}
@@ -0,0 +1,186 @@
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use futures::join;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    models::ExclusiveGroup,
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::Mentionable,
+};
+use super::roles::find_named_associations;
+
+#[group]
+#[description("Sets of groups members may only hold one of.")]
+#[prefixes("exclusive")]
+#[commands(list, create, delete)]
+pub struct ExclusiveGroups;
+
+#[command]
+#[description("Lists the exclusive groups of the server.")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let storage = load_storage(ctx, msg).await?;
+    let groups = storage.exclusive_groups(guild).await?;
+
+    let mut fields = Vec::with_capacity(groups.len());
+    for group in groups.iter().take(25) {
+        let mut value = String::new();
+        for role in &group.roles {
+            writeln!(&mut value, "{}", Mentionable::from(*role))?;
+        }
+        fields.push((group.name.clone(), value, true));
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("Exclusive groups:");
+            if fields.is_empty() {
+                e.description("No exclusive groups configured.");
+            }
+            e.fields(fields)
+        })
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Creates an exclusive group, of which members may only hold one group at a time.")]
+#[usage("<name> <group> <group> [groups...]")]
+#[example("houses red blue green")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Three or more parameters.\
+            \nThe first is the name of the exclusive group.\
+            \nThe rest are the groups members may only join one of, by name, alias, or channel.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let name: String = match args.single() {
+        Ok(name) => name,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    if args.remaining() < 2 {
+        return bad_message(ctx, msg).await;
+    }
+
+    let storage = load_storage(ctx, msg).await?;
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let associations = storage.guild_role_associations(cached.channels.keys().copied().collect(), guild).await?;
+    let mut roles: Vec<RoleId> = Vec::new();
+    while !args.is_empty() {
+        let group: String = args.single()?;
+        match find_named_associations(&cached, associations.clone(), &group).as_slice() {
+            [association] => if !roles.contains(&association.role) {
+                roles.push(association.role);
+            },
+            [] => return send_message(ctx, msg, format_args!("No group found for `{}`", group)).await,
+            _ => return send_message(ctx, msg, format_args!("`{}` matches several groups", group)).await,
+        }
+    }
+    if roles.len() < 2 {
+        return send_message(ctx, msg, "An exclusive group needs at least two different groups").await;
+    }
+
+    let existing = storage
+        .exclusive_groups(guild)
+        .await?
+        .into_iter()
+        .find(|group| group.name.eq_ignore_ascii_case(&name));
+    let updated = existing.is_some();
+    let mut group = existing.unwrap_or(ExclusiveGroup {
+        id: None,
+        server: guild,
+        name,
+        roles: Vec::new(),
+    });
+    group.roles = roles;
+    storage.save_exclusive_group(&mut group).await?;
+
+    let mut description = format!(
+        "`{}` {}; members may hold only one of:\n",
+        group.name,
+        if updated { "updated" } else { "created" },
+    );
+    for role in &group.roles {
+        writeln!(&mut description, "{}", Mentionable::from(*role))?;
+    }
+    send_message(ctx, msg, description).await
+}
+
+#[command]
+#[description("Deletes an exclusive group, leaving its groups as they are.")]
+#[usage("<name>")]
+#[example("houses")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn delete(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let name = args.rest().trim();
+    if name.is_empty() {
+        msg.reply(ctx, "The only parameter must be the name of the exclusive group.").await?;
+        return Ok(());
+    }
+
+    let storage = load_storage(ctx, msg).await?;
+    let group = storage
+        .exclusive_groups(guild)
+        .await?
+        .into_iter()
+        .find(|group| group.name.eq_ignore_ascii_case(name));
+    match group {
+        Some(group) => {
+            storage.delete_exclusive_group(&group).await?;
+            send_message(ctx, msg, format_args!("`{}` deleted; its groups may be joined together again", group.name)).await
+        },
+        None => send_message(ctx, msg, format_args!("No exclusive group named `{}`", name)).await,
+    }
+}
+
+async fn send_message(ctx: &Context, msg: &Message, description: impl std::fmt::Display) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Exclusive groups:")
+            .description(description)
+        )
+    ).await?;
+    Ok(())
+}
+
+async fn load_storage(ctx: &Context, msg: &Message) -> CommandResult<Arc<dyn Storage>> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    Ok(data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone())
+}
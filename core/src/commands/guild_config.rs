@@ -0,0 +1,282 @@
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use futures::join;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    guild_config::{
+        self,
+        CommandGroups,
+        CONFIG_GROUP_NAME,
+    },
+    models::{
+        DiscordCredentials,
+        GuildConfig,
+    },
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::Mentionable,
+};
+
+const KEYS: [&str; 6] = ["prefix", "groups", "locale", "audit_channel", "moderator_channel", "moderator_role"];
+/// Given as the value, clears the setting back to the default.
+const DEFAULT: &str = "default";
+const MAX_PREFIX_LENGTH: usize = 16;
+
+#[group]
+#[description("The settings of the server.")]
+#[prefixes("config")]
+#[commands(get, set)]
+pub struct Config;
+
+#[command]
+#[description("Shows the settings of the server, or only the one named.")]
+#[usage("[setting]")]
+#[example("prefix")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn get(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let key = args.rest().trim();
+    let keys: Vec<&str> = if key.is_empty() {
+        KEYS.to_vec()
+    } else if let Some(key) = KEYS.iter().find(|known| known.eq_ignore_ascii_case(key)) {
+        vec![key]
+    } else {
+        return send_message_unknown_key(ctx, msg, key).await;
+    };
+
+    let (_, default_prefix) = load(ctx, msg).await?;
+    let config = guild_config::load(ctx, guild).await;
+    let mut description = String::new();
+    for key in keys {
+        writeln!(&mut description, "`{}`: {}", key, ValueDisplay(&config, key, &default_prefix))?;
+    }
+    send_message(ctx, msg, description).await
+}
+
+#[command]
+#[description("Changes a setting of the server: `prefix`, `groups`, `locale`, `audit_channel`, `moderator_channel`, or `moderator_role`.")]
+#[usage("<setting> <value | default>")]
+#[example("prefix ?")]
+#[example("groups Roles, Approvals")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Two parameters.\
+            \nThe first is the setting: `prefix`, `groups`, `locale`, `audit_channel`, `moderator_channel`, or `moderator_role`.\
+            \nThe second is its value, or `default` to clear it.\
+            \n`groups` takes the names of every group to enable, separated by spaces or commas.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let key: String = match args.single() {
+        Ok(key) => key.to_lowercase(),
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let value = args.rest().trim();
+    if value.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+    let reset = value.eq_ignore_ascii_case(DEFAULT);
+
+    let (storage, default_prefix) = load(ctx, msg).await?;
+    let mut config = guild_config::load(ctx, guild).await;
+    match key.as_str() {
+        "prefix" => config.prefix = if reset {
+            None
+        } else if value.chars().count() > MAX_PREFIX_LENGTH || value.contains(char::is_whitespace) {
+            return send_message(
+                ctx,
+                msg,
+                format_args!("A prefix has no spaces and at most {} characters", MAX_PREFIX_LENGTH),
+            ).await;
+        } else {
+            Some(value.to_string())
+        },
+        "groups" => config.groups = if reset || value.eq_ignore_ascii_case("all") {
+            None
+        } else {
+            let known: Vec<&'static str> = ctx.data
+                .read()
+                .await
+                .get::<CommandGroups>()
+                .ok_or("Command groups not present")?
+                .names()
+                .to_vec();
+            let mut groups = Vec::new();
+            for name in value.split(|c: char| c == ',' || c.is_whitespace()).filter(|name| !name.is_empty()) {
+                match known.iter().find(|group| group.eq_ignore_ascii_case(name)) {
+                    Some(group) => if !groups.iter().any(|added: &String| added == group) {
+                        groups.push(group.to_string());
+                    },
+                    None => return send_message(
+                        ctx,
+                        msg,
+                        format_args!("No group named `{}`; there are {}", name, known.join(", ")),
+                    ).await,
+                }
+            }
+            Some(groups)
+        },
+        "locale" => config.locale = if reset {
+            None
+        } else if is_language_tag(value) {
+            Some(value.to_string())
+        } else {
+            return send_message(ctx, msg, "A locale is a language tag, such as `en` or `en-US`").await;
+        },
+        "audit_channel" | "moderator_channel" => {
+            let channel = if reset {
+                None
+            } else {
+                match value.parse::<ChannelId>() {
+                    Ok(channel) if ctx.cache.guild_channel(channel).await.map_or(false, |channel| channel.guild_id == guild) =>
+                        Some(channel),
+                    _ => return send_message(ctx, msg, "The value must be a reference to a channel of this server").await,
+                }
+            };
+            if key == "audit_channel" {
+                config.audit_channel = channel;
+            } else {
+                config.moderator_channel = channel;
+            }
+        },
+        "moderator_role" => config.moderator_role = if reset {
+            None
+        } else {
+            match value.parse::<RoleId>() {
+                Ok(role) if ctx.cache.role(guild, role).await.is_some() => Some(role),
+                _ => return send_message(ctx, msg, "The value must be a reference to a role of this server, or its ID").await,
+            }
+        },
+        _ => return send_message_unknown_key(ctx, msg, &key).await,
+    }
+    guild_config::save(ctx, &*storage, &mut config).await?;
+
+    send_message(ctx, msg, format_args!("`{}` is now {}", key, ValueDisplay(&config, &key, &default_prefix))).await
+}
+
+/// Whether the value looks like a BCP 47 language tag, such as `en` or `zh-Hant-TW`.
+fn is_language_tag(value: &str) -> bool {
+    let mut subtags = value.split('-');
+    let language = subtags.next().unwrap_or("");
+    (2..=3).contains(&language.len())
+        && language.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric()))
+}
+
+/// The setting under the key, as shown by `get` and `set`.
+struct ValueDisplay<'a>(&'a GuildConfig, &'a str, &'a str);
+
+impl std::fmt::Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ValueDisplay(config, key, default_prefix) = *self;
+        let unset = |f: &mut std::fmt::Formatter<'_>| f.write_str("not set");
+        match key {
+            "prefix" => match &config.prefix {
+                Some(prefix) => write!(f, "`{}`", prefix),
+                None => write!(f, "`{}` (default)", default_prefix),
+            },
+            "groups" => match &config.groups {
+                Some(groups) if groups.is_empty() => write!(f, "only {}", CONFIG_GROUP_NAME),
+                Some(groups) => {
+                    f.write_str(&groups.join(", "))?;
+                    if !groups.iter().any(|group| group == CONFIG_GROUP_NAME) {
+                        write!(f, ", and {}", CONFIG_GROUP_NAME)?;
+                    }
+                    Ok(())
+                },
+                None => f.write_str("all (default)"),
+            },
+            "locale" => match &config.locale {
+                Some(locale) => write!(f, "`{}`", locale),
+                None => unset(f),
+            },
+            "audit_channel" => match config.audit_channel {
+                Some(channel) => write!(f, "{}", Mentionable::from(channel)),
+                None => unset(f),
+            },
+            "moderator_channel" => match config.moderator_channel {
+                Some(channel) => write!(f, "{}", Mentionable::from(channel)),
+                None => unset(f),
+            },
+            "moderator_role" => match config.moderator_role {
+                Some(role) => write!(f, "{}", Mentionable::from(role)),
+                None => unset(f),
+            },
+            _ => unset(f),
+        }
+    }
+}
+
+async fn send_message_unknown_key(ctx: &Context, msg: &Message, key: &str) -> CommandResult {
+    send_message(ctx, msg, format_args!("No setting named `{}`; there are `{}`", key, KEYS.join("`, `"))).await
+}
+
+async fn send_message(ctx: &Context, msg: &Message, description: impl std::fmt::Display) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Server config:")
+            .description(description)
+        )
+    ).await?;
+    Ok(())
+}
+
+/// The storage, and the prefix servers without their own use.
+async fn load(ctx: &Context, msg: &Message) -> CommandResult<(Arc<dyn Storage>, String)> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    Ok((
+        data
+            .get::<StorageKey>()
+            .ok_or("Storage not present")?
+            .clone(),
+        data
+            .get::<DiscordCredentials>()
+            .ok_or("Credentials not present")?
+            .prefix
+            .clone(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn language_tags() {
+        assert!(is_language_tag("en"));
+        assert!(is_language_tag("en-US"));
+        assert!(is_language_tag("zh-Hant-TW"));
+        assert!(!is_language_tag(""));
+        assert!(!is_language_tag("e"));
+        assert!(!is_language_tag("en_US"));
+        assert!(!is_language_tag("en-"));
+        assert!(!is_language_tag("english"));
+    }
+}
@@ -0,0 +1,55 @@
+use std::collections::HashSet;
+
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandGroup,
+        CommandResult,
+        HelpOptions,
+        help_commands,
+        macros::help,
+    },
+};
+use crate::guild_config::{
+    self,
+    CONFIG_GROUP_NAME,
+};
+
+/// Lists the groups and commands the caller may use where they asked, or describes one of them.
+#[help]
+#[individual_command_tip = "For the usage and examples of a command, pass its name, such as `help join`."]
+#[command_not_found_text = "No command named `{}`."]
+#[max_levenshtein_distance(3)]
+#[lacking_permissions = "Hide"]
+#[lacking_role = "Hide"]
+#[lacking_ownership = "Hide"]
+#[wrong_channel = "Hide"]
+async fn help(
+    ctx: &Context,
+    msg: &Message,
+    args: Args,
+    help_options: &'static HelpOptions,
+    groups: &[&'static CommandGroup],
+    owners: HashSet<UserId>,
+) -> CommandResult {
+    // Commands of groups the server has not enabled are ignored, so they are left out too
+    let enabled = match msg.guild_id {
+        Some(guild) => guild_config::load(ctx, guild).await.groups,
+        None => None,
+    };
+    let groups: Vec<&'static CommandGroup> = match enabled {
+        Some(enabled) => groups
+            .iter()
+            .copied()
+            .filter(|group|
+                group.name == CONFIG_GROUP_NAME
+                || enabled.iter().any(|name| name.eq_ignore_ascii_case(group.name))
+            )
+            .collect(),
+        None => groups.to_vec(),
+    };
+    help_commands::with_embeds(ctx, msg, args, help_options, &groups, owners).await;
+    Ok(())
+}
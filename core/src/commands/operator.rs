@@ -0,0 +1,142 @@
+use std::{
+    fmt::Write as _,
+    time::Duration,
+};
+
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    errors::{
+        ErrorRecord,
+        ErrorReporter,
+        RECENT_ERRORS,
+    },
+    shutdown::{
+        request,
+        Exit,
+    },
+    status::now,
+    util::{
+        DurationDisplay,
+        Mentionable,
+    },
+};
+
+/// Errors listed when no count is given.
+const DEFAULT_ERRORS: usize = 10;
+/// Room left in an embed description, which Discord caps at 4096 characters.
+const DESCRIPTION_LIMIT: usize = 4000;
+
+#[group]
+#[description("Looking after the bot itself; only for its operator.")]
+#[owners_only]
+#[commands(errors, shutdown, restart)]
+pub struct Operator;
+
+#[command]
+#[description("Lists the latest errors, or shows the whole of the one with the ID.")]
+#[usage("[count | id]")]
+#[example("20")]
+#[example("3fa09c")]
+async fn errors(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let arg = args.rest().trim();
+    let reporter = ctx.data
+        .read()
+        .await
+        .get::<ErrorReporter>()
+        .ok_or("Error reporter not present")?
+        .clone();
+    let now = now();
+
+    // IDs may be all digits, so they are looked for before reading a count
+    let record = if arg.is_empty() {
+        None
+    } else {
+        reporter.find(arg)
+    };
+    let count = if arg.is_empty() {
+        Some(DEFAULT_ERRORS)
+    } else {
+        arg.parse::<usize>().ok()
+    };
+    let description = match (record, count) {
+        (Some(record), _) => describe(&record, now)?,
+        (None, Some(count)) => {
+            let records = reporter.recent(count.min(RECENT_ERRORS));
+            let mut description = String::new();
+            for (ix, record) in records.iter().enumerate() {
+                let message: String = record.message.lines().next().unwrap_or("").chars().take(100).collect();
+                let line = format!(
+                    "`{}` `{} ago` **{}**: {}\n",
+                    record.id,
+                    DurationDisplay(Duration::from_secs((now - record.at).max(0) as u64)),
+                    record.name,
+                    message,
+                );
+                if description.len() + line.len() > DESCRIPTION_LIMIT {
+                    write!(&mut description, "…and {} more", records.len() - ix)?;
+                    break;
+                }
+                description.push_str(&line);
+            }
+            if records.is_empty() {
+                description.push_str("No errors since the bot started.");
+            }
+            description
+        },
+        (None, None) => format!("No error `{}` is kept; only the latest {} are", arg, RECENT_ERRORS),
+    };
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Errors:")
+            .description(description)
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Finishes the RPG moves under way, stops the systems and exits.")]
+async fn shutdown(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx, "Shutting down.").await?;
+    request(ctx, Exit::Shutdown).await
+}
+
+#[command]
+#[description("Shuts down as `shutdown` does, but exits with status 75 so whatever runs the bot starts it again.")]
+async fn restart(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.reply(ctx, "Restarting.").await?;
+    request(ctx, Exit::Restart).await
+}
+
+fn describe(record: &ErrorRecord, now: i64) -> CommandResult<String> {
+    let mut description = format!(
+        "`{}` **{}**, {} ago",
+        record.id,
+        record.name,
+        DurationDisplay(Duration::from_secs((now - record.at).max(0) as u64)),
+    );
+    if let Some(user) = record.user {
+        write!(&mut description, "\nUsed by {}", Mentionable::from(user))?;
+    }
+    if let Some(channel) = record.channel {
+        write!(&mut description, "\nIn {}", Mentionable::from(channel))?;
+    }
+    // Long enough for most errors, short enough for an embed
+    let message: String = record.message.chars().take(1500).collect();
+    write!(&mut description, "\n```\n{}\n```", message)?;
+    Ok(description)
+}
@@ -0,0 +1,276 @@
+use std::{
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use futures::join;
+use ohg_bot_emoji::symbols::{
+    geometric,
+    keycap,
+};
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    expiry,
+    models::{
+        RoleMenu,
+        RoleMenuEntry,
+    },
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::Mentionable,
+};
+use super::{
+    approvals,
+    roles::{
+        find_named_associations,
+        grant,
+    },
+};
+
+/// Assigned to the roles of a menu in order; also the most reactions a message can carry.
+const PALETTE: [&str; 20] = [
+    keycap::KEYCAP_1,
+    keycap::KEYCAP_2,
+    keycap::KEYCAP_3,
+    keycap::KEYCAP_4,
+    keycap::KEYCAP_5,
+    keycap::KEYCAP_6,
+    keycap::KEYCAP_7,
+    keycap::KEYCAP_8,
+    keycap::KEYCAP_9,
+    keycap::KEYCAP_10,
+    geometric::RED_CIRCLE,
+    geometric::ORANGE_CIRCLE,
+    geometric::YELLOW_CIRCLE,
+    geometric::GREEN_CIRCLE,
+    geometric::BLUE_CIRCLE,
+    geometric::PURPLE_CIRCLE,
+    geometric::BROWN_CIRCLE,
+    geometric::BLACK_CIRCLE,
+    geometric::WHITE_CIRCLE,
+    geometric::RED_SQUARE,
+];
+
+#[group]
+#[description("Menus for joining groups by reacting.")]
+#[prefixes("role_menu")]
+#[commands(create)]
+pub struct RoleMenus;
+
+#[command]
+#[description("Posts a menu of groups that members join and leave by reacting; without names, offers every group of the server.")]
+#[usage("[groups...]")]
+#[example("artists writers")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn create(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    // Cloned out, as the data is written to once the menu is posted
+    let db: Arc<dyn Storage> = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    drop(data);
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let associations = db.guild_role_associations(cached.channels.keys().copied().collect(), guild).await?;
+
+    // Without names, every associated role of the server is offered
+    let mut roles: Vec<RoleId> = Vec::new();
+    if args.is_empty() {
+        roles.extend(associations.iter().map(|association| association.role));
+        roles.sort();
+    }
+    while !args.is_empty() {
+        let name: String = args.single()?;
+        match find_named_associations(&cached, associations.clone(), &name).as_slice() {
+            [association] => if !roles.contains(&association.role) {
+                roles.push(association.role);
+            },
+            [] => return send_message_bad_role(ctx, msg, format_args!("No group found for `{}`", name)).await,
+            _ => return send_message_bad_role(ctx, msg, format_args!("`{}` matches several groups", name)).await,
+        }
+    }
+    roles.dedup();
+    if roles.is_empty() {
+        return send_message_bad_role(ctx, msg, "There are no groups to offer").await;
+    }
+    if roles.len() > PALETTE.len() {
+        return send_message_bad_role(ctx, msg, format_args!("A menu can offer at most {} groups", PALETTE.len())).await;
+    }
+
+    let entries: Vec<RoleMenuEntry> = roles
+        .into_iter()
+        .zip(PALETTE.iter())
+        .map(|(role, emoji)| RoleMenuEntry {
+            emoji: emoji.to_string(),
+            role,
+        })
+        .collect();
+    let mut description = String::new();
+    for entry in &entries {
+        writeln!(&mut description, "{} {}", entry.emoji, Mentionable::from(entry.role))?;
+    }
+
+    let menu_message = msg.channel_id.send_message(ctx, |message| message
+        .embed(|e| e
+            .title("Role menu:")
+            .description(description)
+            .footer(|f| f
+                .text("React to join a group; remove the reaction to leave it.")
+            )
+        )
+    ).await?;
+    let mut menu = RoleMenu {
+        id: None,
+        server: guild,
+        channel: msg.channel_id,
+        message: menu_message.id,
+        entries,
+    };
+    db.save_role_menu(&mut menu).await?;
+    ctx.data
+        .write()
+        .await
+        .get_mut::<RoleMenu>()
+        .ok_or("Role menus not present")?
+        .insert(menu.message);
+
+    for entry in &menu.entries {
+        menu_message.react(ctx, ReactionType::Unicode(entry.emoji.clone())).await?;
+    }
+
+    Ok(())
+}
+
+async fn send_message_bad_role(ctx: &Context, msg: &Message, description: impl std::fmt::Display) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Role menu:")
+            .description(description)
+        )
+    ).await?;
+    Ok(())
+}
+
+pub async fn reaction_add(ctx: &Context, reaction: &Reaction) -> CommandResult {
+    reaction_change(ctx, reaction, true).await
+}
+
+pub async fn reaction_remove(ctx: &Context, reaction: &Reaction) -> CommandResult {
+    reaction_change(ctx, reaction, false).await
+}
+
+async fn reaction_change(ctx: &Context, reaction: &Reaction, add: bool) -> CommandResult {
+    let emoji = if let ReactionType::Unicode(emoji) = &reaction.emoji {
+        emoji
+    } else {
+        return Ok(());
+    };
+    let user = if let Some(user) = reaction.user_id {
+        user
+    } else {
+        return Ok(());
+    };
+    if user == ctx.cache.current_user_id().await {
+        return Ok(());
+    }
+    let storage = {
+        let data = ctx.data.read().await;
+        let is_menu = data
+            .get::<RoleMenu>()
+            .map_or(false, |menus| menus.contains(&reaction.message_id));
+        if !is_menu {
+            return Ok(());
+        }
+        data.get::<StorageKey>().ok_or("Storage not present")?.clone()
+    };
+
+    let menu = if let Some(menu) = storage.role_menu(reaction.message_id).await? {
+        menu
+    } else {
+        return Ok(());
+    };
+    let entry = if let Some(entry) = menu.entries.iter().find(|entry| &entry.emoji == emoji) {
+        entry
+    } else {
+        return Ok(());
+    };
+    if !add {
+        // Reactions withdrawn by the bot itself, after a swap or refusal, leave nothing to remove
+        if let Some(member) = ctx.cache.member(menu.server, user).await {
+            if !member.roles.contains(&entry.role) {
+                return Ok(());
+            }
+        }
+        ctx.http.remove_member_role(menu.server.0, user.0, entry.role.0).await?;
+        expiry::cancel(&*storage, menu.server, user, &[entry.role]).await?;
+        return Ok(());
+    }
+
+    let member = menu.server.member(ctx, user).await?;
+    // Gated roles are only requested, the same as joining them by command
+    if approvals::gated(&*storage, &[entry.role]).await? {
+        ctx.http.delete_reaction(
+            menu.channel.0,
+            menu.message.0,
+            Some(user.0),
+            &ReactionType::Unicode(entry.emoji.clone()),
+        ).await?;
+        let notice = if approvals::submit(ctx, &*storage, &member, &[entry.role], menu.channel, None).await? {
+            "needs a moderator's approval; your request was sent"
+        } else {
+            "needs a moderator's approval, but no moderator channel is set up"
+        };
+        let role_name = ctx.cache
+            .role(menu.server, entry.role)
+            .await
+            .map_or_else(|| "The role".to_string(), |role| format!("**{}**", role.name));
+        user
+            .create_dm_channel(ctx)
+            .await?
+            .send_message(ctx, |message| message
+                .embed(|e| e
+                    .title("Role request:")
+                    .description(format_args!("{} {}.", role_name, notice))
+                )
+            ).await?;
+        return Ok(());
+    }
+
+    // A refused role loses its reaction, as does every role swapped out of the menu
+    // Menus join without a limit, so any expiry of the role or those swapped out is forgotten
+    let withdrawn: Vec<&RoleMenuEntry> = match grant(ctx, &*storage, &member, &[entry.role], None).await? {
+        Ok(swapped) => menu.entries
+            .iter()
+            .filter(|entry| swapped.contains(&entry.role))
+            .collect(),
+        Err(_) => vec![entry],
+    };
+    for entry in withdrawn {
+        ctx.http.delete_reaction(
+            menu.channel.0,
+            menu.message.0,
+            Some(user.0),
+            &ReactionType::Unicode(entry.emoji.clone()),
+        ).await?;
+    }
+    Ok(())
+}
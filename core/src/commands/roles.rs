@@ -1,20 +1,15 @@
 use std::{
     result::Result,
     fmt::Write as _,
-    future::Future,
-};
-use wither::{
-    Model,
-    mongodb::Database,
+    sync::Arc,
+    time::Duration,
 };
 use futures::join;
-use tokio::sync::RwLockReadGuard;
 use serenity::{
     prelude::*,
     model::prelude::*,
     framework::standard::{
         Args,
-        CommandError,
         CommandResult,
         macros::{
             command,
@@ -22,179 +17,432 @@ use serenity::{
         },
     },
     builder::CreateEmbed,
+    utils::parse_channel,
 };
 use crate::{
-    models::RoleAssociation,
+    audit,
+    exclusive::{
+        self,
+        Refusal,
+    },
+    expiry,
+    models::{
+        AuditEvent,
+        RoleAssociation,
+        RoleRequest,
+    },
+    storage::{
+        Storage,
+        StorageKey,
+    },
     util::{
-        get_role_associations,
+        parse_duration,
+        DurationDisplay,
         Mentionable,
     },
-    DatabaseHandle,
 };
+use super::approvals;
 
 #[group]
-#[commands(join, dump_associations, leave, register_role)]
+#[description("Joining and leaving groups, and setting up where they can be joined.")]
+#[commands(join, dump_associations, associations, leave, register_role, unregister_role, alias_role)]
 pub struct Roles;
 
 #[command]
+#[description("Joins a group by its name, alias, or channel; without one, joins the groups of this channel. Groups may need a moderator's approval, and may be joined for a limited time.")]
+#[usage("[group] [for <duration>]")]
+#[example("artists")]
+#[example("#art for 7d")]
 #[only_in("guild")]
 async fn join(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
-    if args.len() > 1 {
+    let mut words: Vec<&str> = args.raw().collect();
+    // A trailing `for <duration>` joins only until the duration expires
+    let mut duration = None;
+    if let [.., keyword, value] = words.as_slice() {
+        if keyword.eq_ignore_ascii_case("for") {
+            match parse_duration(value) {
+                Some(parsed) => duration = Some(parsed),
+                None => {
+                    msg.reply(ctx, "The duration must be a number followed by `m`, `h`, `d`, or `w`, such as `7d`, and at most five years").await?;
+                    return Ok(());
+                },
+            }
+            words.truncate(words.len() - 2);
+        }
+    }
+    if words.len() > 1 {
         msg.reply(ctx, "No spaces in the name of the group to join").await?;
         return Ok(());
     }
 
     let guild = msg.guild_id.ok_or("No guild?")?;
     let typing = msg.channel_id.broadcast_typing(ctx);
-    let db = ctx.data.read();
-    let (typing, db) = join!(typing, db);
-    let (_, db): (_, &Database) = (
-        typing?,
-        &db
-            .get::<DatabaseHandle>()
-            .ok_or("Database not present")?
-            .base,
-    );
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    // Cloned out, as requests for gated roles are written to the data
+    let db: Arc<dyn Storage> = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    drop(data);
+    let db = &*db;
 
-    if args.is_empty() {
+    if let [name] = words.as_slice() {
+        let (mut member, guild, associations) = load_member_guild_and_associations(ctx, msg, guild, db).await?;
+        execute_named_role_change(
+            ctx,
+            msg,
+            db,
+            &guild,
+            &mut member,
+            associations,
+            name,
+            Change::Join(duration),
+            |e, roles| e
+                .title("Join command:")
+                .description(format_args!(
+                    "{} has joined {}.",
+                    Mentionable::from(msg.author.id),
+                    RoleList(roles),
+                )),
+        ).await?;
+    } else {
         let (mut member, associations) = load_member_and_associations(ctx, msg, guild, db).await?;
         execute_contextual_role_change(
             ctx,
             msg,
+            db,
             &mut member,
             associations,
-            Member::add_role,
-            |e, role| e
+            Change::Join(duration),
+            |e, roles| e
                 .title("Join command:")
                 .description(format_args!(
                     "{} has joined {} for {}.",
                     Mentionable::from(msg.author.id),
-                    Mentionable::from(role),
+                    RoleList(roles),
                     Mentionable::from(msg.channel_id),
                 )),
-            |e, role| e
+            |e, roles| e
                 .title("Join command:")
                 .description(format_args!(
                     "{} has joined {}.",
                     Mentionable::from(msg.author.id),
-                    Mentionable::from(role),
+                    RoleList(roles),
                 )),
         ).await?;
-    } else {
-        send_message_not_yet_implemented(ctx, msg).await?;
     }
 
     Ok(())
 }
 
 #[command]
+#[description("Leaves a group by its name, alias, or channel; without one, leaves the groups of this channel.")]
+#[usage("[group]")]
+#[example("artists")]
 #[only_in("guild")]
 async fn leave(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
     if args.len() > 1 {
-        msg.reply(ctx, "No spaces in the name of the group to join").await?;
+        msg.reply(ctx, "No spaces in the name of the group to leave").await?;
         return Ok(());
     }
 
     let guild = msg.guild_id.ok_or("No guild?")?;
     let typing = msg.channel_id.broadcast_typing(ctx);
-    let db = ctx.data.read();
-    let (typing, db) = join!(typing, db);
-    let (_, db): (_, &Database) = (
-        typing?,
-        &db
-            .get::<DatabaseHandle>()
-            .ok_or("Database not present")?
-            .base,
-    );
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    // Cloned out, as recording the change reads the data again
+    let db: Arc<dyn Storage> = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    drop(data);
+    let db = &*db;
 
     if args.is_empty() {
         let (mut member, associations) = load_member_and_associations(ctx, msg, guild, db).await?;
         execute_contextual_role_change(
             ctx,
             msg,
+            db,
             &mut member,
             associations,
-            Member::remove_role,
-            |e, role| e
+            Change::Leave,
+            |e, roles| e
                 .title("Leave command:")
                 .description(format_args!(
                     "{} has left {} for {}.",
                     Mentionable::from(msg.author.id),
-                    Mentionable::from(role),
+                    RoleList(roles),
                     Mentionable::from(msg.channel_id),
                 )),
-            |e, role| e
+            |e, roles| e
                 .title("Leave command:")
                 .description(format_args!(
                     "{} has left {}.",
                     Mentionable::from(msg.author.id),
-                    Mentionable::from(role),
+                    RoleList(roles),
                 )),
         ).await?;
     } else {
-        send_message_not_yet_implemented(ctx, msg).await?;
+        let (mut member, guild, associations) = load_member_guild_and_associations(ctx, msg, guild, db).await?;
+        execute_named_role_change(
+            ctx,
+            msg,
+            db,
+            &guild,
+            &mut member,
+            associations,
+            args.rest(),
+            Change::Leave,
+            |e, roles| e
+                .title("Leave command:")
+                .description(format_args!(
+                    "{} has left {}.",
+                    Mentionable::from(msg.author.id),
+                    RoleList(roles),
+                )),
+        ).await?;
+    }
+
+    Ok(())
+}
+
+/// Mentions of several roles, joined for an embed.
+pub(super) struct RoleList<'a>(pub(super) &'a [RoleId]);
+
+impl std::fmt::Display for RoleList<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (ix, role) in self.0.iter().enumerate() {
+            match ix {
+                0 => {},
+                _ if ix + 1 == self.0.len() => f.write_str(" and ")?,
+                _ => f.write_str(", ")?,
+            }
+            write!(f, "{}", Mentionable::from(*role))?;
+        }
+        Ok(())
+    }
+}
+
+/// What a role change does to the member.
+#[derive(Clone, Copy)]
+enum Change {
+    /// Joins the roles, until the duration expires if any.
+    Join(Option<Duration>),
+    Leave,
+}
+
+/// Joins the roles through their exclusive groups, then tracks or forgets their expiry.
+///
+/// Returns the roles swapped out, or why nothing changed.
+pub(super) async fn grant(
+    ctx: &Context,
+    db: &dyn Storage,
+    member: &Member,
+    roles: &[RoleId],
+    duration: Option<Duration>,
+) -> CommandResult<Result<Vec<RoleId>, Refusal>> {
+    let (guild, user) = (member.guild_id, member.user.id);
+    let outcome = exclusive::join(ctx, db, member, roles).await?;
+    if let Ok(swapped) = &outcome {
+        expiry::cancel(db, guild, user, swapped).await?;
+        match duration {
+            Some(duration) => {
+                expiry::schedule(db, guild, user, roles, duration).await?;
+            },
+            None => expiry::cancel(db, guild, user, roles).await?,
+        }
     }
+    Ok(outcome)
+}
 
+/// Joins or leaves the roles, then replies with the embed, or with why nothing changed.
+///
+/// Joining gated roles only requests them from the moderators.
+async fn change_roles(
+    ctx: &Context,
+    msg: &Message,
+    db: &dyn Storage,
+    member: &mut Member,
+    roles: &[RoleId],
+    gated: bool,
+    change: Change,
+    embed: impl for<'e> FnOnce(&'e mut CreateEmbed, &[RoleId]) -> &'e mut CreateEmbed,
+) -> CommandResult {
+    let (outcome, command) = match change {
+        Change::Join(duration) if gated => return approvals::request(ctx, msg, db, member, roles, duration).await,
+        Change::Join(duration) => (grant(ctx, db, member, roles, duration).await?, "join"),
+        Change::Leave => {
+            member.remove_roles(ctx, roles).await?;
+            expiry::cancel(db, member.guild_id, member.user.id, roles).await?;
+            (Ok(Vec::new()), "leave")
+        },
+    };
+    if let Ok(swapped) = &outcome {
+        let mut event = audit::event(msg, member.guild_id, command, roles.to_vec());
+        if !swapped.is_empty() {
+            event.old = Some(format!("holding {}", RoleList(swapped)));
+        }
+        audit::record(ctx, db, event).await?;
+    }
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| match &outcome {
+            Ok(swapped) => {
+                embed(e, roles);
+                if !swapped.is_empty() {
+                    e.field("Swapped out:", RoleList(swapped), false);
+                }
+                if let Change::Join(Some(duration)) = change {
+                    e.field("Expires in:", DurationDisplay(duration), false);
+                }
+                e
+            },
+            Err(refusal) => e
+                .title("Exclusive groups:")
+                .description(refusal),
+        })
+    ).await?;
     Ok(())
 }
 
-async fn execute_contextual_role_change<'a, F1, F2, V, E>(
-    ctx: &'a Context,
+/// Changes every role the channel carries, or else the server's generic role.
+async fn execute_contextual_role_change(
+    ctx: &Context,
     msg: &Message,
-    member: &'a mut Member,
+    db: &dyn Storage,
+    member: &mut Member,
     associations: Vec<RoleAssociation>,
-    change_roles: F1,
-    embed_channel_context: impl FnOnce(&mut CreateEmbed, RoleId) -> &mut CreateEmbed,
-    embed_server_context: impl FnOnce(&mut CreateEmbed, RoleId) -> &mut CreateEmbed,
-) -> CommandResult
-    where
-        F1: FnOnce(
-            &'a mut Member,
-            &'a Context,
-            RoleId,
-        ) -> F2,
-        F2: Future<Output=Result<V, E>>,
-        CommandError: From<E>,
-{
-    if let Some(association) = associations
+    change: Change,
+    embed_channel_context: impl for<'e> FnOnce(&'e mut CreateEmbed, &[RoleId]) -> &'e mut CreateEmbed,
+    embed_server_context: impl for<'e> FnOnce(&'e mut CreateEmbed, &[RoleId]) -> &'e mut CreateEmbed,
+) -> CommandResult {
+    let mut channel_roles: Vec<RoleId> = associations
         .iter()
-        .find(|association| association.channel.is_some())
-    {
-        change_roles(member, ctx, association.role).await?;
-        msg.channel_id.send_message(ctx, |message| message
-            .reference_message(msg)
-            .embed(|e| embed_channel_context(e, association.role))
-        ).await?;
+        .filter(|association| association.channel.is_some())
+        .map(|association| association.role)
+        .collect();
+    channel_roles.sort();
+    channel_roles.dedup();
+    let gated = |roles: &[RoleId]| associations
+        .iter()
+        .any(|association| association.approval && roles.contains(&association.role));
+    if !channel_roles.is_empty() {
+        let gated = gated(&channel_roles);
+        change_roles(ctx, msg, db, member, &channel_roles, gated, change, embed_channel_context).await?;
     } else if let Some(association) = associations
         .iter()
         .find(|association| association.server.is_some())
     {
-        change_roles(member, ctx, association.role).await?;
-        msg.channel_id.send_message(ctx, |message| message
-            .reference_message(msg)
-            .embed(|e| embed_server_context(e, association.role))
-        ).await?;
+        let roles = [association.role];
+        change_roles(ctx, msg, db, member, &roles, gated(&roles), change, embed_server_context).await?;
     } else {
         send_message_no_group_found(ctx, msg).await?;
     }
     return Ok(())
 }
 
+async fn execute_named_role_change(
+    ctx: &Context,
+    msg: &Message,
+    db: &dyn Storage,
+    guild: &Guild,
+    member: &mut Member,
+    associations: Vec<RoleAssociation>,
+    name: &str,
+    change: Change,
+    embed: impl for<'e> FnOnce(&'e mut CreateEmbed, &[RoleId]) -> &'e mut CreateEmbed,
+) -> CommandResult {
+    // Any association of a role may gate it, not only the one the name resolves to
+    let gated: Vec<RoleId> = associations
+        .iter()
+        .filter(|association| association.approval)
+        .map(|association| association.role)
+        .collect();
+    let mut candidates = find_named_associations(guild, associations, name);
+    // Among several matches, those of the current channel act as a selector
+    if candidates.len() > 1
+        && candidates.iter().any(|association| association.channel == Some(msg.channel_id))
+    {
+        candidates.retain(|association| association.channel == Some(msg.channel_id));
+    }
+    // A name matching several roles of a single channel, such as the channel itself, picks them all
+    let single_channel = candidates
+        .first()
+        .and_then(|first| first.channel)
+        .filter(|channel| candidates.iter().all(|association| association.channel == Some(*channel)))
+        .is_some();
+    match candidates.as_slice() {
+        [] => send_message_no_named_group_found(ctx, msg, name).await?,
+        candidates if candidates.len() == 1 || single_channel => {
+            let roles: Vec<RoleId> = candidates
+                .iter()
+                .map(|association| association.role)
+                .collect();
+            let gated = roles.iter().any(|role| gated.contains(role));
+            change_roles(ctx, msg, db, member, &roles, gated, change, embed).await?;
+        },
+        candidates => send_message_ambiguous_group(ctx, msg, guild, name, candidates).await?,
+    }
+    Ok(())
+}
+
+/// Resolves a group by role, alias, or channel, with one association per distinct role.
+pub(super) fn find_named_associations(
+    guild: &Guild,
+    associations: Vec<RoleAssociation>,
+    name: &str,
+) -> Vec<RoleAssociation> {
+    let mentioned_role: Option<RoleId> = name.parse().ok();
+    let mentioned_channel: Option<ChannelId> = name.parse().ok();
+    let name = name
+        .trim_start_matches(|c: char| c == '#' || c == '@')
+        .to_lowercase();
+
+    let mut candidates: Vec<RoleAssociation> = associations
+        .into_iter()
+        .filter(|association| {
+            let role = guild.roles.get(&association.role);
+            let channel = association.channel
+                .and_then(|channel| guild.channels.get(&channel));
+            mentioned_role == Some(association.role)
+                || (mentioned_channel.is_some() && mentioned_channel == association.channel)
+                || association.alias.as_deref() == Some(name.as_str())
+                || role.map_or(false, |role| role.name.to_lowercase() == name)
+                || channel.map_or(false, |channel| channel.name.to_lowercase() == name)
+        })
+        .collect();
+    candidates.sort_by_key(|association| association.role);
+    candidates.dedup_by_key(|association| association.role);
+    candidates
+}
+
+async fn load_member_guild_and_associations(ctx: &Context, msg: &Message, guild: GuildId, db: &dyn Storage) -> CommandResult<(Member, Guild, Vec<RoleAssociation>)> {
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let member = guild.member(ctx, &msg.author.id);
+    let associations = db.guild_role_associations(cached.channels.keys().copied().collect(), guild);
+    let (member, associations): (Result<Member, _>, Result<Vec<RoleAssociation>, _>) = join!(member, associations);
+    Ok((member?, cached, associations?))
+}
+
 #[command]
+#[description("Shows the raw role associations of this channel.")]
 #[only_in("guild")]
 async fn dump_associations(ctx: &Context, msg: &Message) -> CommandResult {
     let guild = msg.guild_id.ok_or("No guild present")?;
     let typing = msg.channel_id.broadcast_typing(ctx);
     let db = ctx.data.read();
     let (typing, db) = join!(typing, db);
-    let (_, db): (_, &Database) = (
+    let (_, db): (_, &dyn Storage) = (
         typing?,
-        &db
-            .get::<DatabaseHandle>()
-            .ok_or("Database not present")?
-            .base,
+        &**db
+            .get::<StorageKey>()
+            .ok_or("Storage not present")?,
     );
-    let associations = get_role_associations(db,msg.channel_id, guild).await?;
+    let associations = db.role_associations(msg.channel_id, guild).await?;
 
     let mut description = "```\n".to_string();
     for (ix, association) in associations.iter().enumerate() {
@@ -247,27 +495,120 @@ async fn dump_associations(ctx: &Context, msg: &Message) -> CommandResult {
     Ok(())
 }
 
-async fn load_member_and_associations(ctx: &Context, msg: &Message, guild: GuildId, db: &Database) -> CommandResult<(Member, Vec<RoleAssociation>)> {
-    let member = guild.member(ctx, &msg.author.id);
-    let associations = get_role_associations(db, msg.channel_id, guild);
-    let (member, associations): (Result<Member, _>, Result<Vec<RoleAssociation>, _>) = join!(member, associations);
-    Ok((member?, associations?))
-}
+const ASSOCIATIONS_PAGE: usize = 15;
+
+#[command]
+#[description("Lists the groups of the server, and how to join them.")]
+#[usage("[page]")]
+#[example("2")]
+#[only_in("guild")]
+async fn associations(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Zero or one parameters.\
+            \nThe parameter is the page of associations to show.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let page: usize = if args.is_empty() {
+        1
+    } else {
+        match args.single() {
+            Ok(page) if page > 0 => page,
+            _ => return bad_message(ctx, msg).await,
+        }
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let db = ctx.data.read();
+    let (typing, db) = join!(typing, db);
+    let (_, db): (_, &dyn Storage) = (
+        typing?,
+        &**db
+            .get::<StorageKey>()
+            .ok_or("Storage not present")?,
+    );
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let mut associations: Vec<RoleAssociation> = db
+        .server_role_associations(
+            guild,
+            cached.channels.keys().copied().collect(),
+            cached.roles.keys().copied().collect(),
+        )
+        .await?;
+    associations.sort_by_key(|association| (association.channel, association.role));
+
+    if associations.is_empty() {
+        return send_message_no_group_found(ctx, msg).await;
+    }
+    let pages = (associations.len() + ASSOCIATIONS_PAGE - 1) / ASSOCIATIONS_PAGE;
+    if page > pages {
+        return bad_message(ctx, msg).await;
+    }
+
+    let mut description = String::new();
+    let mut flagged = 0;
+    for (ix, association) in associations.iter().enumerate() {
+        let role_missing = !cached.roles.contains_key(&association.role);
+        let channel_missing = association.channel
+            .map_or(false, |channel| !cached.channels.contains_key(&channel));
+        if role_missing || channel_missing {
+            flagged += 1;
+        }
+        if ix / ASSOCIATIONS_PAGE + 1 != page {
+            continue;
+        }
+        write!(&mut description, "{} in ", Mentionable::from(association.role))?;
+        match association.channel {
+            Some(channel) => write!(&mut description, "{}", Mentionable::from(channel))?,
+            None => write!(&mut description, "the server")?,
+        }
+        if let Some(alias) = &association.alias {
+            write!(&mut description, " as `{}`", alias)?;
+        }
+        if role_missing {
+            write!(&mut description, " ⚠ role `{}` no longer exists", association.role.0)?;
+        }
+        if channel_missing {
+            write!(&mut description, " ⚠ channel no longer exists")?;
+        }
+        writeln!(&mut description)?;
+    }
+    let total = associations.len();
 
-async fn send_message_not_yet_implemented(ctx: &Context, msg: &Message) -> CommandResult {
     msg.channel_id.send_message(ctx, |message| message
         .reference_message(msg)
         .embed(|e| e
-            .title("I can't do that yet!")
-            .description("\
-                At some future date, I'll be able to find the group you want.\
-                \n\nFor now, please try to join/leave in the respective channel.\
-            ")
+            .title("Role Associations:")
+            .description(description)
+            .footer(|f| f
+                .text(format_args!(
+                    "Page {} of {} · {} associations, {} flagged",
+                    page,
+                    pages,
+                    total,
+                    flagged,
+                ))
+            )
         )
     ).await?;
+
     Ok(())
 }
 
+async fn load_member_and_associations(ctx: &Context, msg: &Message, guild: GuildId, db: &dyn Storage) -> CommandResult<(Member, Vec<RoleAssociation>)> {
+    let member = guild.member(ctx, &msg.author.id);
+    let associations = db.role_associations(msg.channel_id, guild);
+    let (member, associations): (Result<Member, _>, Result<Vec<RoleAssociation>, _>) = join!(member, associations);
+    Ok((member?, associations?))
+}
+
 async fn send_message_no_group_found(ctx: &Context, msg: &Message) -> CommandResult {
     msg.channel_id.send_message(ctx, |message| message
         .reference_message(msg)
@@ -285,7 +626,165 @@ async fn send_message_no_group_found(ctx: &Context, msg: &Message) -> CommandRes
     Ok(())
 }
 
+async fn send_message_no_named_group_found(ctx: &Context, msg: &Message, name: &str) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("No groups found!")
+            .description(format_args!(
+                "\
+                    No group named `{}`.\
+                    \nTry the name of the role, its alias, or its channel.\
+                ",
+                name,
+            ))
+        )
+    ).await?;
+    Ok(())
+}
+
+async fn send_message_ambiguous_group(
+    ctx: &Context,
+    msg: &Message,
+    guild: &Guild,
+    name: &str,
+    candidates: &[RoleAssociation],
+) -> CommandResult {
+    let mut description = String::new();
+    writeln!(&mut description, "`{}` could mean any of these groups:", name)?;
+    for association in candidates {
+        write!(&mut description, "\n{}", Mentionable::from(association.role))?;
+        if let Some(channel) = association.channel {
+            write!(&mut description, " in {}", Mentionable::from(channel))?;
+        }
+        if let Some(alias) = &association.alias {
+            write!(&mut description, " (alias `{}`)", alias)?;
+        } else if let Some(role) = guild.roles.get(&association.role) {
+            write!(&mut description, " (role `{}`)", role.name)?;
+        }
+    }
+    description.push_str("\n\nTry again with the role mention or alias.");
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Which group?")
+            .description(description)
+        )
+    ).await?;
+    Ok(())
+}
+
 #[command]
+#[description("Sets the alias a group can be joined by, or clears it.")]
+#[usage("<group> [alias]")]
+#[example("@Artists art")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn alias_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be either a reference to the group, or the group ID.\
+            \nThe second is the alias, without spaces; leave it out to clear the alias.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let role: RoleId = match args.single() {
+        Ok(role) => role,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let alias = if args.is_empty() {
+        None
+    } else {
+        Some(args.single::<String>()?.to_lowercase())
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    // Cloned out, as recording the change reads the data again
+    let db: Arc<dyn Storage> = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    drop(data);
+    let db = &*db;
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let associations = db.guild_role_associations(cached.channels.keys().copied().collect(), guild).await?;
+
+    if let Some(alias) = &alias {
+        if let Some(taken) = associations
+            .iter()
+            .find(|association| association.role != role && association.alias.as_ref() == Some(alias))
+        {
+            msg.channel_id.send_message(ctx, |message| message
+                .reference_message(msg)
+                .embed(|e| e
+                    .title("Role Alias:")
+                    .description(format_args!(
+                        "`{}` is already the alias of {}",
+                        alias,
+                        Mentionable::from(taken.role),
+                    ))
+                )
+            ).await?;
+            return Ok(());
+        }
+    }
+
+    let mut updated = 0;
+    let mut event = audit::event(msg, guild, "alias_role", vec![role]);
+    for mut association in associations {
+        if association.role == role {
+            event.old.get_or_insert_with(|| audit::describe(&association));
+            association.alias = alias.clone();
+            event.new.get_or_insert_with(|| audit::describe(&association));
+            db.save_role_association(&mut association).await?;
+            updated += 1;
+        }
+    }
+    if updated > 0 {
+        audit::record(ctx, db, event).await?;
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("Role Alias:");
+            match (updated, &alias) {
+                (0, _) => e.description(format_args!(
+                    "{} is not associated to anything",
+                    Mentionable::from(role),
+                )),
+                (_, Some(alias)) => e.description(format_args!(
+                    "{} can now be joined as `{}`",
+                    Mentionable::from(role),
+                    alias,
+                )),
+                (_, None) => e.description(format_args!(
+                    "{} no longer has an alias",
+                    Mentionable::from(role),
+                )),
+            }
+        })
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Makes a group joinable in the channel, alongside its other groups; without a channel, makes it the server's generic group.")]
+#[usage("[channel] <group>")]
+#[example("#art @Artists")]
+#[example("@Members")]
 #[only_in("guild")]
 #[required_permissions("ADMINISTRATOR")]
 async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
@@ -328,17 +827,15 @@ async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
         // This is split into a function for ? usage
         // But, even more-so, it's a two-await that can be done concurrently to the single-awaits
         async fn get_db_and_associations(ctx: &Context, guild: GuildId, channel: ChannelId)
-            -> CommandResult<(RwLockReadGuard<'_, TypeMap>, Vec<RoleAssociation>)>
+            -> CommandResult<(Arc<dyn Storage>, Vec<RoleAssociation>)>
         {
-            let db = ctx.data.read().await;
-            let associations = get_role_associations(
-                &db
-                    .get::<DatabaseHandle>()
-                    .ok_or("Database not present")?
-                    .base,
-                channel,
-                guild,
-            ).await?;
+            let db = ctx.data
+                .read()
+                .await
+                .get::<StorageKey>()
+                .ok_or("Storage not present")?
+                .clone();
+            let associations = db.role_associations(channel, guild).await?;
             Ok((db, associations))
         }
         get_db_and_associations(ctx, guild, channel.unwrap_or(ChannelId(!0))).await
@@ -361,58 +858,65 @@ async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
     };
     type PossibleParse<T> = Result<Option<T>, ()>;
     let (db, channel, role): (_, PossibleParse<ChannelId>, PossibleParse<RoleId>) = join!(db, channel, role);
-    let (db, associations): (_, Vec<RoleAssociation>) = db?;
-    let db: &Database = &db
-        .get::<DatabaseHandle>()
-        .ok_or("Database not present")?
-        .base;
+    let (db, associations): (Arc<dyn Storage>, Vec<RoleAssociation>) = db?;
     let (channel, role) = match (channel, role) {
         (_, Ok(None)) => return bad_message(ctx, msg).await,
         (Ok(channel), Ok(Some(role))) => (channel, role),
         _ => return unamused(ctx, msg).await, // Cross-guild poisoning
     };
+    // New associations follow the role's others, so no channel opens a way around its approval
+    let approval = approvals::gated(&*db, &[role]).await?;
 
     if let Some(channel) = channel {
-        for mut association in associations {
-            if association.channel.is_some() {
-                let old = association.role;
-                association.role = role;
-                association.save(db, None).await?;
-
-                msg.channel_id.send_message(ctx, |message| message
-                    .reference_message(msg)
-                    .embed(|e| e
-                        .title("Role Association:")
-                        .description(format_args!(
-                            "{} updated to {} from {}",
-                            Mentionable::from(channel),
-                            Mentionable::from(role),
-                            Mentionable::from(old),
-                        ))
-                    )
-                ).await?;
-                return Ok(());
-            }
+        // A channel carries any number of roles, so this only ever adds
+        let existing: Vec<RoleId> = associations
+            .iter()
+            .filter(|association| association.channel == Some(channel))
+            .map(|association| association.role)
+            .collect();
+        if existing.contains(&role) {
+            msg.channel_id.send_message(ctx, |message| message
+                .reference_message(msg)
+                .embed(|e| e
+                    .title("Role Association:")
+                    .description(format_args!(
+                        "{} is already associated to {}",
+                        Mentionable::from(channel),
+                        Mentionable::from(role),
+                    ))
+                )
+            ).await?;
+            return Ok(());
         }
 
-        // None exist; make a new one!
-        RoleAssociation {
+        let alongside = if existing.is_empty() {
+            String::new()
+        } else {
+            format!(", alongside {}", RoleList(&existing))
+        };
+        let mut association = RoleAssociation {
             id: None,
             channel: Some(channel),
             server: None,
-            role
-        }
-            .save(db, None)
-            .await?;
+            role,
+            alias: None,
+            approval,
+        };
+        db.save_role_association(&mut association).await?;
+        audit::record(ctx, &*db, AuditEvent {
+            new: Some(audit::describe(&association)),
+            ..audit::event(msg, guild, "register_role", vec![role])
+        }).await?;
 
         msg.channel_id.send_message(ctx, |message| message
             .reference_message(msg)
             .embed(|e| e
                 .title("Role Association:")
                 .description(format_args!(
-                    "{} is now associated to {}",
+                    "{} is now associated to {}{}",
                     Mentionable::from(channel),
                     Mentionable::from(role),
+                    alongside,
                 ))
             )
         ).await?;
@@ -420,8 +924,15 @@ async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
         for mut association in associations {
             if association.server.is_some() {
                 let old = association.role;
+                let described = audit::describe(&association);
                 association.role = role;
-                association.save(db, None).await?;
+                association.approval = approval;
+                db.save_role_association(&mut association).await?;
+                audit::record(ctx, &*db, AuditEvent {
+                    old: Some(described),
+                    new: Some(audit::describe(&association)),
+                    ..audit::event(msg, guild, "register_role", vec![old, role])
+                }).await?;
 
                 msg.channel_id.send_message(ctx, |message| message
                     .reference_message(msg)
@@ -439,14 +950,19 @@ async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
         }
 
         // None exist; make a new one!
-        RoleAssociation {
+        let mut association = RoleAssociation {
             id: None,
             channel: None,
             server: Some(guild),
-            role
-        }
-            .save(db, None)
-            .await?;
+            role,
+            alias: None,
+            approval,
+        };
+        db.save_role_association(&mut association).await?;
+        audit::record(ctx, &*db, AuditEvent {
+            new: Some(audit::describe(&association)),
+            ..audit::event(msg, guild, "register_role", vec![role])
+        }).await?;
 
         msg.channel_id.send_message(ctx, |message| message
             .reference_message(msg)
@@ -462,3 +978,225 @@ async fn register_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandR
 
     Ok(())
 }
+
+const CONFIRM: &str = "confirm";
+
+#[command]
+#[description("Stops a group being joinable in the channel; without a channel, unregisters the server's generic group. Shows what would be removed until confirmed.")]
+#[usage("[channel] [group] [confirm]")]
+#[example("#art")]
+#[example("#art @Artists confirm")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn unregister_role(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Zero to three parameters.\
+            \nOne may be a reference to the channel; without it, the server's generic role is unregistered.\
+            \nOne may be a reference to the group, or the group ID, to unregister only that role of the channel.\
+            \nThe last may be `confirm`, to actually remove it.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let mut channel: Option<ChannelId> = None;
+    let mut role: Option<RoleId> = None;
+    let mut confirmed = false;
+    while !args.is_empty() {
+        let arg: String = args.single()?;
+        if confirmed {
+            return bad_message(ctx, msg).await;
+        } else if arg.eq_ignore_ascii_case(CONFIRM) {
+            confirmed = true;
+        // A bare ID is the role's, so the channel must be a reference
+        } else if let (None, Ok(parsed)) = (role, arg.parse()) {
+            role = Some(parsed);
+        } else if let (None, Some(parsed)) = (channel, parse_channel(&arg)) {
+            channel = Some(ChannelId(parsed));
+        } else {
+            return bad_message(ctx, msg).await;
+        }
+    }
+
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    // Cloned out, as recording the change reads the data again
+    let db: Arc<dyn Storage> = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    drop(data);
+    let db = &*db;
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    // A deleted channel can still be unregistered, as long as its role shows it belonged here
+    let targets: Vec<RoleAssociation> = db
+        .role_associations(channel.unwrap_or(ChannelId(!0)), guild)
+        .await?
+        .into_iter()
+        .filter(|association| match channel {
+            Some(channel) =>
+                association.channel == Some(channel)
+                && (cached.channels.contains_key(&channel) || cached.roles.contains_key(&association.role)),
+            None =>
+                association.channel.is_none() && association.server == Some(guild),
+        })
+        .filter(|association| role.map_or(true, |role| association.role == role))
+        .collect();
+
+    let place = match channel {
+        Some(channel) => format!("for {}", Mentionable::from(channel)),
+        None => "as the server's generic role".to_string(),
+    };
+    if targets.is_empty() {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .embed(|e| e
+                .title("Role Association:")
+                .description(format_args!("Nothing is registered {}", place))
+            )
+        ).await?;
+        return Ok(());
+    }
+
+    // Roles left in no other association stop being groups, so what still refers to them goes too
+    let mut released = Vec::new();
+    for association in &targets {
+        if released.contains(&association.role) {
+            continue;
+        }
+        let elsewhere = db
+            .role_associations_of(association.role)
+            .await?
+            .into_iter()
+            .any(|other| targets.iter().all(|target| target.id != other.id));
+        if !elsewhere {
+            released.push(association.role);
+        }
+    }
+    let references = release(ctx, db, guild, &released, confirmed).await?;
+
+    let mut roles = String::new();
+    for association in &targets {
+        if confirmed {
+            db.delete_role_association(association).await?;
+        }
+        write!(&mut roles, "{} ", Mentionable::from(association.role))?;
+    }
+    let references = if references.is_empty() {
+        String::new()
+    } else {
+        format!("\n{} {}.", if confirmed { "Also removed" } else { "It would also remove" }, references.join(", "))
+    };
+    if confirmed {
+        let described: Vec<String> = targets.iter().map(audit::describe).collect();
+        audit::record(ctx, db, AuditEvent {
+            old: Some(described.join("; ")),
+            ..audit::event(msg, guild, "unregister_role", targets.iter().map(|association| association.role).collect())
+        }).await?;
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Role Association:")
+            .description(if confirmed {
+                format!("Unregistered {}{}{}", roles, place, references)
+            } else {
+                format!(
+                    "This would unregister {}{}.{}\nRepeat the command ending with `{}` to remove it.",
+                    roles,
+                    place,
+                    references,
+                    CONFIRM,
+                )
+            })
+        )
+    ).await?;
+
+    Ok(())
+}
+
+/// What still refers to roles that are no longer any group: role menu entries, places in exclusive groups,
+/// pending requests and expiries. They are removed only once `confirmed`.
+///
+/// Returns a description of each kind found.
+async fn release(ctx: &Context, db: &dyn Storage, guild: GuildId, roles: &[RoleId], confirmed: bool) -> CommandResult<Vec<String>> {
+    let mut found = Vec::new();
+    if roles.is_empty() {
+        return Ok(found);
+    }
+
+    let mut entries = 0;
+    for mut menu in db.role_menus().await?.into_iter().filter(|menu| menu.server == guild) {
+        let before = menu.entries.len();
+        menu.entries.retain(|entry| !roles.contains(&entry.role));
+        if menu.entries.len() < before {
+            entries += before - menu.entries.len();
+            if confirmed {
+                db.save_role_menu(&mut menu).await?;
+            }
+        }
+    }
+    if entries > 0 {
+        found.push(format!("{} role menu entries", entries));
+    }
+
+    for mut group in db.exclusive_groups(guild).await? {
+        let before = group.roles.len();
+        group.roles.retain(|role| !roles.contains(role));
+        if group.roles.len() == before {
+            continue;
+        }
+        found.push(format!("a place in the exclusive group `{}`", group.name));
+        if !confirmed {
+            continue;
+        } else if group.roles.is_empty() {
+            db.delete_exclusive_group(&group).await?;
+        } else {
+            db.save_exclusive_group(&mut group).await?;
+        }
+    }
+
+    let mut requests = 0;
+    for mut request in db.role_requests(Some(guild)).await? {
+        let before = request.roles.len();
+        request.roles.retain(|role| !roles.contains(role));
+        if request.roles.len() == before {
+            continue;
+        }
+        requests += 1;
+        if !confirmed {
+            continue;
+        } else if request.roles.is_empty() {
+            // Unclaimed first, so a moderator deciding it now finds nothing to decide
+            if let Some(pending) = ctx.data.write().await.get_mut::<RoleRequest>() {
+                pending.remove(&request.message);
+            }
+            db.delete_role_request(&request).await?;
+        } else {
+            db.save_role_request(&mut request).await?;
+        }
+    }
+    if requests > 0 {
+        found.push(format!("{} pending requests", requests));
+    }
+
+    let mut expiries = 0;
+    for role in roles {
+        for expiry in db.role_expiries_of(guild, *role).await? {
+            expiries += 1;
+            if confirmed {
+                db.delete_role_expiry(&expiry).await?;
+            }
+        }
+    }
+    if expiries > 0 {
+        found.push(format!("{} expiries", expiries));
+    }
+
+    Ok(found)
+}
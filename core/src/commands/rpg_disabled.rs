@@ -11,9 +11,32 @@ use serenity::{
 };
 
 #[group]
-#[commands(play, rpg_channel)]
+#[commands(play, rpg_channel, rewind, rpg_rewind_limit)]
+#[sub_groups(RPGTools)]
+// Only answers that the RPG is unavailable, so left out of the help
+#[help_available(false)]
 pub struct RPG;
 
+#[group]
+#[prefixes("rpg")]
+#[commands(history)]
+#[help_available(false)]
+pub struct RPGTools;
+
+#[command]
+#[only_in("guild")]
+async fn history(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("RPG")
+            .description("Sorry, RPG is unavailable.")
+        )
+    ).await?;
+
+    Ok(())
+}
+
 #[command]
 #[only_in("guild")]
 async fn play(ctx: &Context, msg: &Message) -> CommandResult {
@@ -42,3 +65,33 @@ async fn rpg_channel(ctx: &Context, msg: &Message) -> CommandResult {
 
     Ok(())
 }
+
+#[command]
+#[aliases("undo")]
+#[only_in("guild")]
+async fn rewind(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("RPG")
+            .description("Sorry, RPG is unavailable.")
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn rpg_rewind_limit(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("RPG")
+            .description("Sorry, RPG is unavailable.")
+        )
+    ).await?;
+
+    Ok(())
+}
@@ -1,11 +1,10 @@
-use wither::{
-    bson::doc,
-    Model,
-    mongodb::{
-        Database,
-        options::FindOneOptions,
-    },
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Write as _,
 };
+
+use wither::bson::oid::ObjectId;
 use futures::{
     join,
     future::join_all,
@@ -24,6 +23,10 @@ use serenity::{
 };
 use tokio::sync::MutexGuard;
 use cache_2q::Entry;
+use serde_json::{
+    json,
+    Value,
+};
 use ohg_bot_headers::{
     Action,
     Reactions,
@@ -31,23 +34,191 @@ use ohg_bot_headers::{
     StateReaction,
 };
 use crate::{
+    metrics::Metrics,
     models::{
+        RPGRewindLimit,
         RPGState,
         RPGChannel,
-        Shim,
+    },
+    storage::{
+        Storage,
+        StorageKey,
     },
     util::{
+        parse_message_link,
         Mentionable,
         RPGStateHolder,
     },
-    DatabaseHandle,
 };
 
 #[group]
-#[commands(play, rpg_channel)]
+#[description("Playing the RPG.")]
+#[commands(play, rpg_channel, rewind, rpg_rewind_limit)]
+#[sub_groups(RPGTools)]
 pub struct RPG;
 
+#[group]
+#[description("Looking back on RPG games.")]
+#[prefixes("rpg")]
+#[commands(history)]
+pub struct RPGTools;
+
+const HISTORY_PAGE: usize = 15;
+
 #[command]
+#[description("Shows the moves of a game.")]
+#[usage("<game link> [page]")]
+#[only_in("guild")]
+async fn history(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be a link to the game.\
+            \nThe second is the page of moves to show.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let (channel, message) = match args
+        .single::<String>()
+        .ok()
+        .and_then(|link| parse_message_link(&link, msg.channel_id))
+    {
+        Some(link) => link,
+        None => return bad_message(ctx, msg).await,
+    };
+    let page: usize = if args.is_empty() {
+        1
+    } else {
+        match args.single() {
+            Ok(page) if page > 0 => page,
+            _ => return bad_message(ctx, msg).await,
+        }
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    msg.channel_id.broadcast_typing(ctx).await?;
+    let (storage, rpg_channel) = {
+        let data = ctx.data.read().await;
+        (
+            data.get::<StorageKey>().ok_or("Storage not present")?.clone(),
+            data.get::<RPGChannel>().ok_or("Channels not present")?.contains(&channel),
+        )
+    };
+    // Only games of this server are shown, so the link must be to one of its RPG channels, and the game in it
+    let in_guild = ctx.cache
+        .guild_channel(channel)
+        .await
+        .map_or(false, |channel| channel.guild_id == guild);
+    let chain = if rpg_channel && in_guild && channel.message(ctx, message).await.is_ok() {
+        load_chain(&*storage, message).await?
+    } else {
+        Vec::new()
+    };
+
+    let (first, last) = if let (Some(first), Some(last)) = (chain.first(), chain.last()) {
+        (first, last)
+    } else {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .content("No game found for that message.")
+        ).await?;
+        return Ok(());
+    };
+    let pages = (chain.len() + HISTORY_PAGE - 1) / HISTORY_PAGE;
+    if page > pages {
+        return bad_message(ctx, msg).await;
+    }
+
+    let mut description = String::new();
+    let mut moves = Vec::with_capacity(chain.len());
+    for (ix, state) in chain.iter().enumerate() {
+        let contents = serde_json::to_value(&state.state)?;
+        if ix / HISTORY_PAGE + 1 == page {
+            writeln!(
+                &mut description,
+                "`{:>4}` {}",
+                state.iteration,
+                contents
+                    .get("state")
+                    .and_then(Value::as_str)
+                    .unwrap_or("?"),
+            )?;
+        }
+        moves.push(json!({
+            "id": state.id.as_ref().map(ObjectId::to_hex),
+            "previous": state.previous.as_ref().map(ObjectId::to_hex),
+            "iteration": state.iteration,
+            "active": state.active,
+            "state": contents,
+        }));
+    }
+    let export = serde_json::to_vec_pretty(&json!({
+        "message": message.0.to_string(),
+        "owner": first.owner.0.to_string(),
+        "moves": moves,
+    }))?;
+    let (owner, total, active) = (first.owner, chain.len(), last.active);
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("RPG history:")
+            .description(description)
+            .field("Owner", Mentionable::from(owner), true)
+            .field("Moves", total, true)
+            .field("Status", if active { "In progress" } else { "Ended" }, true)
+            .footer(|f| f
+                .text(format_args!("Page {} of {}", page, pages))
+            )
+        )
+        .add_file(AttachmentType::Bytes {
+            data: Cow::Owned(export),
+            filename: format!("rpg-{}.json", message.0),
+        })
+    ).await?;
+
+    Ok(())
+}
+
+/// Every state of a game, oldest first, following `previous` back from the latest iteration.
+async fn load_chain(storage: &dyn Storage, message: MessageId) -> CommandResult<Vec<RPGState>> {
+    let mut states = HashMap::new();
+    let mut latest: Option<(i32, ObjectId)> = None;
+    for state in storage.rpg_states(message).await? {
+        let id = if let Some(id) = &state.id {
+            id.clone()
+        } else {
+            continue;
+        };
+        if latest.as_ref().map_or(true, |(iteration, _)| *iteration < state.iteration) {
+            latest = Some((state.iteration, id.clone()));
+        }
+        states.insert(id, state);
+    }
+
+    let mut chain = Vec::new();
+    let mut next = latest.map(|(_, id)| id);
+    while let Some(id) = next {
+        if let Some(state) = states.remove(&id) {
+            next = state.previous.clone();
+            chain.push(state);
+        } else {
+            break;
+        }
+    }
+    chain.reverse();
+    Ok(chain)
+}
+
+#[command]
+#[description("Makes the channel one where games can be played.")]
+#[usage("<channel>")]
+#[example("#rpg")]
 #[only_in("guild")]
 #[required_permissions("ADMINISTRATOR")]
 async fn rpg_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
@@ -80,11 +251,11 @@ async fn rpg_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandRes
     let mut data = ctx.data.write().await;
     let channels = data.get_mut::<RPGChannel>().ok_or("No rpg channels?")?;
     if channels.insert(channel.id) {
-        let db = &data.get::<DatabaseHandle>().ok_or("No database?")?.base;
-        RPGChannel {
+        let storage = data.get::<StorageKey>().ok_or("No storage?")?;
+        storage.save_rpg_channel(&mut RPGChannel {
             id: None,
             channel: channel.id,
-        }.save(db, None).await?;
+        }).await?;
         drop(data);
         channel.send_message(ctx, |message| message
             .content("RPG Enabled. Use `!play` with your character name to start.")
@@ -100,9 +271,243 @@ async fn rpg_channel(ctx: &Context, msg: &Message, mut args: Args) -> CommandRes
     Ok(())
 }
 
+/// Rewinds allowed when a server has not set its own limit.
+const DEFAULT_REWIND_LIMIT: i32 = 5;
+
+#[command]
+#[description("Sets how many moves a player may rewind, zero to disable rewinding.")]
+#[usage("<moves>")]
+#[example("3")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn rpg_rewind_limit(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Specify how many moves a player may rewind, zero to disable.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let limit: i32 = match args.single() {
+        Ok(limit) if limit >= 0 => limit,
+        _ => return bad_message(ctx, msg).await,
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let data = ctx.data.read().await;
+    let storage = data.get::<StorageKey>().ok_or("No storage?")?;
+    let mut setting = storage
+        .rewind_limit(guild)
+        .await?
+        .unwrap_or(RPGRewindLimit {
+            id: None,
+            server: guild,
+            limit,
+        });
+    setting.limit = limit;
+    storage.save_rewind_limit(&mut setting).await?;
+    drop(data);
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .content(format_args!("Players may now rewind up to {} moves.", limit))
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Takes back the last moves of a game, up to the server's limit.")]
+#[usage("<game link> [moves]")]
+#[aliases("undo")]
+#[only_in("guild")]
+async fn rewind(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be a link to the game.\
+            \nThe second is how many moves to rewind, one if left out.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        return Ok(());
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let (channel, message) = match args
+        .single::<String>()
+        .ok()
+        .and_then(|link| parse_message_link(&link, msg.channel_id))
+    {
+        Some(parsed) => parsed,
+        None => return bad_message(ctx, msg).await,
+    };
+    let count: i32 = if args.is_empty() {
+        1
+    } else {
+        match args.single() {
+            Ok(count) if count > 0 => count,
+            _ => return bad_message(ctx, msg).await,
+        }
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    msg.channel_id.broadcast_typing(ctx).await?;
+    // The limit is this server's, so the game must be one of its own
+    let in_guild = ctx.cache
+        .guild_channel(channel)
+        .await
+        .map_or(false, |channel| channel.guild_id == guild);
+    if !in_guild || channel.message(ctx, message).await.is_err() {
+        return bad_message(ctx, msg).await;
+    }
+    let data_lock = ctx.data.read().await;
+    if !data_lock.get::<RPGChannel>().ok_or("Channels not present")?.contains(&channel) {
+        return bad_message(ctx, msg).await;
+    }
+    let storage: &dyn Storage = &**data_lock.get::<StorageKey>().ok_or("Storage not present")?;
+    let limit = storage
+        .rewind_limit(guild)
+        .await?
+        .map_or(DEFAULT_REWIND_LIMIT, |setting| setting.limit);
+    if count > limit {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .content(format_args!("Rewinds are limited to {} moves here.", limit))
+        ).await?;
+        return Ok(());
+    }
+
+    let states_mutex = data_lock.get::<RPGState>().ok_or("No States")?;
+    let use_components = states_mutex.lock().await.components;
+    let state = if let Some(state) = obtain_state(storage, states_mutex, message, msg.author.id, data_lock.get::<Metrics>().map(|metrics| &**metrics)).await? {
+        state
+    } else {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .content("Only the owner of an idle game can rewind it.")
+        ).await?;
+        return Ok(());
+    };
+
+    let rewound = match rewind_state(ctx, storage, state, count, channel, message, use_components).await {
+        Ok((state, rewound)) => {
+            unlock(states_mutex, Some(Some(state)), message).await;
+            rewound
+        },
+        Err(e) => {
+            unlock(states_mutex, None, message).await;
+            return Err(e);
+        },
+    };
+    drop(data_lock);
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .content(if rewound {
+            format!("Rewound {} moves.", count)
+        } else {
+            format!("That game doesn't go back {} moves.", count)
+        })
+    ).await?;
+
+    Ok(())
+}
+
+/// Saves the snapshot from `count` iterations ago as the newest iteration, and displays it.
+///
+/// The current state is returned untouched if the chain is too short.
+async fn rewind_state(
+    ctx: &Context,
+    storage: &dyn Storage,
+    current: RPGState,
+    count: i32,
+    channel: ChannelId,
+    message: MessageId,
+    use_components: bool,
+) -> CommandResult<(RPGState, bool)> {
+    let snapshot = match rewind_snapshot(storage, &current, count).await? {
+        Some(snapshot) => snapshot,
+        None => return Ok((current, false)),
+    };
+
+    let mut state = RPGState {
+        id: None,
+        state: snapshot.state,
+        active: true,
+        message,
+        owner: current.owner,
+        iteration: current.iteration + 1,
+        previous: current.id,
+    };
+    let (reactions, embed) = state.state.display_in(storage.rpg_documents()).await?;
+    if !use_components {
+        channel.delete_reactions(ctx, message).await?;
+    }
+    storage.save_rpg_state(&mut state).await?;
+    edit_game(ctx, channel, message, state.owner, embed, &reactions, use_components).await?;
+    if !use_components {
+        pre_fill_reactions(ctx, reactions, channel, message).await?;
+    }
+
+    Ok((state, true))
+}
+
+/// The snapshot `count` iterations before the current state, following the previous chain.
+async fn rewind_snapshot(storage: &dyn Storage, current: &RPGState, count: i32) -> CommandResult<Option<RPGState>> {
+    let mut next = current.previous.clone();
+    let mut snapshot = None;
+    for _ in 0..count {
+        let id = if let Some(id) = next {
+            id
+        } else {
+            return Ok(None);
+        };
+        snapshot = storage.rpg_state_by_id(&id).await?;
+        next = if let Some(snapshot) = &snapshot {
+            snapshot.previous.clone()
+        } else {
+            return Ok(None);
+        };
+    }
+    Ok(snapshot)
+}
+
+async fn edit_game(
+    ctx: &Context,
+    channel: ChannelId,
+    message: MessageId,
+    user: UserId,
+    embed: CreateEmbed,
+    reactions: &Reactions,
+    use_components: bool,
+) -> CommandResult {
+    channel.edit_message(ctx, message, |e| {
+        e
+            .content(Mentionable::from(user))
+            .embed(|e| {
+                *e = embed;
+                e
+            });
+        if use_components {
+            e.0.insert("components", components(reactions));
+        }
+        e
+    }).await?;
+    Ok(())
+}
+
 const ADDITIONAL_ALLOWED_CHARS: &[char] = &[' ', '-', '.'] as _;
 
 #[command]
+#[description("Starts a game in an RPG channel, naming the hero after you unless a name is given.")]
+#[usage("[name]")]
+#[example("Sir Lancelot")]
 #[only_in("guild")]
 #[cfg(feature = "rpg")]
 async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
@@ -151,29 +556,48 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         ).await?;
         return Ok(())
     }
-    let db: &Database = &data_lock.get::<DatabaseHandle>().ok_or("Database not present")?.rpg;
+    let storage: &dyn Storage = &**data_lock.get::<StorageKey>().ok_or("Storage not present")?;
+    let db = storage.rpg_documents();
 
     let initial = ohg_bot_rpg::initial(defined_name);
     let rpg_states = data_lock.get::<RPGState>().ok_or("No RPG states?")?;
 
     // Get the lock before the message, in case a reaction appears before the unyield.
     let rpg_states_lock = rpg_states.lock();
-    let display = initial.display(db);
+    let display = initial.display_in(db);
     let (mut rpg_states_lock, display): (MutexGuard<'_, RPGStateHolder>, _) =
         join!(rpg_states_lock, display);
     let (reactions, embed): (Reactions, CreateEmbed) = display?;
+    let use_components = rpg_states_lock.components;
+    if rpg_states_lock.closing {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .content("The bot is shutting down; try again once it is back.")
+        ).await?;
+        return Ok(());
+    }
 
-    let message = msg.channel_id.send_message(ctx, |message| message
-        .reference_message(msg)
-        .content(author_mention)
-        .embed(|e| {
-            *e = embed;
-            e
-        })
-    ).await?;
+    let message = msg.channel_id.send_message(ctx, |message| {
+        message
+            .reference_message(msg)
+            .content(author_mention)
+            .embed(|e| {
+                *e = embed;
+                e
+            });
+        if use_components {
+            message.0.insert("components", components(&reactions));
+        }
+        message
+    }).await?;
 
-    let reactions =
-        pre_fill_reactions(ctx, reactions, msg.channel_id, message.id);
+    let reactions = async {
+        if use_components {
+            Ok(())
+        } else {
+            pre_fill_reactions(ctx, reactions, msg.channel_id, message.id).await
+        }
+    };
 
     let mut state = RPGState {
         id: None,
@@ -184,7 +608,7 @@ async fn play(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
         iteration: 0,
         previous: None,
     };
-    let save_state = state.save(db, None);
+    let save_state = storage.save_rpg_state(&mut state);
 
     let (reactions, save_state) = join!(reactions, save_state);
     save_state?;
@@ -223,14 +647,59 @@ async fn pre_fill_reactions(ctx: &Context, reactions: Reactions, channel: Channe
     Ok(())
 }
 
+/// Discord allows at most this many buttons in an action row.
+const BUTTON_LIMIT: usize = 5;
+const LABEL_LIMIT: usize = 80;
+const SELECT_ID: &str = "rpg";
+
+/// Buttons for a handful of options, otherwise a select menu; either identifies the option by emoji.
+fn components(reactions: &Reactions) -> Value {
+    fn label(description: &str) -> String {
+        description.chars().take(LABEL_LIMIT).collect()
+    }
+
+    if reactions.is_empty() {
+        return json!([]);
+    }
+    let component = if reactions.len() <= BUTTON_LIMIT {
+        reactions
+            .iter()
+            .map(|reaction| json!({
+                "type": 2,
+                "style": 2,
+                "custom_id": reaction.emoji,
+                "label": label(reaction.description),
+                "emoji": { "name": reaction.emoji },
+            }))
+            .collect::<Vec<Value>>()
+    } else {
+        let options: Vec<Value> = reactions
+            .iter()
+            .map(|reaction| json!({
+                "label": label(reaction.description),
+                "value": reaction.emoji,
+                "emoji": { "name": reaction.emoji },
+            }))
+            .collect();
+        vec![json!({
+            "type": 3,
+            "custom_id": SELECT_ID,
+            "placeholder": "Choose an action",
+            "options": options,
+        })]
+    };
+    json!([{
+        "type": 1,
+        "components": component,
+    }])
+}
+
 pub async fn reaction_add(ctx: &Context, reaction: Reaction) -> CommandResult {
     let emoji = if let ReactionType::Unicode(emoji) = &reaction.emoji {
         emoji.as_str()
     } else {
         return Ok(());
     };
-    let channel = reaction.channel_id;
-    let message = reaction.message_id;
     let user = if let Some(user) = reaction.user_id {
         user
     } else {
@@ -239,6 +708,17 @@ pub async fn reaction_add(ctx: &Context, reaction: Reaction) -> CommandResult {
     if user == ctx.cache.current_user_id().await {
         return Ok(());
     }
+    action(ctx, reaction.channel_id, reaction.message_id, user, emoji).await
+}
+
+/// Applies an action, from either a reaction or a component, to the game on a message.
+pub async fn action(
+    ctx: &Context,
+    channel: ChannelId,
+    message: MessageId,
+    user: UserId,
+    emoji: &str,
+) -> CommandResult {
     let data_lock = ctx.data.read().await;
     if !data_lock
         .get::<RPGChannel>()
@@ -248,15 +728,17 @@ pub async fn reaction_add(ctx: &Context, reaction: Reaction) -> CommandResult {
         return Ok(());
     }
 
-    let db = &data_lock.get::<DatabaseHandle>().ok_or("No Database")?.rpg;
+    let storage: &dyn Storage = &**data_lock.get::<StorageKey>().ok_or("No Storage")?;
     let states_mutex = data_lock.get::<RPGState>().ok_or("No States")?;
+    let use_components = states_mutex.lock().await.components;
     let state =
         if let Some(state) =
             obtain_state(
-                db,
+                storage,
                 states_mutex,
                 message,
                 user,
+                data_lock.get::<Metrics>().map(|metrics| &**metrics),
             ).await?
         {
             state
@@ -264,7 +746,7 @@ pub async fn reaction_add(ctx: &Context, reaction: Reaction) -> CommandResult {
             return Ok(());
         };
 
-    let result = operate_on_state(ctx, db, emoji, state, channel, message, user).await;
+    let result = operate_on_state(ctx, storage, emoji, state, channel, message, user, use_components).await;
     match result {
         Ok(state) => {
             unlock(states_mutex, Some(state), message).await;
@@ -280,16 +762,22 @@ pub async fn reaction_add(ctx: &Context, reaction: Reaction) -> CommandResult {
 
 async fn operate_on_state(
     ctx: &Context,
-    db: &Database,
+    storage: &dyn Storage,
     emoji: &str,
     mut state: RPGState,
     channel: ChannelId,
     message: MessageId,
     user: UserId,
+    use_components: bool,
 ) -> CommandResult<Option<RPGState>> {
-    let old_reactions = state.state.reactions(db).await?;
+    let db = storage.rpg_documents();
+    let old_reactions = if use_components {
+        Reactions::new()
+    } else {
+        state.state.reactions_in(db).await?
+    };
     let change: bool;
-    state.state = match state.state.action(db, emoji).await {
+    state.state = match state.state.action_in(db, emoji).await {
         Ok(Action::NoChange(state)) => {
             change = false;
             state
@@ -323,16 +811,10 @@ async fn operate_on_state(
         )
     );
     let edit = async {
-        let (reactions, embed) = state.state.display(db).await?;
+        let (reactions, embed) = state.state.display_in(db).await?;
 
-        let save = state.save(db, None);
-        let edit = channel.edit_message(ctx, message, |e| e
-            .content(Mentionable::from(user))
-            .embed(|e| {
-                *e = embed;
-                e
-            })
-        );
+        let save = storage.save_rpg_state(&mut state);
+        let edit = edit_game(ctx, channel, message, user, embed, &reactions, use_components);
 
         let (save, edit) = join!(save, edit);
         save?;
@@ -345,7 +827,10 @@ async fn operate_on_state(
     for deletion in deletions {
         deletion?;
     }
-    pre_fill_reactions(ctx, edit?, channel, message).await?;
+    let reactions = edit?;
+    if !use_components {
+        pre_fill_reactions(ctx, reactions, channel, message).await?;
+    }
 
     Ok(Some(state))
 }
@@ -359,6 +844,7 @@ async fn unlock(
     let RPGStateHolder {
         cache,
         lockout,
+        ..
     } = &mut *states;
     if let Some(state) = state {
         drop(cache.insert(message, state));
@@ -369,20 +855,27 @@ async fn unlock(
 }
 
 async fn obtain_state(
-    db: &Database,
+    storage: &dyn Storage,
     mutex: &Mutex<RPGStateHolder>,
     message: MessageId,
     user: UserId,
+    metrics: Option<&Metrics>,
 ) -> CommandResult<Option<RPGState>> {
     let mut states = mutex.lock().await;
     let RPGStateHolder {
         cache,
         lockout,
+        closing,
+        ..
     } = &mut *states;
-    if lockout.contains(&message) {
+    if *closing || lockout.contains(&message) {
         return Ok(None);
     }
-    let entry = match cache.entry(message) {
+    let entry = cache.entry(message);
+    if let Some(metrics) = metrics {
+        metrics.rpg_cache(matches!(entry, Entry::Occupied(_)));
+    }
+    let entry = match entry {
         Entry::Occupied(mut occupied) => {
             let occupied = occupied.get_mut();
             let state = if let Some(state) = occupied.take() {
@@ -405,17 +898,7 @@ async fn obtain_state(
 
     // We need to check the database
 
-    let mut options = FindOneOptions::default();
-    options.sort = Some(doc!{
-        "iteration": -1,
-    });
-    let state: Option<RPGState> = RPGState::find_one(
-        db,
-        Some(doc!{
-            "message": doc!{ "$eq": &Shim::from(message) },
-        }),
-        Some(options),
-    ).await?;
+    let state: Option<RPGState> = storage.rpg_state(message, None).await?;
 
     let state = if let Some(state) = state {
         state
@@ -436,3 +919,126 @@ async fn obtain_state(
     lockout.insert(message);
     Ok(Some(state))
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use ohg_bot_headers::{
+        CharacterState,
+        Documents,
+        Error,
+    };
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+    use serenity::async_trait;
+
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    /// A game counting its moves.
+    #[derive(Debug, Serialize, Deserialize)]
+    struct Counter {
+        moves: i32,
+    }
+
+    #[typetag::serde]
+    #[async_trait]
+    impl CharacterState for Counter {
+        async fn action_in(self: Box<Self>, _documents: &dyn Documents, reaction: &str) -> Result<Action, Error> {
+            Ok(match reaction {
+                "+" => Action::Changed(Box::new(Counter { moves: self.moves + 1 })),
+                _ => Action::BadReact(self),
+            })
+        }
+
+        async fn reactions_in(&self, _documents: &dyn Documents) -> Result<Reactions, Error> {
+            Ok(Reactions::new())
+        }
+
+        async fn display_in(&self, documents: &dyn Documents) -> Result<(Reactions, CreateEmbed), Error> {
+            Ok((self.reactions_in(documents).await?, CreateEmbed::default()))
+        }
+    }
+
+    const MESSAGE: MessageId = MessageId(1);
+
+    /// Plays the moves on a new game, saving every iteration as a move does.
+    async fn play(storage: &MemoryStorage, moves: &[&str]) -> CommandResult<RPGState> {
+        let mut state = RPGState {
+            id: None,
+            state: Box::new(Counter { moves: 0 }),
+            active: true,
+            message: MESSAGE,
+            owner: UserId(2),
+            iteration: 0,
+            previous: None,
+        };
+        storage.save_rpg_state(&mut state).await?;
+        for reaction in moves {
+            let next = match state.state.action_in(storage.rpg_documents(), reaction).await? {
+                Action::Changed(next) => next,
+                unchanged => {
+                    state.state = unchanged.inner();
+                    continue;
+                },
+            };
+            state = RPGState {
+                id: None,
+                state: next,
+                active: true,
+                message: MESSAGE,
+                owner: state.owner,
+                iteration: state.iteration + 1,
+                previous: state.id,
+            };
+            storage.save_rpg_state(&mut state).await?;
+        }
+        Ok(state)
+    }
+
+    #[test]
+    fn states_are_found_by_message_and_iteration() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            play(&storage, &["+", "x", "+", "+"]).await?;
+
+            let latest = storage.rpg_state(MESSAGE, None).await?.ok_or("No latest state")?;
+            assert_eq!(latest.iteration, 3);
+            let first = storage.rpg_state(MESSAGE, Some(0)).await?.ok_or("No first state")?;
+            assert_eq!(first.iteration, 0);
+            assert!(storage.rpg_state(MESSAGE, Some(4)).await?.is_none());
+            assert!(storage.rpg_state(MessageId(3), None).await?.is_none());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn history_follows_the_previous_chain() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            play(&storage, &["+", "+"]).await?;
+
+            let chain = load_chain(&storage, MESSAGE).await?;
+            let iterations: Vec<i32> = chain.iter().map(|state| state.iteration).collect();
+            assert_eq!(iterations, vec![0, 1, 2]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn rewinds_reach_back_only_as_far_as_the_chain() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            let current = play(&storage, &["+", "+", "+"]).await?;
+
+            let snapshot = rewind_snapshot(&storage, &current, 2).await?.ok_or("No snapshot")?;
+            assert_eq!(snapshot.iteration, 1);
+            let snapshot = rewind_snapshot(&storage, &current, 3).await?.ok_or("No snapshot")?;
+            assert_eq!(snapshot.iteration, 0);
+            assert!(rewind_snapshot(&storage, &current, 4).await?.is_none());
+            Ok(())
+        })
+    }
+}
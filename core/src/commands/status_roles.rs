@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use futures::join;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    models::RoleStatus,
+    status::{
+        self,
+        ParsedRule,
+        RuleDisplay,
+    },
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::Mentionable,
+};
+
+/// Members listed per role in a preview before the rest are only counted.
+const PREVIEW_MEMBERS: usize = 10;
+
+#[group]
+#[description("Roles given and taken by member activity.")]
+#[prefixes("status_role")]
+#[commands(list, add, clear, preview)]
+pub struct StatusRoles;
+
+#[command]
+#[description("Lists the status roles of the server, and their rules.")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn list(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let storage = load_storage(ctx, msg).await?;
+    let statuses = status::guild_statuses(ctx, &*storage, guild).await?;
+
+    let mut fields = Vec::with_capacity(statuses.len());
+    for status in statuses.iter().take(25) {
+        let mut value = format!("{}\n", Mentionable::from(status.role));
+        if status.rules.is_empty() {
+            value.push_str("No rules");
+        }
+        for rule in &status.rules {
+            writeln!(&mut value, "• {}", RuleDisplay(rule))?;
+        }
+        let name = ctx.cache
+            .role(guild, status.role)
+            .await
+            .map_or_else(|| "Deleted role".to_string(), |role| role.name);
+        fields.push((name, value, true));
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("Status roles:");
+            if fields.is_empty() {
+                e.description("No status roles configured.");
+            }
+            e.fields(fields)
+        })
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Adds a rule giving the role to members: `groups <count>`, `first_message`, or `inactive <days>`.")]
+#[usage("<role> <rule>")]
+#[example("@Regular groups 3")]
+#[example("@Dormant inactive 30")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn add(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            Two or more parameters.\
+            \nThe first must be either a reference to the role, or the role ID.\
+            \nThe rest is the rule: `groups <count>`, `first_message`, or `inactive <days>`.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let role: RoleId = match args.single() {
+        Ok(role) => role,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let rule = match args.rest().parse() {
+        Ok(ParsedRule(rule)) => rule,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    if ctx.cache.role(guild, role).await.is_none() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let storage = load_storage(ctx, msg).await?;
+    let mut status = status::guild_statuses(ctx, &*storage, guild)
+        .await?
+        .into_iter()
+        .find(|status| status.role == role)
+        .unwrap_or(RoleStatus {
+            id: None,
+            role,
+            server: Some(guild),
+            rules: Vec::new(),
+        });
+    status.server = Some(guild);
+    let added = !status.rules.contains(&rule);
+    if added {
+        status.rules.push(rule.clone());
+        storage.save_role_status(&mut status).await?;
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Status roles:")
+            .description(format_args!(
+                "{} will now {}{}",
+                Mentionable::from(role),
+                RuleDisplay(&rule),
+                if added { "" } else { " (unchanged)" },
+            ))
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Removes every rule of the role.")]
+#[usage("<role>")]
+#[example("@Regular")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn clear(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One parameter.\
+            \nIt must be either a reference to the role, or the role ID.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let role: RoleId = match args.single() {
+        Ok(role) => role,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let storage = load_storage(ctx, msg).await?;
+    let mut cleared = false;
+    for mut status in status::guild_statuses(ctx, &*storage, guild).await? {
+        if status.role == role && !status.rules.is_empty() {
+            status.rules.clear();
+            storage.save_role_status(&mut status).await?;
+            cleared = true;
+        }
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("Status roles:")
+            .description(if cleared {
+                format!("Cleared the rules of {}", Mentionable::from(role))
+            } else {
+                format!("{} has no rules", Mentionable::from(role))
+            })
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Shows the changes the rules would make now, for every status role or only the one given.")]
+#[usage("[role]")]
+#[example("@Regular")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn preview(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let role: Option<RoleId> = if args.is_empty() {
+        None
+    } else {
+        match args.single() {
+            Ok(role) => Some(role),
+            Err(_) => {
+                msg.reply(ctx, "The only parameter must be either a reference to the role, or the role ID.").await?;
+                return Ok(());
+            },
+        }
+    };
+
+    let storage = load_storage(ctx, msg).await?;
+    let members: Vec<Member> = ctx.cache
+        .guild(guild)
+        .await
+        .ok_or("Guild not cached")?
+        .members
+        .values()
+        .cloned()
+        .collect();
+    let activity = status::guild_activity(&*storage, guild).await?;
+    let changes = status::plan(ctx, &*storage, guild, &members, &activity).await?;
+
+    let mut by_role: HashMap<RoleId, Vec<status::Change>> = HashMap::new();
+    for change in changes {
+        if role.map_or(true, |role| role == change.role) {
+            by_role.entry(change.role).or_default().push(change);
+        }
+    }
+    let mut fields = Vec::with_capacity(by_role.len());
+    for (role, changes) in by_role.iter().take(25) {
+        let mut value = String::new();
+        for change in changes.iter().take(PREVIEW_MEMBERS) {
+            writeln!(&mut value, "{}", change)?;
+        }
+        if changes.len() > PREVIEW_MEMBERS {
+            writeln!(&mut value, "…and {} more", changes.len() - PREVIEW_MEMBERS)?;
+        }
+        let name = ctx.cache
+            .role(guild, *role)
+            .await
+            .map_or_else(|| "Deleted role".to_string(), |role| role.name);
+        fields.push((name, value, false));
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("Status role preview:");
+            if fields.is_empty() {
+                e.description("The rules call for no changes.");
+            } else {
+                e.description("Changes the next sweep would make:");
+            }
+            e.fields(fields)
+        })
+    ).await?;
+
+    Ok(())
+}
+
+async fn load_storage(ctx: &Context, msg: &Message) -> CommandResult<Arc<dyn Storage>> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    Ok(data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone())
+}
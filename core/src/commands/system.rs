@@ -0,0 +1,297 @@
+use std::{
+    borrow::Cow,
+    fmt::Write as _,
+    sync::Arc,
+};
+
+use futures::join;
+use serenity::{
+    prelude::*,
+    model::prelude::*,
+    framework::standard::{
+        Args,
+        CommandResult,
+        macros::{
+            command,
+            group,
+        },
+    },
+};
+use crate::{
+    models::System,
+    storage::StorageKey,
+    supervisor::{
+        LOG_LINES,
+        State,
+        Status,
+        Supervisor,
+    },
+    util::DurationDisplay,
+};
+
+/// Systems shown by `status`, as many fields as an embed holds; the rest are only counted.
+const STATUS_LISTED: usize = 25;
+
+#[group]
+#[description("The sub-systems run for the server.")]
+#[prefixes("system")]
+#[commands(start, stop, status, logs)]
+pub struct Systems;
+
+#[command]
+#[description("Starts a sub-system of the server.")]
+#[usage("<system>")]
+#[example("minecraft")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn start(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (guild, name) = match parse_name(msg, &args) {
+        Some(parsed) => parsed,
+        None => return bad_message(ctx, msg).await,
+    };
+    let (supervisor, system) = load_system(ctx, msg, guild, name).await?;
+    let system = if let Some(system) = system {
+        system
+    } else {
+        return send_message_no_system(ctx, msg, name).await;
+    };
+
+    let started = supervisor.start(guild, &system.sub_system).await?;
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("System:")
+            .description(if started {
+                format!("Started `{}`.", name)
+            } else {
+                format!("`{}` is already running.", name)
+            })
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Stops a sub-system of the server.")]
+#[usage("<system>")]
+#[example("minecraft")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn stop(ctx: &Context, msg: &Message, args: Args) -> CommandResult {
+    let (guild, name) = match parse_name(msg, &args) {
+        Some(parsed) => parsed,
+        None => return bad_message(ctx, msg).await,
+    };
+    let (supervisor, system) = load_system(ctx, msg, guild, name).await?;
+    let system = if let Some(system) = system {
+        system
+    } else {
+        return send_message_no_system(ctx, msg, name).await;
+    };
+
+    let stopped = supervisor.stop(guild, &system.sub_system).await?;
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("System:")
+            .description(if stopped {
+                format!("Stopped `{}`.", name)
+            } else {
+                format!("`{}` is not running.", name)
+            })
+        )
+    ).await?;
+
+    Ok(())
+}
+
+#[command]
+#[description("Shows whether each sub-system of the server is running.")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn status(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    let db = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    let supervisor = data
+        .get::<Supervisor>()
+        .ok_or("Supervisor not present")?
+        .clone();
+    drop(data);
+    let systems = db.systems(Some(guild)).await?;
+
+    let mut fields = Vec::with_capacity(systems.len());
+    for system in systems.iter().take(STATUS_LISTED) {
+        let name = &system.sub_system.name;
+        let status = supervisor.status(guild, name).await;
+        fields.push((name, describe_status(status.as_ref())?, true));
+    }
+
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| {
+            e.title("System status:");
+            if fields.is_empty() {
+                e.description("No sub-systems configured.");
+            } else if systems.len() > STATUS_LISTED {
+                e.description(format_args!("…and {} more", systems.len() - STATUS_LISTED));
+            }
+            e.fields(fields)
+        })
+    ).await?;
+
+    Ok(())
+}
+
+fn describe_status(status: Option<&Status>) -> Result<String, std::fmt::Error> {
+    let status = if let Some(status) = status {
+        status
+    } else {
+        return Ok("Never started".to_string());
+    };
+    let mut value = String::new();
+    writeln!(&mut value, "State: {:?}", status.state)?;
+    if let Some(pid) = status.pid {
+        writeln!(&mut value, "PID: {}", pid)?;
+    }
+    if status.state == State::Running {
+        writeln!(&mut value, "Uptime: {}", DurationDisplay(status.since.elapsed()))?;
+    } else {
+        writeln!(&mut value, "Since: {} ago", DurationDisplay(status.since.elapsed()))?;
+    }
+    if let Some(exit) = status.last_exit {
+        writeln!(&mut value, "Last exit: {}", exit)?;
+    }
+    Ok(value)
+}
+
+const DEFAULT_LOG_LINES: usize = 20;
+const INLINE_LOG_LENGTH: usize = 1900;
+
+#[command]
+#[description("Shows the latest log lines of a sub-system.")]
+#[usage("<system> [lines]")]
+#[example("minecraft 20")]
+#[only_in("guild")]
+#[required_permissions("ADMINISTRATOR")]
+async fn logs(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+        const CONTENT: &str = "\
+            One or two parameters.\
+            \nThe first must be the sub-system name.\
+            \nThe second is how many lines to show.\
+        ";
+        msg.reply(ctx, CONTENT).await?;
+        Ok(())
+    }
+
+    let guild = msg.guild_id.ok_or("No guild present")?;
+    let name: String = match args.single() {
+        Ok(name) => name,
+        Err(_) => return bad_message(ctx, msg).await,
+    };
+    let count: usize = if args.is_empty() {
+        DEFAULT_LOG_LINES
+    } else {
+        match args.single() {
+            Ok(count) => count,
+            Err(_) => return bad_message(ctx, msg).await,
+        }
+    };
+    if !args.is_empty() {
+        return bad_message(ctx, msg).await;
+    }
+
+    let (supervisor, system) = load_system(ctx, msg, guild, &name).await?;
+    if system.is_none() {
+        return send_message_no_system(ctx, msg, &name).await;
+    }
+    let lines = supervisor
+        .logs(guild, &name, count.min(LOG_LINES))
+        .await
+        .unwrap_or_default();
+    if lines.is_empty() {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .embed(|e| e
+                .title("System logs:")
+                .description(format_args!("No output captured for `{}`.", name))
+            )
+        ).await?;
+        return Ok(());
+    }
+
+    let text = lines.join("\n");
+    if text.len() <= INLINE_LOG_LENGTH {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .embed(|e| e
+                .title(format_args!("System logs: {}", name))
+                .description(format_args!("```\n{}\n```", text.replace("```", "`\u{200B}``")))
+            )
+        ).await?;
+    } else {
+        msg.channel_id.send_message(ctx, |message| message
+            .reference_message(msg)
+            .content(format_args!("Last {} lines of `{}`:", lines.len(), name))
+            .add_file(AttachmentType::Bytes {
+                data: Cow::Owned(text.into_bytes()),
+                filename: format!("{}.log", name),
+            })
+        ).await?;
+    }
+
+    Ok(())
+}
+
+fn parse_name<'a>(msg: &Message, args: &'a Args) -> Option<(GuildId, &'a str)> {
+    if args.len() != 1 {
+        return None;
+    }
+    Some((msg.guild_id?, args.rest()))
+}
+
+async fn bad_message(ctx: &Context, msg: &Message) -> CommandResult {
+    const CONTENT: &str = "\
+        Specify exactly one sub-system name.\
+    ";
+    msg.reply(ctx, CONTENT).await?;
+    Ok(())
+}
+
+async fn send_message_no_system(ctx: &Context, msg: &Message, name: &str) -> CommandResult {
+    msg.channel_id.send_message(ctx, |message| message
+        .reference_message(msg)
+        .embed(|e| e
+            .title("System:")
+            .description(format_args!("No sub-system named `{}`.", name))
+        )
+    ).await?;
+    Ok(())
+}
+
+async fn load_system(ctx: &Context, msg: &Message, guild: GuildId, name: &str) -> CommandResult<(Arc<Supervisor>, Option<System>)> {
+    let typing = msg.channel_id.broadcast_typing(ctx);
+    let data = ctx.data.read();
+    let (typing, data) = join!(typing, data);
+    typing?;
+    let db = data
+        .get::<StorageKey>()
+        .ok_or("Storage not present")?
+        .clone();
+    let supervisor = data
+        .get::<Supervisor>()
+        .ok_or("Supervisor not present")?
+        .clone();
+    drop(data);
+
+    let system = db.system(guild, name).await?;
+    Ok((supervisor, system))
+}
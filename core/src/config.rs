@@ -0,0 +1,506 @@
+use std::{
+    env,
+    fmt::Display,
+    fs::read_to_string,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::{
+        Path,
+        PathBuf,
+    },
+    str::FromStr,
+};
+
+use serde::Deserialize;
+use serenity::model::prelude::*;
+
+use crate::{
+    models::DiscordCredentials,
+    DATABASE_NAME,
+    RPG_DATABASE_NAME,
+};
+
+/// Read when `OHG_CONFIG` does not name another file.
+pub const DEFAULT_CONFIG_PATH: &str = "./ohg.toml";
+/// Where the database url was read from before the config file.
+const LEGACY_DATABASE_URL_PATH: &str = "./db-url.txt";
+pub const DEFAULT_RPG_CACHE_SIZE: usize = 128;
+pub const DEFAULT_LOG_FILTER: &str = "info";
+
+pub struct Config {
+    pub database: DatabaseConfig,
+    pub credentials: DiscordCredentials,
+    pub rpg_cache_size: usize,
+    pub features: Features,
+    pub logging: LoggingConfig,
+    /// Where `/metrics` is served; nothing is measured when absent.
+    pub metrics_address: Option<SocketAddr>,
+}
+
+pub struct DatabaseConfig {
+    pub url: String,
+    pub name: String,
+    pub rpg_name: String,
+}
+
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    /// Logged to stdout when absent.
+    pub file: Option<PathBuf>,
+    pub rotation: LogRotation,
+    /// Which spans and events are kept, in `RUST_LOG` syntax.
+    pub filter: String,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum LogFormat {
+    Human,
+    Json,
+}
+
+/// How often the log file is replaced by a new one, named with the date.
+#[derive(Copy, Clone, Debug)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+    Never,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Features {
+    pub rpg: bool,
+    pub systems: bool,
+    pub status_roles: bool,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Layer {
+    database: DatabaseLayer,
+    discord: DiscordLayer,
+    rpg: RPGLayer,
+    features: FeaturesLayer,
+    logging: LoggingLayer,
+    metrics: MetricsLayer,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DatabaseLayer {
+    url: Option<String>,
+    name: Option<String>,
+    rpg_name: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct DiscordLayer {
+    token: Option<String>,
+    prefix: Option<String>,
+    operator: Option<u64>,
+    bot_id: Option<String>,
+    public: Option<String>,
+    private: Option<String>,
+    interactions_address: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct RPGLayer {
+    cache_size: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct LoggingLayer {
+    format: Option<String>,
+    file: Option<String>,
+    rotation: Option<String>,
+    filter: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct MetricsLayer {
+    address: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct FeaturesLayer {
+    rpg: Option<bool>,
+    systems: Option<bool>,
+    status_roles: Option<bool>,
+}
+
+impl Config {
+    /// Reads the config file, applies environment overrides, and validates the result.
+    ///
+    /// Every problem found is returned, rather than only the first.
+    pub fn load() -> Result<Config, Vec<String>> {
+        let (layer, errors) = layered();
+        Config::validate(layer, errors)
+    }
+
+    /// Like `load`, but with `contents` in place of the config file.
+    pub fn parse(contents: &str) -> Result<Config, Vec<String>> {
+        let mut errors = Vec::new();
+        let layer = parse_layer("config", contents, &mut errors);
+        Config::validate(overridden(layer, &mut errors), errors)
+    }
+
+    fn validate(layer: Layer, mut errors: Vec<String>) -> Result<Config, Vec<String>> {
+        let database = layer.database.validate(&mut errors);
+        let credentials = layer.discord.validate(&mut errors);
+        let logging = layer.logging.validate(&mut errors);
+        let metrics_address = layer.metrics.address.and_then(|address| match address.parse::<SocketAddr>() {
+            Ok(address) => Some(address),
+            Err(e) => {
+                errors.push(format!("metrics.address {:?}: {}", address, e));
+                None
+            },
+        });
+
+        let rpg_cache_size = layer.rpg.cache_size.unwrap_or(DEFAULT_RPG_CACHE_SIZE);
+        if rpg_cache_size == 0 {
+            errors.push("rpg.cache_size must be at least 1".into());
+        }
+        let features = Features {
+            rpg: layer.features.rpg.unwrap_or(cfg!(feature = "rpg")),
+            systems: layer.features.systems.unwrap_or(true),
+            status_roles: layer.features.status_roles.unwrap_or(true),
+        };
+        if features.rpg && !cfg!(feature = "rpg") {
+            errors.push("features.rpg is enabled, but this build does not include the rpg feature".into());
+        }
+
+        match (database, credentials) {
+            (Some(database), Some(credentials)) if errors.is_empty() => Ok(Config {
+                database,
+                credentials,
+                rpg_cache_size,
+                features,
+                logging,
+                metrics_address,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+impl DatabaseConfig {
+    /// Like `Config::load`, for tools that only need the database.
+    pub fn load() -> Result<DatabaseConfig, Vec<String>> {
+        let (layer, mut errors) = layered();
+        match layer.database.validate(&mut errors) {
+            Some(database) if errors.is_empty() => Ok(database),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Prints every configuration problem and exits.
+pub fn report(errors: Vec<String>) -> ! {
+    eprintln!("Invalid configuration:");
+    for error in errors {
+        eprintln!("  {}", error);
+    }
+    std::process::exit(1)
+}
+
+/// The config file in use: `OHG_CONFIG`, or `DEFAULT_CONFIG_PATH`.
+pub fn path() -> String {
+    env::var("OHG_CONFIG").unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+}
+
+fn layered() -> (Layer, Vec<String>) {
+    let mut errors = Vec::new();
+    let path = path();
+    let required = env::var_os("OHG_CONFIG").is_some();
+    let layer = match read_to_string(&path) {
+        Ok(contents) => parse_layer(&path, &contents, &mut errors),
+        Err(e) if e.kind() == ErrorKind::NotFound && !required => Layer::default(),
+        Err(e) => {
+            errors.push(format!("{}: {}", path, e));
+            Layer::default()
+        },
+    };
+    let layer = overridden(layer, &mut errors);
+    (layer, errors)
+}
+
+fn parse_layer(source: &str, contents: &str, errors: &mut Vec<String>) -> Layer {
+    toml::from_str(contents).unwrap_or_else(|e| {
+        errors.push(format!("{}: {}", source, e));
+        Layer::default()
+    })
+}
+
+fn overridden(mut layer: Layer, errors: &mut Vec<String>) -> Layer {
+    env_override(&mut layer.database.url, "OHG_DATABASE_URL", errors);
+    env_override(&mut layer.database.name, "DATABASE_NAME", errors);
+    env_override(&mut layer.database.rpg_name, "RPG_DATABASE_NAME", errors);
+    env_override(&mut layer.discord.token, "OHG_TOKEN", errors);
+    env_override(&mut layer.discord.prefix, "OHG_PREFIX", errors);
+    env_override(&mut layer.discord.operator, "OHG_OPERATOR", errors);
+    env_override(&mut layer.discord.bot_id, "OHG_BOT_ID", errors);
+    env_override(&mut layer.discord.public, "OHG_PUBLIC_KEY", errors);
+    env_override(&mut layer.discord.private, "OHG_PRIVATE_KEY", errors);
+    env_override(&mut layer.discord.interactions_address, "OHG_INTERACTIONS_ADDRESS", errors);
+    env_override(&mut layer.rpg.cache_size, "OHG_RPG_CACHE_SIZE", errors);
+    env_override(&mut layer.features.rpg, "OHG_FEATURE_RPG", errors);
+    env_override(&mut layer.features.systems, "OHG_FEATURE_SYSTEMS", errors);
+    env_override(&mut layer.features.status_roles, "OHG_FEATURE_STATUS_ROLES", errors);
+    env_override(&mut layer.logging.format, "OHG_LOG_FORMAT", errors);
+    env_override(&mut layer.logging.file, "OHG_LOG_FILE", errors);
+    env_override(&mut layer.logging.rotation, "OHG_LOG_ROTATION", errors);
+    env_override(&mut layer.logging.filter, "OHG_LOG", errors);
+    env_override(&mut layer.metrics.address, "OHG_METRICS_ADDRESS", errors);
+    layer
+}
+
+fn env_override<T>(value: &mut Option<T>, name: &str, errors: &mut Vec<String>)
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match env::var(name) {
+        Ok(raw) => match raw.parse() {
+            Ok(parsed) => *value = Some(parsed),
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        },
+        Err(env::VarError::NotPresent) => {},
+        Err(e) => errors.push(format!("{}: {}", name, e)),
+    }
+}
+
+fn required<T>(value: Option<T>, name: &str, errors: &mut Vec<String>) -> Option<T> {
+    if value.is_none() {
+        errors.push(format!("{} is required", name));
+    }
+    value
+}
+
+fn check_database_name(name: &str, key: &str, errors: &mut Vec<String>) {
+    if name.is_empty() {
+        errors.push(format!("{} must not be empty", key));
+    } else if name.contains(&['/', '\\', '.', ' ', '"', '$'][..]) {
+        errors.push(format!("{} contains a character MongoDB does not allow: {:?}", key, name));
+    }
+}
+
+impl DatabaseLayer {
+    fn validate(self, errors: &mut Vec<String>) -> Option<DatabaseConfig> {
+        let url = required(self.url.filter(|url| !url.is_empty()), "database.url", errors);
+        if url.is_none() && Path::new(LEGACY_DATABASE_URL_PATH).exists() {
+            errors.push(format!(
+                "{} is no longer read; move its url to database.url in {} or OHG_DATABASE_URL",
+                LEGACY_DATABASE_URL_PATH,
+                path(),
+            ));
+        }
+        let name = self.name.unwrap_or_else(|| DATABASE_NAME.into());
+        check_database_name(&name, "database.name", errors);
+        let rpg_name = self.rpg_name.unwrap_or_else(|| RPG_DATABASE_NAME.into());
+        check_database_name(&rpg_name, "database.rpg_name", errors);
+        if cfg!(feature = "rpg") && name == rpg_name {
+            errors.push("database.name and database.rpg_name must differ".into());
+        }
+
+        Some(DatabaseConfig {
+            url: url?,
+            name,
+            rpg_name,
+        })
+    }
+}
+
+impl DiscordLayer {
+    fn validate(self, errors: &mut Vec<String>) -> Option<DiscordCredentials> {
+        let token = required(self.token.filter(|token| !token.is_empty()), "discord.token", errors);
+        let prefix = required(self.prefix, "discord.prefix", errors);
+        if let Some(prefix) = &prefix {
+            if prefix.is_empty() || prefix.contains(char::is_whitespace) {
+                errors.push(format!("discord.prefix must be non-empty without whitespace: {:?}", prefix));
+            }
+        }
+        let operator = required(self.operator, "discord.operator", errors);
+        if operator == Some(0) {
+            errors.push("discord.operator must be a user id".into());
+        }
+
+        let bot_id = self.bot_id.unwrap_or_default();
+        let public = self.public.unwrap_or_default();
+        if let Some(address) = &self.interactions_address {
+            if let Err(e) = address.parse::<SocketAddr>() {
+                errors.push(format!("discord.interactions_address {:?}: {}", address, e));
+            }
+            if bot_id.is_empty() {
+                errors.push("discord.bot_id is required when discord.interactions_address is set".into());
+            }
+            if hex::decode(&public).map_or(true, |key| key.len() != 32) {
+                errors.push("discord.public must be a 32 byte hex key when discord.interactions_address is set".into());
+            }
+        }
+
+        Some(DiscordCredentials {
+            id: None,
+            private: self.private.unwrap_or_default(),
+            public,
+            token: token?,
+            bot_id,
+            prefix: prefix?,
+            operator: UserId(operator?),
+            interactions_address: self.interactions_address,
+        })
+    }
+}
+
+impl LoggingLayer {
+    fn validate(self, errors: &mut Vec<String>) -> LoggingConfig {
+        let format = match self.format.as_deref() {
+            None | Some("human") => LogFormat::Human,
+            Some("json") => LogFormat::Json,
+            Some(other) => {
+                errors.push(format!("logging.format must be \"human\" or \"json\": {:?}", other));
+                LogFormat::Human
+            },
+        };
+        let rotation = match self.rotation.as_deref() {
+            Some("hourly") => LogRotation::Hourly,
+            None | Some("daily") => LogRotation::Daily,
+            Some("never") => LogRotation::Never,
+            Some(other) => {
+                errors.push(format!("logging.rotation must be \"hourly\", \"daily\", or \"never\": {:?}", other));
+                LogRotation::Daily
+            },
+        };
+        let file = self.file.filter(|file| !file.is_empty()).map(PathBuf::from);
+        if let Some(file) = &file {
+            if file.file_name().is_none() {
+                errors.push(format!("logging.file must name a file: {:?}", file));
+            }
+        }
+        let filter = self.filter.unwrap_or_else(|| DEFAULT_LOG_FILTER.into());
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&filter) {
+            errors.push(format!("logging.filter {:?}: {}", filter, e));
+        }
+
+        LoggingConfig {
+            format,
+            file,
+            rotation,
+            filter,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINIMAL: &str = r#"
+        [database]
+        url = "mongodb://localhost"
+
+        [discord]
+        token = "token"
+        prefix = "!"
+        operator = 1
+    "#;
+
+    /// Validates the contents alone, without environment overrides.
+    fn validate(contents: &str) -> Result<Config, Vec<String>> {
+        let mut errors = Vec::new();
+        let layer = parse_layer("config", contents, &mut errors);
+        Config::validate(layer, errors)
+    }
+
+    #[test]
+    fn minimal_configs_fall_back_to_defaults() -> Result<(), Vec<String>> {
+        let config = validate(MINIMAL)?;
+        assert_eq!(config.database.name, DATABASE_NAME);
+        assert_eq!(config.database.rpg_name, RPG_DATABASE_NAME);
+        assert_eq!(config.credentials.prefix, "!");
+        assert_eq!(config.credentials.operator, UserId(1));
+        assert_eq!(config.rpg_cache_size, DEFAULT_RPG_CACHE_SIZE);
+        assert!(config.features.systems && config.features.status_roles);
+        assert!(config.metrics_address.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn every_problem_is_reported_at_once() {
+        let errors = validate(r#"
+            [database]
+            name = "a.b"
+
+            [discord]
+            prefix = "two words"
+            operator = 0
+            interactions_address = "nowhere"
+
+            [rpg]
+            cache_size = 0
+        "#).err().unwrap_or_default();
+        for expected in &[
+            "database.url is required",
+            "database.name contains",
+            "discord.token is required",
+            "discord.prefix must",
+            "discord.operator must",
+            "discord.interactions_address",
+            "discord.bot_id is required",
+            "discord.public must",
+            "rpg.cache_size must",
+        ] {
+            assert!(errors.iter().any(|error| error.starts_with(expected)), "{} in {:?}", expected, errors);
+        }
+    }
+
+    #[test]
+    fn logging_defaults_to_human_daily_and_info() {
+        let mut errors = Vec::new();
+        let logging = LoggingLayer::default().validate(&mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(matches!(logging.format, LogFormat::Human));
+        assert!(matches!(logging.rotation, LogRotation::Daily));
+        assert!(logging.file.is_none());
+        assert_eq!(logging.filter, DEFAULT_LOG_FILTER);
+    }
+
+    #[test]
+    fn logging_accepts_its_options() {
+        let mut errors = Vec::new();
+        let logging = LoggingLayer {
+            format: Some("json".into()),
+            file: Some("logs/ohg.log".into()),
+            rotation: Some("never".into()),
+            filter: Some("ohg_bot_core=debug,warn".into()),
+        }.validate(&mut errors);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert!(matches!(logging.format, LogFormat::Json));
+        assert!(matches!(logging.rotation, LogRotation::Never));
+        assert_eq!(logging.file, Some(PathBuf::from("logs/ohg.log")));
+    }
+
+    #[test]
+    fn logging_rejects_unknown_options() {
+        let mut errors = Vec::new();
+        LoggingLayer {
+            format: Some("xml".into()),
+            file: Some("logs/..".into()),
+            rotation: Some("weekly".into()),
+            filter: Some("ohg_bot_core=loud".into()),
+        }.validate(&mut errors);
+        for expected in &["logging.format", "logging.file", "logging.rotation", "logging.filter"] {
+            assert!(errors.iter().any(|error| error.starts_with(expected)), "{} in {:?}", expected, errors);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let errors = validate(&format!("{}\n[extra]\nkey = 1\n", MINIMAL)).err().unwrap_or_default();
+        assert!(errors.iter().any(|error| error.starts_with("config:")), "{:?}", errors);
+    }
+}
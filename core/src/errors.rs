@@ -0,0 +1,335 @@
+use std::{
+    collections::{
+        hash_map::DefaultHasher,
+        VecDeque,
+    },
+    fmt::Write as _,
+    hash::{
+        Hash,
+        Hasher,
+    },
+    sync::{
+        atomic::{
+            AtomicBool,
+            AtomicU64,
+            Ordering,
+        },
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+use tokio::time::delay_for;
+
+use crate::status::now;
+
+/// How many errors are kept for `errors`, oldest dropped first.
+pub const RECENT_ERRORS: usize = 100;
+/// The operator is sent at most one summary this often; errors in between wait for the next.
+pub const ALERT_INTERVAL: Duration = Duration::from_secs(10 * 60);
+/// How often errors held back by the rate limit are checked for.
+const ALERT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+/// Distinct errors listed in a summary before the rest are only counted.
+const SUMMARY_LINES: usize = 10;
+/// Characters of an error message kept in a summary line.
+const SUMMARY_MESSAGE_LENGTH: usize = 200;
+
+/// An error of a command or event, as kept for the operator.
+#[derive(Clone, Debug)]
+pub struct ErrorRecord {
+    /// Short enough for users to report.
+    pub id: String,
+    pub at: i64,
+    /// The command or event that failed.
+    pub name: String,
+    pub channel: Option<ChannelId>,
+    pub user: Option<UserId>,
+    pub message: String,
+}
+
+/// Errors of one name and message not yet sent to the operator.
+struct Pending {
+    name: String,
+    message: String,
+    first_id: String,
+    count: usize,
+}
+
+#[derive(Default)]
+struct Reports {
+    recent: VecDeque<ErrorRecord>,
+    pending: Vec<Pending>,
+    last_alert: Option<Instant>,
+}
+
+/// Collects errors, and summarizes them for the operator.
+pub struct ErrorReporter {
+    operator: UserId,
+    /// Mixed into the IDs, so they differ between runs.
+    seed: i64,
+    sequence: AtomicU64,
+    /// Guards the alert loop so reconnects do not start another.
+    alerting: AtomicBool,
+    reports: Mutex<Reports>,
+}
+
+impl TypeMapKey for ErrorReporter {
+    type Value = Arc<ErrorReporter>;
+}
+
+impl ErrorReporter {
+    pub fn new(operator: UserId) -> Self {
+        ErrorReporter {
+            operator,
+            seed: now(),
+            sequence: AtomicU64::new(0),
+            alerting: AtomicBool::new(false),
+            reports: Mutex::new(Reports::default()),
+        }
+    }
+
+    /// The latest errors, newest first.
+    pub fn recent(&self, count: usize) -> Vec<ErrorRecord> {
+        self.lock()
+            .recent
+            .iter()
+            .rev()
+            .take(count)
+            .cloned()
+            .collect()
+    }
+
+    /// The kept error with the ID, ignoring case.
+    pub fn find(&self, id: &str) -> Option<ErrorRecord> {
+        self.lock()
+            .recent
+            .iter()
+            .find(|record| record.id.eq_ignore_ascii_case(id))
+            .cloned()
+    }
+
+    fn next_id(&self) -> String {
+        let mut hasher = DefaultHasher::new();
+        (self.seed, self.sequence.fetch_add(1, Ordering::Relaxed)).hash(&mut hasher);
+        format!("{:06x}", hasher.finish() & 0xFF_FFFF)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Reports> {
+        // A panic while holding the lock leaves nothing half-written worth refusing
+        self.reports.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn record(&self, mut record: ErrorRecord) -> String {
+        record.id = self.next_id();
+        let id = record.id.clone();
+        let mut reports = self.lock();
+        match reports
+            .pending
+            .iter_mut()
+            .find(|pending| pending.name == record.name && pending.message == record.message)
+        {
+            Some(pending) => pending.count += 1,
+            None => reports.pending.push(Pending {
+                name: record.name.clone(),
+                message: record.message.clone(),
+                first_id: id.clone(),
+                count: 1,
+            }),
+        }
+        if reports.recent.len() == RECENT_ERRORS {
+            reports.recent.pop_front();
+        }
+        reports.recent.push_back(record);
+        id
+    }
+
+    /// The summary of the pending errors, if one is due.
+    fn take_summary(&self) -> Option<String> {
+        let mut reports = self.lock();
+        if reports.pending.is_empty() {
+            return None;
+        }
+        let due = reports.last_alert.map_or(true, |last| last.elapsed() >= ALERT_INTERVAL);
+        if !due {
+            return None;
+        }
+        reports.last_alert = Some(Instant::now());
+        let pending = std::mem::take(&mut reports.pending);
+        drop(reports);
+
+        let total: usize = pending.iter().map(|pending| pending.count).sum();
+        let mut summary = format!("{} errors since the last summary:\n", total);
+        for pending in pending.iter().take(SUMMARY_LINES) {
+            let mut message: String = pending.message.chars().take(SUMMARY_MESSAGE_LENGTH).collect();
+            if message.len() < pending.message.len() {
+                message.push('…');
+            }
+            // Writing to a String cannot fail
+            let _ = write!(&mut summary, "\n`{}` **{}**", pending.first_id, pending.name);
+            if pending.count > 1 {
+                let _ = write!(&mut summary, " ×{}", pending.count);
+            }
+            let _ = write!(&mut summary, ": {}", message);
+        }
+        if pending.len() > SUMMARY_LINES {
+            let _ = write!(&mut summary, "\n\nAnd {} more kinds; see `errors`.", pending.len() - SUMMARY_LINES);
+        }
+        Some(summary)
+    }
+}
+
+/// Keeps the error of an event, if any, and alerts the operator.
+///
+/// Returns the ID given to the error.
+pub async fn report(ctx: &Context, name: &str, result: CommandResult) -> Option<String> {
+    report_impl(ctx, name, None, None, result).await
+}
+
+/// Keeps the error of a command, if any, and alerts the operator.
+///
+/// Returns the ID given to the error.
+pub async fn report_command(ctx: &Context, msg: &Message, name: &str, result: CommandResult) -> Option<String> {
+    report_impl(ctx, name, Some(msg.channel_id), Some(msg.author.id), result).await
+}
+
+async fn report_impl(
+    ctx: &Context,
+    name: &str,
+    channel: Option<ChannelId>,
+    user: Option<UserId>,
+    result: CommandResult,
+) -> Option<String> {
+    let error = result.err()?;
+    let message = error.to_string();
+    crate::print_errors_impl(name, Err(error));
+
+    let reporter = ctx.data.read().await.get::<ErrorReporter>()?.clone();
+    let id = reporter.record(ErrorRecord {
+        id: String::new(),
+        at: now(),
+        name: name.to_string(),
+        channel,
+        user,
+        message,
+    });
+    if let Some(summary) = reporter.take_summary() {
+        crate::print_errors_impl("Error_Alert", alert(ctx, reporter.operator, summary).await);
+    }
+    Some(id)
+}
+
+/// Starts sending the errors held back by the rate limit, once.
+pub async fn ready(ctx: &Context) -> CommandResult {
+    let reporter = ctx.data
+        .read()
+        .await
+        .get::<ErrorReporter>()
+        .ok_or("Error reporter not present")?
+        .clone();
+    if reporter.alerting.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            delay_for(ALERT_CHECK_INTERVAL).await;
+            if let Some(summary) = reporter.take_summary() {
+                crate::print_errors_impl("Error_Alert", alert(&ctx, reporter.operator, summary).await);
+            }
+        }
+    });
+    Ok(())
+}
+
+async fn alert(ctx: &Context, operator: UserId, summary: String) -> CommandResult {
+    operator
+        .create_dm_channel(ctx)
+        .await?
+        .send_message(ctx, |message| message
+            .embed(|e| e
+                .title("Errors:")
+                .description(summary)
+            )
+        ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(name: &str, message: &str) -> ErrorRecord {
+        ErrorRecord {
+            id: String::new(),
+            at: 0,
+            name: name.to_string(),
+            channel: None,
+            user: None,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn recorded_errors_are_found_by_id() {
+        let reporter = ErrorReporter::new(UserId(1));
+        let first = reporter.record(error("Ping", "first"));
+        let second = reporter.record(error("Ping", "second"));
+        assert_ne!(first, second);
+
+        let found = reporter.find(&first.to_uppercase()).expect("Not found by ID");
+        assert_eq!(found.message, "first");
+        let recent: Vec<String> = reporter.recent(5).into_iter().map(|record| record.message).collect();
+        assert_eq!(recent, vec!["second", "first"]);
+    }
+
+    #[test]
+    fn only_the_latest_errors_are_kept() {
+        let reporter = ErrorReporter::new(UserId(1));
+        let oldest = reporter.record(error("Ping", "0"));
+        for ix in 1..=RECENT_ERRORS {
+            reporter.record(error("Ping", &ix.to_string()));
+        }
+        assert!(reporter.find(&oldest).is_none());
+        assert_eq!(reporter.recent(RECENT_ERRORS + 1).len(), RECENT_ERRORS);
+    }
+
+    #[test]
+    fn summaries_group_repeats_and_wait_for_the_interval() {
+        let reporter = ErrorReporter::new(UserId(1));
+        assert!(reporter.take_summary().is_none());
+
+        let first = reporter.record(error("Ping", "failed"));
+        reporter.record(error("Ping", "failed"));
+        reporter.record(error("Join", "failed"));
+        let summary = reporter.take_summary().expect("No summary");
+        assert!(summary.starts_with("3 errors since the last summary:"));
+        assert!(summary.contains(&format!("`{}` **Ping** ×2: failed", first)));
+        assert!(summary.contains("**Join**: failed"));
+
+        // Held back until the interval passes, and still pending then
+        reporter.record(error("Ping", "again"));
+        assert!(reporter.take_summary().is_none());
+        reporter.lock().last_alert = Instant::now().checked_sub(ALERT_INTERVAL);
+        assert!(reporter.take_summary().expect("No summary").contains("again"));
+    }
+
+    #[test]
+    fn summaries_count_what_they_leave_out() {
+        let reporter = ErrorReporter::new(UserId(1));
+        for ix in 0..SUMMARY_LINES + 2 {
+            reporter.record(error("Ping", &ix.to_string()));
+        }
+        let summary = reporter.take_summary().expect("No summary");
+        assert!(summary.ends_with("And 2 more kinds; see `errors`."));
+    }
+}
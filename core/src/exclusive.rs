@@ -0,0 +1,167 @@
+use std::fmt::{
+    Display,
+    Formatter,
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+
+use crate::{
+    models::ExclusiveGroup,
+    storage::Storage,
+    util::Mentionable,
+};
+
+/// Why a member's roles were left unchanged.
+#[derive(Debug, Clone, Copy)]
+pub enum Refusal {
+    /// Both roles share an exclusive group, so only one may be joined.
+    Together(RoleId, RoleId),
+    /// The role is at or above the bot's highest role.
+    Hierarchy(RoleId),
+}
+
+/// Gives the member the roles, swapping out any they hold that share an exclusive group with them.
+///
+/// Swaps replace the member's roles in a single request, so the member never holds both.
+/// Returns the roles swapped out.
+pub async fn join(
+    ctx: &Context,
+    storage: &dyn Storage,
+    member: &Member,
+    roles: &[RoleId],
+) -> CommandResult<Result<Vec<RoleId>, Refusal>> {
+    let guild = member.guild_id;
+    let groups = storage.exclusive_groups(guild).await?;
+    if let Some(refusal) = together(&groups, roles) {
+        return Ok(Err(refusal));
+    }
+    let swapped = swapped(&groups, &member.roles, roles);
+
+    let cached = ctx.cache.guild(guild).await.ok_or("Guild not cached")?;
+    let bot = guild.member(ctx, ctx.cache.current_user_id().await).await?;
+    let position = |role: &RoleId| cached.roles.get(role).map(|role| role.position);
+    let highest = bot.roles
+        .iter()
+        .filter_map(position)
+        .max()
+        .unwrap_or(0);
+    if let Some(role) = roles
+        .iter()
+        .chain(&swapped)
+        .find(|role| position(role).map_or(false, |position| position >= highest))
+    {
+        return Ok(Err(Refusal::Hierarchy(*role)));
+    }
+
+    if swapped.is_empty() {
+        for role in roles {
+            ctx.http.add_member_role(guild.0, member.user.id.0, role.0).await?;
+        }
+    } else {
+        let mut kept: Vec<RoleId> = member.roles
+            .iter()
+            .copied()
+            .filter(|held| !swapped.contains(held))
+            .collect();
+        kept.extend(roles.iter().filter(|role| !member.roles.contains(role)));
+        guild.edit_member(ctx, member.user.id, |edit| edit.roles(&kept)).await?;
+    }
+    Ok(Ok(swapped))
+}
+
+/// The refusal of two of the roles that share an exclusive group, if any do.
+fn together(groups: &[ExclusiveGroup], roles: &[RoleId]) -> Option<Refusal> {
+    for group in groups {
+        let mut joined = roles.iter().filter(|role| group.roles.contains(role));
+        if let (Some(first), Some(second)) = (joined.next(), joined.next()) {
+            return Some(Refusal::Together(*first, *second));
+        }
+    }
+    None
+}
+
+/// The held roles sharing an exclusive group with any of the roles joined, which are swapped out for them.
+fn swapped(groups: &[ExclusiveGroup], held: &[RoleId], roles: &[RoleId]) -> Vec<RoleId> {
+    held
+        .iter()
+        .copied()
+        .filter(|held| !roles.contains(held))
+        .filter(|held| groups
+            .iter()
+            .any(|group| group.roles.contains(held) && roles.iter().any(|role| group.roles.contains(role)))
+        )
+        .collect()
+}
+
+impl Display for Refusal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Refusal::Together(first, second) => write!(
+                f,
+                "{} and {} are exclusive, so only one of them can be joined",
+                Mentionable::from(first),
+                Mentionable::from(second),
+            ),
+            Refusal::Hierarchy(role) => write!(
+                f,
+                "{} is not below the bot's highest role, so the bot cannot change it",
+                Mentionable::from(role),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: &str, roles: &[u64]) -> ExclusiveGroup {
+        ExclusiveGroup {
+            id: None,
+            server: GuildId(1),
+            name: name.to_string(),
+            roles: roles.iter().copied().map(RoleId).collect(),
+        }
+    }
+
+    fn roles(roles: &[u64]) -> Vec<RoleId> {
+        roles.iter().copied().map(RoleId).collect()
+    }
+
+    #[test]
+    fn roles_of_one_group_are_not_joined_together() {
+        let groups = [group("colors", &[1, 2, 3]), group("teams", &[4, 5])];
+        match together(&groups, &roles(&[1, 4, 3])) {
+            Some(Refusal::Together(first, second)) => assert_eq!((first, second), (RoleId(1), RoleId(3))),
+            refusal => panic!("Expected a refusal, got {:?}", refusal),
+        }
+        assert!(together(&groups, &roles(&[1, 4, 6])).is_none());
+        assert!(together(&[], &roles(&[1, 2])).is_none());
+    }
+
+    #[test]
+    fn joining_swaps_out_roles_of_the_same_group() {
+        let groups = [group("colors", &[1, 2, 3]), group("teams", &[4, 5])];
+        assert_eq!(swapped(&groups, &roles(&[2, 5, 7]), &roles(&[1])), roles(&[2]));
+        assert_eq!(swapped(&groups, &roles(&[2, 5, 7]), &roles(&[1, 4])), roles(&[2, 5]));
+        // Roles joined again, and roles of no group, are kept
+        assert!(swapped(&groups, &roles(&[1, 7]), &roles(&[1])).is_empty());
+        assert!(swapped(&groups, &roles(&[2]), &roles(&[7])).is_empty());
+    }
+
+    #[test]
+    fn refusals_name_their_roles() {
+        assert_eq!(
+            Refusal::Together(RoleId(1), RoleId(2)).to_string(),
+            "<@&1> and <@&2> are exclusive, so only one of them can be joined",
+        );
+        assert_eq!(
+            Refusal::Hierarchy(RoleId(3)).to_string(),
+            "<@&3> is not below the bot's highest role, so the bot cannot change it",
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    convert::TryFrom,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+    },
+    time::Duration,
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+use tokio::time::delay_for;
+
+use crate::{
+    models::RoleExpiry,
+    status::now,
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::DurationDisplay,
+};
+
+/// How often pending expiries are checked; removals may run this late.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+/// Seconds before expiry that members are warned, or half the duration if shorter.
+pub const WARNING_LEAD: i64 = 24 * 60 * 60;
+/// Seconds past expiry that a removal is retried, before the expiry is dropped as unreachable.
+pub const RETRY_LIMIT: i64 = 24 * 60 * 60;
+
+/// Guards the sweep so reconnects do not start another.
+#[derive(Default)]
+pub struct Expiries {
+    sweeping: AtomicBool,
+}
+
+impl TypeMapKey for Expiries {
+    type Value = Arc<Expiries>;
+}
+
+/// Removes the member's roles after the duration, replacing any expiry they had.
+///
+/// Returns the Unix seconds at which the roles expire.
+pub async fn schedule(
+    storage: &dyn Storage,
+    guild: GuildId,
+    user: UserId,
+    roles: &[RoleId],
+    duration: Duration,
+) -> CommandResult<i64> {
+    let seconds = i64::try_from(duration.as_secs()).map_err(|_| "Duration too long")?;
+    let expires_at = now().saturating_add(seconds);
+    let warn_at = expires_at - (seconds / 2).min(WARNING_LEAD);
+    for role in roles {
+        let mut expiry = storage
+            .role_expiry(guild, user, *role)
+            .await?
+            .unwrap_or(RoleExpiry {
+                id: None,
+                server: guild,
+                user,
+                role: *role,
+                expires_at,
+                warn_at,
+                warned: false,
+            });
+        expiry.expires_at = expires_at;
+        expiry.warn_at = warn_at;
+        expiry.warned = false;
+        storage.save_role_expiry(&mut expiry).await?;
+    }
+    Ok(expires_at)
+}
+
+/// Forgets the expiries of roles that were left, or joined again without a limit.
+pub async fn cancel(storage: &dyn Storage, guild: GuildId, user: UserId, roles: &[RoleId]) -> CommandResult {
+    for role in roles {
+        if let Some(expiry) = storage.role_expiry(guild, user, *role).await? {
+            storage.delete_role_expiry(&expiry).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Starts the periodic sweep of pending expiries, once.
+///
+/// Expiries are persisted, so any that came due while the bot was down are handled on the first sweep.
+pub async fn ready(ctx: &Context) -> CommandResult {
+    let (expiries, storage) = {
+        let data = ctx.data.read().await;
+        (
+            data.get::<Expiries>().ok_or("Expiries not present")?.clone(),
+            data.get::<StorageKey>().ok_or("Storage not present")?.clone(),
+        )
+    };
+    if expiries.sweeping.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            crate::errors::report(&ctx, "Expiry_Sweep", sweep(&ctx, &*storage).await).await;
+            delay_for(SWEEP_INTERVAL).await;
+        }
+    });
+    Ok(())
+}
+
+async fn sweep(ctx: &Context, storage: &dyn Storage) -> CommandResult {
+    let now = now();
+    for mut expiry in storage.due_role_expiries(now).await? {
+        if expiry.expires_at <= now {
+            crate::errors::report(ctx, "Expiry_Remove", expire(ctx, storage, &expiry, now).await).await;
+        } else if !expiry.warned && expiry.warn_at <= now {
+            crate::errors::report(ctx, "Expiry_Warn", warn(ctx, storage, &mut expiry, now).await).await;
+        }
+    }
+    Ok(())
+}
+
+async fn expire(ctx: &Context, storage: &dyn Storage, expiry: &RoleExpiry, now: i64) -> CommandResult {
+    let removal = ctx.http.remove_member_role(expiry.server.0, expiry.user.0, expiry.role.0).await;
+    // Kept for the next sweep unless the removal went through, or there is nothing left to remove
+    let gone = ctx.cache
+        .guild_field(expiry.server, |guild| !guild.roles.contains_key(&expiry.role) || !guild.members.contains_key(&expiry.user))
+        .await
+        .unwrap_or(false);
+    match removal {
+        Ok(()) => {},
+        Err(_) if gone => {},
+        // A server the bot left, or that is never cached again, is not retried forever
+        Err(why) if now - expiry.expires_at >= RETRY_LIMIT => {
+            storage.delete_role_expiry(expiry).await?;
+            return Err(format!("Gave up removing {} from {}: {}", expiry.role, expiry.user, why).into());
+        },
+        Err(why) => return Err(why.into()),
+    }
+    storage.delete_role_expiry(expiry).await
+}
+
+async fn warn(ctx: &Context, storage: &dyn Storage, expiry: &mut RoleExpiry, now: i64) -> CommandResult {
+    // Marked first, so members with closed DMs are not retried every sweep
+    expiry.warned = true;
+    storage.save_role_expiry(expiry).await?;
+
+    // Mentions do not resolve in DMs, so names are used instead
+    let role = ctx.cache
+        .role(expiry.server, expiry.role)
+        .await
+        .map_or_else(|| "a".to_string(), |role| format!("the **{}**", role.name));
+    let server = ctx.cache
+        .guild_field(expiry.server, |guild| guild.name.clone())
+        .await
+        .unwrap_or_else(|| "a server".to_string());
+    let remaining = Duration::from_secs((expiry.expires_at - now).max(0) as u64);
+    expiry.user
+        .create_dm_channel(ctx)
+        .await?
+        .send_message(ctx, |message| message
+            .embed(|e| e
+                .title("Role expiring:")
+                .description(format_args!(
+                    "You will lose {} role in {} in {}.\
+                    \nJoin it again for longer, or without a duration to keep it.",
+                    role,
+                    server,
+                    DurationDisplay(remaining),
+                ))
+            )
+        ).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use crate::storage::MemoryStorage;
+    use super::*;
+
+    const GUILD: GuildId = GuildId(1);
+    const USER: UserId = UserId(2);
+    const HOUR: u64 = 60 * 60;
+
+    #[test]
+    fn scheduling_again_replaces_the_expiry() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            let expires_at = schedule(&storage, GUILD, USER, &[RoleId(3), RoleId(4)], Duration::from_secs(HOUR)).await?;
+            let expiry = storage.role_expiry(GUILD, USER, RoleId(3)).await?.ok_or("Not scheduled")?;
+            // Short durations are warned halfway through
+            assert_eq!((expiry.expires_at, expiry.warn_at), (expires_at, expires_at - HOUR as i64 / 2));
+
+            let expires_at = schedule(&storage, GUILD, USER, &[RoleId(3)], Duration::from_secs(7 * 24 * HOUR)).await?;
+            let expiries = storage.role_expiries_of(GUILD, RoleId(3)).await?;
+            assert_eq!(expiries.len(), 1);
+            assert_eq!((expiries[0].expires_at, expiries[0].warn_at), (expires_at, expires_at - WARNING_LEAD));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cancelling_forgets_only_those_roles() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            schedule(&storage, GUILD, USER, &[RoleId(3), RoleId(4)], Duration::from_secs(HOUR)).await?;
+            cancel(&storage, GUILD, USER, &[RoleId(3), RoleId(5)]).await?;
+            assert!(storage.role_expiry(GUILD, USER, RoleId(3)).await?.is_none());
+            assert!(storage.role_expiry(GUILD, USER, RoleId(4)).await?.is_some());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn only_due_expiries_are_swept() -> CommandResult {
+        let storage = MemoryStorage::default();
+        let now = 1_600_000_000;
+        block_on(async {
+            for (role, expires_at, warn_at, warned) in vec![
+                (1, now - 1, now - 10, true),
+                (2, now + 10, now - 1, false),
+                (3, now + 10, now - 1, true),
+                (4, now + 10, now + 1, false),
+            ] {
+                storage.save_role_expiry(&mut RoleExpiry {
+                    id: None,
+                    server: GUILD,
+                    user: USER,
+                    role: RoleId(role),
+                    expires_at,
+                    warn_at,
+                    warned,
+                }).await?;
+            }
+            let mut due: Vec<RoleId> = storage
+                .due_role_expiries(now)
+                .await?
+                .into_iter()
+                .map(|expiry| expiry.role)
+                .collect();
+            due.sort();
+            assert_eq!(due, vec![RoleId(1), RoleId(2)]);
+            Ok(())
+        })
+    }
+}
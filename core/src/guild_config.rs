@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use serenity::{
+    framework::standard::{
+        macros::hook,
+        Command,
+        CommandGroup,
+        CommandResult,
+    },
+    model::prelude::*,
+    prelude::*,
+};
+
+use crate::{
+    models::{
+        DiscordCredentials,
+        GuildConfig,
+    },
+    storage::Storage,
+};
+
+/// The group managing the settings, which stays enabled so it can enable the others again.
+pub const CONFIG_GROUP_NAME: &str = "Config";
+
+/// The settings of the server from the client data, or the defaults if it has none.
+pub async fn load(ctx: &Context, guild: GuildId) -> GuildConfig {
+    ctx.data
+        .read()
+        .await
+        .get::<GuildConfig>()
+        .and_then(|configs| configs.get(&guild))
+        .cloned()
+        .unwrap_or(GuildConfig {
+            server: guild,
+            ..Default::default()
+        })
+}
+
+/// Saves the settings, then replaces them in the client data.
+///
+/// Takes the data for writing, so callers must not hold it.
+pub async fn save(ctx: &Context, storage: &dyn Storage, config: &mut GuildConfig) -> CommandResult {
+    storage.save_guild_config(config).await?;
+    ctx.data
+        .write()
+        .await
+        .get_mut::<GuildConfig>()
+        .ok_or("Guild configs not present")?
+        .insert(config.server, config.clone());
+    Ok(())
+}
+
+/// The group of every command, from the groups registered with the framework.
+pub struct CommandGroups {
+    names: Vec<&'static str>,
+    /// Groups with prefixes, by prefix, with the names of their commands by alias.
+    prefixed: HashMap<&'static str, (&'static str, HashMap<&'static str, &'static str>)>,
+    /// Groups without prefixes, and the names of their commands, by alias.
+    unprefixed: HashMap<&'static str, (&'static str, &'static str)>,
+    /// Commands of groups without prefixes, by alias.
+    commands: HashMap<&'static str, &'static Command>,
+}
+
+impl TypeMapKey for CommandGroups {
+    type Value = CommandGroups;
+}
+
+impl CommandGroups {
+    pub fn new(groups: &[&'static CommandGroup]) -> Self {
+        let mut index = CommandGroups {
+            names: Vec::with_capacity(groups.len()),
+            prefixed: HashMap::new(),
+            unprefixed: HashMap::new(),
+            commands: HashMap::new(),
+        };
+        for group in groups {
+            index.names.push(group.name);
+            index.insert(group, group.name);
+        }
+        index
+    }
+
+    /// Indexes the commands of the group and its sub-groups, as belonging to the registered group.
+    fn insert(&mut self, group: &'static CommandGroup, registered: &'static str) {
+        let mut commands = HashMap::new();
+        for command in group.options.commands {
+            let canonical = command.options.names[0];
+            for name in command.options.names {
+                commands.insert(*name, canonical);
+            }
+        }
+        if group.options.prefixes.is_empty() {
+            for (name, canonical) in commands {
+                self.unprefixed.insert(name, (registered, canonical));
+            }
+            for command in group.options.commands {
+                for name in command.options.names {
+                    self.commands.insert(*name, *command);
+                }
+            }
+        } else {
+            for prefix in group.options.prefixes {
+                self.prefixed.insert(*prefix, (registered, commands.clone()));
+            }
+        }
+        for sub_group in group.options.sub_groups {
+            self.insert(sub_group, registered);
+        }
+    }
+
+    pub fn names(&self) -> &[&'static str] {
+        &self.names
+    }
+
+    /// The registered name of the group, ignoring case.
+    pub fn find(&self, name: &str) -> Option<&'static str> {
+        self.names
+            .iter()
+            .copied()
+            .find(|group| group.eq_ignore_ascii_case(name))
+    }
+
+    /// The group of the command, given the message content following the prefix.
+    pub fn group_of(&self, invocation: &str, command: &str) -> Option<&'static str> {
+        let first = invocation.split_whitespace().next()?;
+        self.prefixed
+            .get(first)
+            .map(|(group, _)| group)
+            .or_else(|| self.unprefixed.get(command).map(|(group, _)| group))
+            .copied()
+    }
+
+    /// The command run by the name alone, if its group is registered.
+    pub fn command(&self, name: &str) -> Option<&'static Command> {
+        self.commands.get(name).copied()
+    }
+
+    /// The name of the command the message content following the prefix runs, after its group's prefix if any.
+    pub fn command_of(&self, invocation: &str) -> Option<String> {
+        let mut words = invocation.split_whitespace();
+        let first = words.next()?;
+        match self.prefixed.get(first) {
+            Some((_, commands)) => commands
+                .get(words.next()?)
+                .map(|command| format!("{} {}", first, command)),
+            None => self.unprefixed
+                .get(first)
+                .map(|(_, command)| command.to_string()),
+        }
+    }
+}
+
+/// The message content following the prefix, or a mention of the bot, which also works as one.
+pub fn strip_prefix<'a>(content: &'a str, prefix: &str, bot: UserId) -> Option<&'a str> {
+    if let Some(invocation) = content.strip_prefix(prefix) {
+        return Some(invocation);
+    }
+    let mention = content.trim_start().strip_prefix("<@")?;
+    let mention = mention.strip_prefix('!').unwrap_or(mention);
+    let invocation = mention.strip_prefix(&bot.0.to_string())?.strip_prefix('>')?;
+    Some(invocation.trim_start())
+}
+
+/// The server's own prefix, or the configured one.
+pub fn prefix(data: &TypeMap, guild: Option<GuildId>) -> Option<String> {
+    guild
+        .and_then(|guild| data.get::<GuildConfig>()?.get(&guild)?.prefix.clone())
+        .or_else(|| data.get::<DiscordCredentials>().map(|credentials| credentials.prefix.clone()))
+}
+
+#[hook]
+pub async fn dynamic_prefix(ctx: &Context, msg: &Message) -> Option<String> {
+    prefix(&*ctx.data.read().await, msg.guild_id)
+}
+
+/// Ignores commands of groups the server has not enabled.
+#[hook]
+pub async fn enabled_groups(ctx: &Context, msg: &Message, command: &str) -> bool {
+    let guild = if let Some(guild) = msg.guild_id {
+        guild
+    } else {
+        return true;
+    };
+    let data = ctx.data.read().await;
+    let config = if let Some(config) = data.get::<GuildConfig>().and_then(|configs| configs.get(&guild)) {
+        config
+    } else {
+        return true;
+    };
+    if config.groups.is_none() {
+        return true;
+    }
+    let (groups, credentials) = match (data.get::<CommandGroups>(), data.get::<DiscordCredentials>()) {
+        (Some(groups), Some(credentials)) => (groups, credentials),
+        _ => return true,
+    };
+
+    let prefix = config.prefix.as_deref().unwrap_or(&credentials.prefix);
+    let bot = ctx.cache.current_user_id().await;
+    let invocation = strip_prefix(&msg.content, prefix, bot).unwrap_or("");
+    group_enabled(Some(config), groups.group_of(invocation, command))
+}
+
+/// Whether the server lets the group's commands be used, however they are invoked.
+///
+/// Commands outside any registered group, and servers without settings, are always allowed.
+pub fn group_enabled(config: Option<&GuildConfig>, group: Option<&str>) -> bool {
+    match (config.and_then(|config| config.groups.as_ref()), group) {
+        (Some(enabled), Some(group)) =>
+            group == CONFIG_GROUP_NAME
+            || enabled.iter().any(|name| name.eq_ignore_ascii_case(group)),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::commands::{
+        AUDIT_GROUP,
+        GENERAL_GROUP,
+    };
+    use super::*;
+
+    #[test]
+    fn commands_belong_to_their_registered_group() {
+        let groups = CommandGroups::new(&[&GENERAL_GROUP, &AUDIT_GROUP]);
+        assert_eq!(groups.names(), &["General", "Audit"]);
+        assert_eq!(groups.find("audit"), Some("Audit"));
+        assert_eq!(groups.group_of("ping", "ping"), Some("General"));
+        assert_eq!(groups.group_of("audit user @Someone", "user"), Some("Audit"));
+        assert_eq!(groups.group_of("missing", "missing"), None);
+        assert_eq!(groups.command_of("audit user @Someone"), Some("audit user".to_string()));
+        assert_eq!(groups.command_of("ping"), Some("ping".to_string()));
+        assert_eq!(groups.command_of("audit missing"), None);
+    }
+
+    #[test]
+    fn only_enabled_groups_are_allowed() {
+        let mut config = GuildConfig::default();
+        assert!(group_enabled(None, Some("Roles")));
+        assert!(group_enabled(Some(&config), Some("Roles")));
+
+        config.groups = Some(vec!["general".to_string()]);
+        assert!(group_enabled(Some(&config), Some("General")));
+        assert!(!group_enabled(Some(&config), Some("Roles")));
+        assert!(group_enabled(Some(&config), Some(CONFIG_GROUP_NAME)));
+        assert!(group_enabled(Some(&config), None));
+    }
+
+    #[test]
+    fn prefixes_and_mentions_both_invoke() {
+        let bot = UserId(5);
+        assert_eq!(strip_prefix("!ping", "!", bot), Some("ping"));
+        assert_eq!(strip_prefix("<@5> ping", "!", bot), Some("ping"));
+        assert_eq!(strip_prefix("<@!5>ping", "!", bot), Some("ping"));
+        assert_eq!(strip_prefix("<@6> ping", "!", bot), None);
+        assert_eq!(strip_prefix("ping", "!", bot), None);
+    }
+}
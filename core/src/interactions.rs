@@ -0,0 +1,620 @@
+use std::{
+    convert::{
+        Infallible,
+        TryFrom,
+    },
+    fmt::Write as _,
+    net::SocketAddr,
+    sync::atomic::{
+        AtomicBool,
+        Ordering,
+    },
+    time::Duration,
+};
+
+use ed25519_dalek::{
+    Keypair,
+    PublicKey,
+    SecretKey,
+    Signature,
+    Signer,
+    Verifier,
+};
+use hyper::{
+    Body,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+    service::{
+        make_service_fn,
+        service_fn,
+    },
+};
+use serde::Deserialize;
+use serde_json::{
+    json,
+    Value,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        Command,
+        CommandResult,
+        Delimiter,
+        OnlyIn,
+    },
+    model::prelude::*,
+    prelude::*,
+};
+use tokio::time::delay_for;
+
+use crate::{
+    guild_config::{
+        group_enabled,
+        CommandGroups,
+    },
+    models::{
+        DiscordCredentials,
+        GuildConfig,
+    },
+};
+
+const API: &str = "https://discord.com/api/v8";
+pub const SIGNATURE_HEADER: &str = "X-Signature-Ed25519";
+pub const TIMESTAMP_HEADER: &str = "X-Signature-Timestamp";
+/// How far a request's timestamp may be from now, in seconds, so a captured request can't be replayed later.
+const TIMESTAMP_TOLERANCE: i64 = 5 * 60;
+
+static SERVING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Copy, Clone)]
+enum OptionKind {
+    String = 3,
+    Channel = 7,
+    Role = 8,
+}
+
+struct SlashOption {
+    name: &'static str,
+    description: &'static str,
+    kind: OptionKind,
+    required: bool,
+}
+
+struct SlashCommand {
+    name: &'static str,
+    description: &'static str,
+    options: &'static [SlashOption],
+}
+
+const fn option(name: &'static str, description: &'static str, kind: OptionKind, required: bool) -> SlashOption {
+    SlashOption { name, description, kind, required }
+}
+
+/// Slash commands dispatch to the prefix command of the same name, with options as its arguments.
+const SLASH_COMMANDS: &[SlashCommand] = &[
+    SlashCommand {
+        name: "ping",
+        description: "Pong!",
+        options: &[],
+    },
+    SlashCommand {
+        name: "parrot",
+        description: "Repeats every argument back.",
+        options: &[
+            option("text", "What to repeat", OptionKind::String, false),
+        ],
+    },
+    SlashCommand {
+        name: "join",
+        description: "Join the group for this channel, or a group by name.",
+        options: &[
+            option("group", "Role, alias, or channel name of the group", OptionKind::String, false),
+        ],
+    },
+    SlashCommand {
+        name: "leave",
+        description: "Leave the group for this channel, or a group by name.",
+        options: &[
+            option("group", "Role, alias, or channel name of the group", OptionKind::String, false),
+        ],
+    },
+    SlashCommand {
+        name: "register_role",
+        description: "Associate a role to a channel, or to the server.",
+        options: &[
+            option("role", "The role to associate", OptionKind::Role, true),
+            option("channel", "The channel to associate; the server when absent", OptionKind::Channel, false),
+        ],
+    },
+    SlashCommand {
+        name: "play",
+        description: "Start an RPG game.",
+        options: &[
+            option("name", "Your character's name", OptionKind::String, false),
+        ],
+    },
+    SlashCommand {
+        name: "rpg_channel",
+        description: "Enable the RPG in a channel.",
+        options: &[
+            option("channel", "The channel to enable", OptionKind::Channel, true),
+        ],
+    },
+];
+
+#[derive(Deserialize, Debug)]
+struct Interaction {
+    #[serde(rename = "type")]
+    kind: u8,
+    token: String,
+    #[serde(default)]
+    data: Option<InteractionData>,
+    #[serde(default)]
+    guild_id: Option<GuildId>,
+    #[serde(default)]
+    channel_id: Option<ChannelId>,
+    #[serde(default)]
+    member: Option<InteractionMember>,
+    #[serde(default)]
+    user: Option<User>,
+    #[serde(default)]
+    message: Option<InteractionMessage>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InteractionData {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    options: Vec<InteractionOption>,
+    #[serde(default)]
+    custom_id: Option<String>,
+    #[serde(default)]
+    values: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct InteractionMessage {
+    id: MessageId,
+}
+
+#[derive(Deserialize, Debug)]
+struct InteractionOption {
+    name: String,
+    value: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct InteractionMember {
+    user: User,
+    #[serde(default)]
+    permissions: Option<String>,
+}
+
+/// Whether the body was signed with the key at the timestamp, and the timestamp is close to `now`.
+pub fn verify(public_key: &str, signature: &str, timestamp: &str, body: &[u8], now: i64) -> bool {
+    match timestamp.parse::<i64>() {
+        Ok(timestamp) if (now - timestamp).abs() <= TIMESTAMP_TOLERANCE => {},
+        _ => return false,
+    }
+    let public_key = match hex::decode(public_key)
+        .ok()
+        .and_then(|key| PublicKey::from_bytes(&key).ok())
+    {
+        Some(key) => key,
+        None => return false,
+    };
+    let signature = match hex::decode(signature)
+        .ok()
+        .and_then(|signature| Signature::try_from(signature.as_slice()).ok())
+    {
+        Some(signature) => signature,
+        None => return false,
+    };
+    let mut message = timestamp.as_bytes().to_vec();
+    message.extend_from_slice(body);
+    public_key.verify(&message, &signature).is_ok()
+}
+
+/// Signs a request body as Discord would, for fixtures.
+///
+/// Returns the hex-encoded public key and signature.
+pub fn sign(secret_key: &str, timestamp: &str, body: &[u8]) -> Result<(String, String), Box<dyn std::error::Error + Send + Sync>> {
+    let secret = SecretKey::from_bytes(&hex::decode(secret_key)?)?;
+    let public = PublicKey::from(&secret);
+    let keypair = Keypair { secret, public };
+    let mut message = timestamp.as_bytes().to_vec();
+    message.extend_from_slice(body);
+    Ok((
+        hex::encode(public.as_bytes()),
+        hex::encode(keypair.sign(&message).to_bytes()),
+    ))
+}
+
+/// Registers guild commands and starts the endpoint, once, if an address is configured.
+pub async fn ready(ctx: &Context, ready: &Ready) -> CommandResult {
+    let (address, token, application) = {
+        let data = ctx.data.read().await;
+        let creds = data.get::<DiscordCredentials>().ok_or("Credentials not present")?;
+        let address = if let Some(address) = &creds.interactions_address {
+            address.parse::<SocketAddr>()?
+        } else {
+            return Ok(());
+        };
+        (address, creds.token.clone(), creds.bot_id.clone())
+    };
+    if SERVING.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let ctx = ctx.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| handle(ctx.clone(), request)))
+            }
+        });
+        if let Err(why) = Server::bind(&address).serve(make_service).await {
+            tracing::error!(error = ?why, "The interactions endpoint stopped");
+        }
+    });
+
+    let client = reqwest::Client::new();
+    let authorization = if token.starts_with("Bot ") {
+        token
+    } else {
+        format!("Bot {}", token)
+    };
+    // Overwriting them all at once also removes commands no longer defined
+    let commands = definitions();
+    for guild in &ready.guilds {
+        client
+            .put(&format!("{}/applications/{}/guilds/{}/commands", API, application, guild.id().0))
+            .header("Authorization", &authorization)
+            .json(&commands)
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+
+    Ok(())
+}
+
+/// Every slash command, as registered with Discord.
+fn definitions() -> Value {
+    SLASH_COMMANDS
+        .iter()
+        .map(|command| json!({
+            "name": command.name,
+            "description": command.description,
+            "options": command.options
+                .iter()
+                .map(|option| json!({
+                    "name": option.name,
+                    "description": option.description,
+                    "type": option.kind as u8,
+                    "required": option.required,
+                }))
+                .collect::<Vec<Value>>(),
+        }))
+        .collect()
+}
+
+async fn handle(ctx: Context, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let header = |name: &str| parts.headers
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+    let (signature, timestamp) = match (header(SIGNATURE_HEADER), header(TIMESTAMP_HEADER)) {
+        (Some(signature), Some(timestamp)) => (signature, timestamp),
+        _ => return Ok(status(StatusCode::UNAUTHORIZED)),
+    };
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+    };
+
+    let public_key = match ctx.data.read().await.get::<DiscordCredentials>() {
+        Some(creds) => creds.public.clone(),
+        None => return Ok(status(StatusCode::SERVICE_UNAVAILABLE)),
+    };
+    if !verify(&public_key, &signature, &timestamp, &body, crate::status::now()) {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+
+    let interaction: Interaction = match serde_json::from_slice(&body) {
+        Ok(interaction) => interaction,
+        Err(_) => return Ok(status(StatusCode::BAD_REQUEST)),
+    };
+    let response = match interaction.kind {
+        // Ping
+        1 => json!({ "type": 1 }),
+        // Application command
+        2 => dispatch(ctx, interaction).await,
+        // Message component
+        3 => component(ctx, interaction),
+        _ => return Ok(status(StatusCode::BAD_REQUEST)),
+    };
+
+    Ok(Response::builder()
+        .header("Content-Type", "application/json")
+        .body(Body::from(response.to_string()))
+        .unwrap())
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    Response::builder()
+        .status(code)
+        .body(Body::empty())
+        .unwrap()
+}
+
+fn ephemeral(content: &str) -> Value {
+    json!({
+        "type": 4,
+        "data": {
+            "content": content,
+            "flags": 64,
+        },
+    })
+}
+
+/// Validates a command interaction, and echoes it back as the message the command replies to.
+async fn dispatch(ctx: Context, interaction: Interaction) -> Value {
+    let data = match &interaction.data {
+        Some(data) => data,
+        None => return ephemeral("Missing command data."),
+    };
+    let found = {
+        let client_data = ctx.data.read().await;
+        let config = interaction.guild_id
+            .and_then(|guild| client_data.get::<GuildConfig>()?.get(&guild));
+        client_data
+            .get::<CommandGroups>()
+            .and_then(|groups| Some((find_command(groups, &data.name)?, enabled(groups, config, &data.name))))
+    };
+    let (slash, command) = match found {
+        Some((found, true)) => found,
+        Some((_, false)) => return ephemeral("That command is disabled in this server."),
+        None => return ephemeral("Unknown command."),
+    };
+    let (author, permissions) = match (&interaction.member, &interaction.user) {
+        (Some(member), _) => (
+            member.user.clone(),
+            member.permissions
+                .as_deref()
+                .and_then(|permissions| permissions.parse().ok())
+                .map(Permissions::from_bits_truncate)
+                .unwrap_or_else(Permissions::empty),
+        ),
+        (None, Some(user)) => (user.clone(), Permissions::empty()),
+        (None, None) => return ephemeral("Missing user."),
+    };
+    let channel = match interaction.channel_id {
+        Some(channel) => channel,
+        None => return ephemeral("Missing channel."),
+    };
+    if let (OnlyIn::Guild, None) = (command.options.only_in, interaction.guild_id) {
+        return ephemeral("That command only works in a server.");
+    }
+    if !permissions.contains(command.options.required_permissions) {
+        return ephemeral("You don't have permission to use that command.");
+    }
+
+    let mut args = String::new();
+    for definition in slash.options {
+        let value = data.options
+            .iter()
+            .find(|option| option.name == definition.name)
+            .map(|option| match &option.value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            });
+        let value = if let Some(value) = value {
+            value
+        } else {
+            continue;
+        };
+        if !args.is_empty() {
+            args.push(' ');
+        }
+        let _ = match definition.kind {
+            OptionKind::String => write!(&mut args, "{}", value),
+            OptionKind::Channel => write!(&mut args, "<#{}>", value),
+            OptionKind::Role => write!(&mut args, "<@&{}>", value),
+        };
+    }
+    let content = if args.is_empty() {
+        format!("`/{}`", slash.name)
+    } else {
+        format!("`/{} {}`", slash.name, args)
+    };
+
+    let token = interaction.token;
+    let guild = interaction.guild_id;
+    tokio::spawn(async move {
+        let result = run(ctx.clone(), command, token, guild, channel, author, args).await;
+        crate::errors::report(&ctx, slash.name, result).await;
+    });
+
+    json!({
+        "type": 4,
+        "data": {
+            "content": content,
+            "allowed_mentions": { "parse": [] },
+        },
+    })
+}
+
+/// Routes a button press or menu selection into the RPG, like a reaction.
+#[cfg(feature = "rpg")]
+fn component(ctx: Context, interaction: Interaction) -> Value {
+    let action = match &interaction.data {
+        Some(InteractionData { values, custom_id, .. }) =>
+            values.first().or_else(|| custom_id.as_ref()).cloned(),
+        None => None,
+    };
+    let user = interaction.member
+        .map(|member| member.user.id)
+        .or_else(|| interaction.user.map(|user| user.id));
+    let (action, user, channel, message) = match (action, user, interaction.channel_id, interaction.message) {
+        (Some(action), Some(user), Some(channel), Some(message)) => (action, user, channel, message.id),
+        _ => return ephemeral("Missing component data."),
+    };
+
+    tokio::spawn(async move {
+        crate::errors::report(
+            &ctx,
+            "RPG_Component",
+            crate::commands::rpg_action(&ctx, channel, message, user, &action).await,
+        ).await;
+    });
+
+    // Deferred update; the message is edited once the action resolves.
+    json!({ "type": 6 })
+}
+
+#[cfg(not(feature = "rpg"))]
+fn component(_: Context, _: Interaction) -> Value {
+    ephemeral("Sorry, RPG is unavailable.")
+}
+
+/// The slash command and the prefix command it runs, if the prefix command's group is registered.
+fn find_command(groups: &CommandGroups, name: &str) -> Option<(&'static SlashCommand, &'static Command)> {
+    let slash = SLASH_COMMANDS.iter().find(|slash| slash.name == name)?;
+    Some((slash, groups.command(name)?))
+}
+
+/// Whether the server has enabled the group of the command, as the framework checks before prefix commands.
+fn enabled(groups: &CommandGroups, config: Option<&GuildConfig>, name: &str) -> bool {
+    group_enabled(config, groups.group_of(name, name))
+}
+
+/// Runs the command against the echoed response, once Discord has created it.
+async fn run(
+    ctx: Context,
+    command: &'static Command,
+    token: String,
+    guild: Option<GuildId>,
+    channel: ChannelId,
+    author: User,
+    args: String,
+) -> CommandResult {
+    let application = ctx.data
+        .read()
+        .await
+        .get::<DiscordCredentials>()
+        .ok_or("Credentials not present")?
+        .bot_id
+        .clone();
+    let url = format!("{}/webhooks/{}/{}/messages/@original", API, application, token);
+
+    let client = reqwest::Client::new();
+    let mut attempts = 0;
+    let mut msg: Message = loop {
+        delay_for(Duration::from_millis(500)).await;
+        let response = client.get(&url).send().await?;
+        if response.status().is_success() {
+            break response.json().await?;
+        }
+        attempts += 1;
+        if attempts >= 5 {
+            return Err(format!("Original response unavailable: {}", response.status()).into());
+        }
+    };
+    msg.guild_id = guild;
+    msg.channel_id = channel;
+    msg.author = author;
+
+    (command.fun)(&ctx, &msg, Args::new(&args, &[Delimiter::Single(' ')])).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    const BODY: &[u8] = br#"{"type":1}"#;
+    const NOW: i64 = 1_600_000_000;
+
+    #[test]
+    fn signed_requests_verify() -> CommandResult {
+        let timestamp = NOW.to_string();
+        let (public, signature) = sign(SECRET, &timestamp, BODY)?;
+        assert!(verify(&public, &signature, &timestamp, BODY, NOW));
+        assert!(verify(&public, &signature, &timestamp, BODY, NOW + TIMESTAMP_TOLERANCE));
+        Ok(())
+    }
+
+    #[test]
+    fn tampered_requests_fail() -> CommandResult {
+        let timestamp = NOW.to_string();
+        let (public, signature) = sign(SECRET, &timestamp, BODY)?;
+        assert!(!verify(&public, &signature, &timestamp, br#"{"type":2}"#, NOW));
+        assert!(!verify(&public, &signature, &(NOW + 1).to_string(), BODY, NOW));
+        assert!(!verify(&public, "not hex", &timestamp, BODY, NOW));
+        Ok(())
+    }
+
+    #[test]
+    fn stale_requests_fail() -> CommandResult {
+        let timestamp = NOW.to_string();
+        let (public, signature) = sign(SECRET, &timestamp, BODY)?;
+        assert!(!verify(&public, &signature, &timestamp, BODY, NOW + TIMESTAMP_TOLERANCE + 1));
+        assert!(!verify(&public, &signature, &timestamp, BODY, NOW - TIMESTAMP_TOLERANCE - 1));
+
+        let (public, signature) = sign(SECRET, "yesterday", BODY)?;
+        assert!(!verify(&public, &signature, "yesterday", BODY, NOW));
+        Ok(())
+    }
+
+    #[test]
+    fn only_registered_commands_are_found() {
+        let groups = CommandGroups::new(&[&crate::commands::GENERAL_GROUP]);
+        assert!(find_command(&groups, "ping").is_some());
+        assert!(find_command(&groups, "play").is_none());
+        assert!(find_command(&groups, "missing").is_none());
+
+        let groups = CommandGroups::new(&[&crate::commands::GENERAL_GROUP, &crate::commands::RPG_GROUP]);
+        assert!(find_command(&groups, "play").is_some());
+    }
+
+    #[test]
+    fn every_command_is_registered_at_once() {
+        let commands = definitions();
+        let commands = commands.as_array().expect("An array of commands");
+        assert_eq!(commands.len(), SLASH_COMMANDS.len());
+        let register_role = commands
+            .iter()
+            .find(|command| command["name"] == "register_role")
+            .expect("register_role defined");
+        assert_eq!(register_role["options"][0], json!({
+            "name": "role",
+            "description": "The role to associate",
+            "type": 8,
+            "required": true,
+        }));
+    }
+
+    #[test]
+    fn disabled_groups_refuse_slash_commands() {
+        let groups = CommandGroups::new(&[
+            &crate::commands::GENERAL_GROUP,
+            &crate::commands::ROLES_GROUP,
+            &crate::commands::RPG_GROUP,
+        ]);
+        let mut config = GuildConfig::default();
+        assert!(enabled(&groups, None, "join"));
+        assert!(enabled(&groups, Some(&config), "join"));
+
+        config.groups = Some(vec!["General".to_string()]);
+        assert!(enabled(&groups, Some(&config), "ping"));
+        assert!(!enabled(&groups, Some(&config), "join"));
+        assert!(!enabled(&groups, Some(&config), "register_role"));
+        assert!(!enabled(&groups, Some(&config), "play"));
+    }
+}
@@ -1,55 +1,137 @@
 #![deny(rust_2018_idioms)]
 
 use std::{
-    fs::read_to_string,
-    time::{
-        SystemTime,
-        UNIX_EPOCH,
-    },
+    iter,
+    sync::Arc,
+    time::Duration,
 };
 
 use serenity::{
     client::{
+        bridge::gateway::ShardManager,
         Client,
     },
     framework::standard::{
+        CommandGroup,
         CommandResult,
         macros::hook,
         StandardFramework,
     },
-    prelude::TypeMapKey,
+    prelude::{
+        Mutex,
+        TypeMapKey,
+    },
 };
+use tokio::sync::mpsc;
 use wither::{
     mongodb::{
+        error::Error as DBError,
+        options::ClientOptions,
         Client as DBClient,
         Database,
     },
-    prelude::*
+    Model,
 };
 
-use crate::models::DiscordCredentials;
+use crate::{
+    config::{
+        Config,
+        DatabaseConfig,
+    },
+    models::{
+        DiscordCredentials,
+        GuildConfig,
+        RoleMenu,
+        RoleRequest,
+        System,
+    },
+    storage::{
+        MongoStorage,
+        Storage,
+        StorageKey,
+    },
+    errors::ErrorReporter,
+    expiry::Expiries,
+    guild_config::CommandGroups,
+    logging::TracedFramework,
+    metrics::Metrics,
+    shutdown::{
+        Exit,
+        Shutdown,
+    },
+    status::StatusRoles,
+    supervisor::Supervisor,
+};
 
+pub mod config;
 pub mod models;
+pub mod interactions;
+pub mod storage;
+mod audit;
 mod commands;
+mod errors;
+mod exclusive;
+mod expiry;
+mod guild_config;
+mod logging;
+mod metrics;
+mod shutdown;
+mod status;
+mod supervisor;
 mod util;
 
 pub const DATABASE_NAME: &str = "ohg";
-#[cfg(feature = "rpg")]
 pub const RPG_DATABASE_NAME: &str = "rpg";
 
-pub async fn connect_db() -> DatabaseHandle {
-    let client = DBClient::with_uri_str(
-        &read_to_string("./db-url.txt")
-            .expect("Failed to read db-url.txt")
-    )
-        .await
-        .expect("Failed to connect");
-    DatabaseHandle {
-        base: client.database(DATABASE_NAME),
+/// Connects lazily, so only a bad url fails here; an unreachable server fails the first query.
+pub async fn connect_db(config: &DatabaseConfig) -> Result<DatabaseHandle, DBError> {
+    connect_db_with(config, None).await
+}
+
+/// Like `connect_db`, timing every query in the metrics if given.
+async fn connect_db_with(config: &DatabaseConfig, metrics: Option<Arc<Metrics>>) -> Result<DatabaseHandle, DBError> {
+    let mut options = ClientOptions::parse(&config.url).await?;
+    if let Some(metrics) = metrics {
+        options.command_event_handler = Some(metrics);
+    }
+    let client = DBClient::with_options(options)?;
+    Ok(DatabaseHandle {
+        base: client.database(&config.name),
         #[cfg(feature = "rpg")]
-        rpg: client.database(RPG_DATABASE_NAME),
+        rpg: client.database(&config.rpg_name),
         client,
+    })
+}
+
+/// Adds where to find the credentials when the config lacks them but the database still holds them,
+/// as they were kept there before the config file.
+async fn note_stored_credentials(mut errors: Vec<String>) -> Vec<String> {
+    let lacks_credentials = errors
+        .iter()
+        .any(|error| error.starts_with("discord.") && error.ends_with(" is required"));
+    let database = match DatabaseConfig::load() {
+        Ok(database) if lacks_credentials => database,
+        _ => return errors,
+    };
+    let mut options = match ClientOptions::parse(&database.url).await {
+        Ok(options) => options,
+        Err(_) => return errors,
+    };
+    // Only a hint, so an unreachable database shouldn't hold up the report
+    options.server_selection_timeout = Some(Duration::from_secs(5));
+    let stored = match DBClient::with_options(options) {
+        Ok(client) => DiscordCredentials::find_one(&client.database(&database.name), None, None)
+            .await
+            .map_or(false, |credentials| credentials.is_some()),
+        Err(_) => false,
+    };
+    if stored {
+        errors.push(format!(
+            "discord credentials are in the database, which is no longer read; move them to the [discord] section of {} (`--dump --include-secrets` shows them)",
+            config::path(),
+        ));
     }
+    errors
 }
 
 pub struct DatabaseHandle {
@@ -59,31 +141,79 @@ pub struct DatabaseHandle {
     pub rpg: Database,
 }
 
-impl TypeMapKey for DatabaseHandle {
-    type Value = DatabaseHandle;
+pub struct ShardManagerContainer;
+
+impl TypeMapKey for ShardManagerContainer {
+    type Value = Arc<Mutex<ShardManager>>;
 }
 
-pub async fn main() {
-    let database_handle = connect_db().await;
-    let creds: DiscordCredentials = DiscordCredentials::find_one(
-        &database_handle.base,
-        None,
-        None,
-    )
-        .await
-        .expect("Failed to search discord credentials")
-        .expect("Failed to find discord credentials");
+/// Runs the bot until it is stopped, returning the status to exit with.
+pub async fn main() -> i32 {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(errors) => config::report(note_stored_credentials(errors).await),
+    };
+    let _logging = logging::init(&config.logging);
+    let features = config.features;
+    let creds = config.credentials;
+    let metrics = config.metrics_address.map(|_| Arc::new(Metrics::new()));
+    let database_handle = match connect_db_with(&config.database, metrics.clone()).await {
+        Ok(handle) => handle,
+        Err(e) => {
+            tracing::error!(error = %e, "Failed to connect to the database");
+            return Exit::Failed.status();
+        },
+    };
+    let storage: Arc<dyn Storage> = Arc::new(MongoStorage::new(&database_handle));
 
-    let framework = StandardFramework::new()
-        .configure(|c| c.prefix(&creds.prefix)) // set the bot's prefix to "~"
-        .group(&commands::GENERAL_GROUP)
-        .group(&commands::ROLES_GROUP)
-        .group(&commands::RPG_GROUP)
-        .after(print_errors);
+    let mut groups: Vec<&'static CommandGroup> = vec![
+        &commands::GENERAL_GROUP,
+        &commands::ROLES_GROUP,
+        &commands::ROLE_MENUS_GROUP,
+        &commands::EXCLUSIVE_GROUPS_GROUP,
+        &commands::APPROVALS_GROUP,
+        &commands::AUDIT_GROUP,
+        &commands::CONFIG_GROUP,
+        &commands::OPERATOR_GROUP,
+    ];
+    if features.rpg {
+        groups.push(&commands::RPG_GROUP);
+    }
+    if features.systems {
+        groups.push(&commands::SYSTEMS_GROUP);
+    }
+    if features.status_roles {
+        groups.push(&commands::STATUS_ROLES_GROUP);
+    }
+    // Each server may set its own prefix, falling back to the configured one
+    let mut framework = StandardFramework::new()
+        .configure(|c| c
+            .prefix("")
+            .dynamic_prefix(guild_config::dynamic_prefix)
+            .owners(iter::once(creds.operator).collect())
+        )
+        .before(guild_config::enabled_groups)
+        .after(report_errors)
+        .help(&commands::HELP);
+    for group in &groups {
+        framework = framework.group(*group);
+    }
+
+    let systems: Vec<System> = if features.systems {
+        storage
+            .systems(None)
+            .await
+            .expect("Failed to retrieve systems")
+    } else {
+        Vec::new()
+    };
+    let supervisor = Arc::new(Supervisor::default());
+    print_errors_impl("System_Boot", supervisor.boot(&systems).await);
 
+    let (shutdown_sender, mut shutdown_receiver) = mpsc::unbounded_channel();
     let mut client = Client::builder(&creds.token)
         .event_handler(commands::Handler)
-        .framework(framework)
+        .framework(TracedFramework(framework))
         .await
         .expect("Error creating client");
 
@@ -94,7 +224,6 @@ pub async fn main() {
             use std::collections::HashSet;
 
             use cache_2q::Cache;
-            use futures::stream::StreamExt;
             use crate::{
                 models::{
                     RPGChannel,
@@ -103,39 +232,116 @@ pub async fn main() {
                 util::RPGStateHolder,
             };
 
-            let mut channels = HashSet::new();
-            let mut db_channels = RPGChannel::find(&database_handle.base, None, None).await
-                .expect("Failed to retrieve RPG channels");
-            while let Some(channel) = db_channels.next().await {
-                let RPGChannel { channel, .. } = channel.expect("Failed to retrieve RPG channel");
-                channels.insert(channel);
-            }
+            // Without any RPG channels, reactions and components are ignored
+            let channels: HashSet<_> = if features.rpg {
+                storage
+                    .rpg_channels()
+                    .await
+                    .expect("Failed to retrieve RPG channels")
+                    .into_iter()
+                    .map(|RPGChannel { channel, .. }| channel)
+                    .collect()
+            } else {
+                HashSet::new()
+            };
             data.insert::<RPGChannel>(channels);
             data.insert::<RPGState>(
                 RPGStateHolder {
-                    cache: Cache::new(128),
+                    cache: Cache::new(config.rpg_cache_size),
                     lockout: Default::default(),
+                    // Components are only delivered through the interactions endpoint
+                    components: creds.interactions_address.is_some(),
+                    closing: false,
                 }.into()
             );
         }
-        data.insert::<DatabaseHandle>(database_handle);
+        let menus = storage
+            .role_menus()
+            .await
+            .expect("Failed to retrieve role menus")
+            .into_iter()
+            .map(|RoleMenu { message, .. }| message)
+            .collect();
+        data.insert::<RoleMenu>(menus);
+        let requests = storage
+            .role_requests(None)
+            .await
+            .expect("Failed to retrieve role requests")
+            .into_iter()
+            .map(|RoleRequest { message, .. }| message)
+            .collect();
+        data.insert::<RoleRequest>(requests);
+        let configs = storage
+            .guild_configs()
+            .await
+            .expect("Failed to retrieve guild configs")
+            .into_iter()
+            .map(|config| (config.server, config))
+            .collect();
+        data.insert::<GuildConfig>(configs);
+        data.insert::<CommandGroups>(CommandGroups::new(&groups));
+        data.insert::<StorageKey>(storage);
+        data.insert::<ErrorReporter>(Arc::new(ErrorReporter::new(creds.operator)));
         data.insert::<DiscordCredentials>(creds);
+        data.insert::<Supervisor>(supervisor.clone());
+        data.insert::<Expiries>(Default::default());
+        data.insert::<ShardManagerContainer>(client.shard_manager.clone());
+        data.insert::<Shutdown>(shutdown_sender);
+        if let Some(metrics) = metrics {
+            data.insert::<Metrics>(metrics);
+        }
+        if features.status_roles {
+            data.insert::<StatusRoles>(Default::default());
+        }
+    }
+
+    if let Some(address) = config.metrics_address {
+        metrics::serve(address, client.data.clone());
     }
 
     // start listening for events by starting a single shard
-    if let Err(why) = client.start().await {
-        println!("An error occurred while running the client: {:?}", why);
+    let exit = tokio::select! {
+        result = client.start() => {
+            if let Err(why) = result {
+                tracing::error!(error = ?why, "The client stopped");
+            }
+            Exit::Failed
+        },
+        exit = shutdown::requested(&mut shutdown_receiver) => exit,
+    };
+    tracing::info!(?exit, "Shutting down");
+
+    // No new events arrive once the shards are closed, though those already dispatched run on
+    client.shard_manager.lock().await.shutdown_all().await;
+    let drained = shutdown::drain(&client.data).await;
+    let stopped = supervisor.shutdown(&systems).await;
+    let clean = drained.is_ok() && stopped.is_ok();
+    print_errors_impl("Shutdown_Drain", drained);
+    print_errors_impl("System_Shutdown", stopped);
+
+    // Restarts go ahead regardless, as they are what fixes most unclean exits
+    match exit {
+        Exit::Shutdown if !clean => Exit::Failed.status(),
+        exit => exit.status(),
     }
 }
 
 #[hook]
-async fn print_errors(
-    _: &serenity::prelude::Context,
-    _: &serenity::model::channel::Message,
+async fn report_errors(
+    ctx: &serenity::prelude::Context,
+    msg: &serenity::model::channel::Message,
     cmd_name: &str,
     error: CommandResult,
 ) {
-    print_errors_impl(cmd_name, error)
+    if let Some(id) = errors::report_command(ctx, msg, cmd_name, error).await {
+        let reply = msg.reply(
+            ctx,
+            format!("Something went wrong. If it keeps happening, tell the operator about error `{}`.", id),
+        ).await;
+        if let Err(why) = reply {
+            print_errors_impl("Error_Reply", Err(why.into()));
+        }
+    }
 }
 
 fn print_errors_impl(cmd_name: &str, error: CommandResult) {
@@ -144,13 +350,5 @@ fn print_errors_impl(cmd_name: &str, error: CommandResult) {
     } else {
         return;
     };
-    println!(
-        "{} {}: {:#?}",
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("<1970 not supported")
-            .as_millis(),
-        cmd_name,
-        error,
-    );
+    tracing::error!(name = cmd_name, error = ?error, "Failed");
 }
@@ -0,0 +1,125 @@
+use std::{
+    future::Future,
+    path::Path,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serenity::{
+    async_trait,
+    framework::{
+        standard::StandardFramework,
+        Framework,
+    },
+    model::prelude::*,
+    prelude::*,
+};
+use tracing::{
+    field,
+    info_span,
+    Instrument,
+    Span,
+};
+use tracing_appender::{
+    non_blocking::WorkerGuard,
+    rolling::{
+        RollingFileAppender,
+        Rotation,
+    },
+};
+use tracing_subscriber::EnvFilter;
+
+use crate::{
+    config::{
+        LogFormat,
+        LogRotation,
+        LoggingConfig,
+    },
+    guild_config::{
+        self,
+        CommandGroups,
+    },
+    metrics::Metrics,
+};
+
+/// Sends spans and events where the config says.
+///
+/// Lines are written from another thread; the guard flushes them when dropped, so hold it until exit.
+pub fn init(config: &LoggingConfig) -> WorkerGuard {
+    let (writer, guard) = match &config.file {
+        Some(file) => {
+            let rotation = match config.rotation {
+                LogRotation::Hourly => Rotation::HOURLY,
+                LogRotation::Daily => Rotation::DAILY,
+                LogRotation::Never => Rotation::NEVER,
+            };
+            let directory = file
+                .parent()
+                .filter(|parent| !parent.as_os_str().is_empty())
+                .unwrap_or_else(|| Path::new("."));
+            let name = file.file_name().expect("Validated with the config");
+            tracing_appender::non_blocking(RollingFileAppender::new(rotation, directory, name))
+        },
+        None => tracing_appender::non_blocking(std::io::stdout()),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::new(&config.filter))
+        .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+        .with_ansi(config.file.is_none())
+        .with_writer(writer);
+    match config.format {
+        LogFormat::Human => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+    guard
+}
+
+/// Runs the future inside the span, then records how long it took as `latency_ms`.
+pub async fn timed<F: Future>(span: Span, future: F) -> (F::Output, Duration) {
+    let start = Instant::now();
+    let output = future.instrument(span.clone()).await;
+    let elapsed = start.elapsed();
+    span.record("latency_ms", &(elapsed.as_millis() as u64));
+    (output, elapsed)
+}
+
+/// Runs each command inside a span of where and by whom it was invoked, and counts it in the metrics.
+pub struct TracedFramework(pub StandardFramework);
+
+#[async_trait]
+impl Framework for TracedFramework {
+    async fn dispatch(&self, ctx: Context, msg: Message) {
+        // Every message is dispatched, but only commands are worth a span
+        let bot = ctx.cache.current_user_id().await;
+        let (command, metrics) = {
+            let data = ctx.data.read().await;
+            let prefix = guild_config::prefix(&data, msg.guild_id).unwrap_or_default();
+            let command = guild_config::strip_prefix(&msg.content, &prefix, bot)
+                .filter(|_| !msg.author.bot)
+                .and_then(|invocation| data.get::<CommandGroups>()?.command_of(invocation));
+            (command, data.get::<Metrics>().cloned())
+        };
+        let command = match command {
+            Some(command) => command,
+            None => return self.0.dispatch(ctx, msg).await,
+        };
+
+        let span = info_span!(
+            "command",
+            guild = field::Empty,
+            channel = msg.channel_id.0,
+            user = msg.author.id.0,
+            command = command.as_str(),
+            latency_ms = field::Empty,
+        );
+        if let Some(guild) = msg.guild_id {
+            span.record("guild", &guild.0);
+        }
+        let ((), elapsed) = timed(span, self.0.dispatch(ctx, msg)).await;
+        if let Some(metrics) = metrics {
+            metrics.command(&command, elapsed);
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::Duration,
+};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{
+        make_service_fn,
+        service_fn,
+    },
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+use prometheus::{
+    core::Collector,
+    Encoder,
+    GaugeVec,
+    Histogram,
+    HistogramOpts,
+    HistogramVec,
+    IntCounterVec,
+    IntGauge,
+    Opts,
+    Registry,
+    TextEncoder,
+};
+use serenity::prelude::*;
+use wither::mongodb::event::command::{
+    CommandEventHandler,
+    CommandFailedEvent,
+    CommandSucceededEvent,
+};
+
+use crate::ShardManagerContainer;
+
+/// Everything measured for the `/metrics` endpoint, when one is configured.
+pub struct Metrics {
+    registry: Registry,
+    commands: IntCounterVec,
+    command_seconds: HistogramVec,
+    reaction_add_seconds: Histogram,
+    database_seconds: HistogramVec,
+    database_failures: IntCounterVec,
+    rpg_cache: IntCounterVec,
+    rpg_lockout: IntGauge,
+    heartbeat_seconds: GaugeVec,
+}
+
+impl TypeMapKey for Metrics {
+    type Value = Arc<Metrics>;
+}
+
+fn register<C: Collector + Clone + 'static>(registry: &Registry, collector: C) -> C {
+    registry
+        .register(Box::new(collector.clone()))
+        .expect("Metric names are unique");
+    collector
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new_custom(Some("ohg".into()), None).expect("Valid namespace");
+        Metrics {
+            commands: register(&registry, IntCounterVec::new(
+                Opts::new("commands_total", "Commands invoked, by command"),
+                &["command"],
+            ).expect("Valid metric")),
+            command_seconds: register(&registry, HistogramVec::new(
+                HistogramOpts::new("command_duration_seconds", "Time taken to run each command"),
+                &["command"],
+            ).expect("Valid metric")),
+            reaction_add_seconds: register(&registry, Histogram::with_opts(
+                HistogramOpts::new("reaction_add_duration_seconds", "Time taken to handle each added reaction"),
+            ).expect("Valid metric")),
+            database_seconds: register(&registry, HistogramVec::new(
+                HistogramOpts::new("database_duration_seconds", "Time taken by MongoDB queries, by database command")
+                    .buckets(vec![0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5]),
+                &["command"],
+            ).expect("Valid metric")),
+            database_failures: register(&registry, IntCounterVec::new(
+                Opts::new("database_failures_total", "MongoDB queries that failed, by database command"),
+                &["command"],
+            ).expect("Valid metric")),
+            rpg_cache: register(&registry, IntCounterVec::new(
+                Opts::new("rpg_cache_lookups_total", "Lookups of RPG games in the cache, by hit or miss"),
+                &["result"],
+            ).expect("Valid metric")),
+            rpg_lockout: register(&registry, IntGauge::new(
+                "rpg_lockout_size",
+                "RPG games locked while a move is made",
+            ).expect("Valid metric")),
+            heartbeat_seconds: register(&registry, GaugeVec::new(
+                Opts::new("gateway_heartbeat_latency_seconds", "Latency of the last gateway heartbeat, by shard"),
+                &["shard"],
+            ).expect("Valid metric")),
+            registry,
+        }
+    }
+
+    pub fn command(&self, command: &str, elapsed: Duration) {
+        self.commands.with_label_values(&[command]).inc();
+        self.command_seconds.with_label_values(&[command]).observe(elapsed.as_secs_f64());
+    }
+
+    pub fn reaction_add(&self, elapsed: Duration) {
+        self.reaction_add_seconds.observe(elapsed.as_secs_f64());
+    }
+
+    pub fn rpg_cache(&self, hit: bool) {
+        self.rpg_cache.with_label_values(&[if hit { "hit" } else { "miss" }]).inc();
+    }
+
+    fn database(&self, command: &str, elapsed: Duration, failed: bool) {
+        self.database_seconds.with_label_values(&[command]).observe(elapsed.as_secs_f64());
+        if failed {
+            self.database_failures.with_label_values(&[command]).inc();
+        }
+    }
+}
+
+// Queries are timed by the driver, so every call through wither is measured
+impl CommandEventHandler for Metrics {
+    fn handle_command_succeeded_event(&self, event: CommandSucceededEvent) {
+        self.database(&event.command_name, event.duration, false);
+    }
+
+    fn handle_command_failed_event(&self, event: CommandFailedEvent) {
+        self.database(&event.command_name, event.duration, true);
+    }
+}
+
+/// Serves the metrics in the Prometheus text format at `/metrics`.
+pub fn serve(address: SocketAddr, data: Arc<RwLock<TypeMap>>) {
+    tokio::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let data = data.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| scrape(data.clone(), request)))
+            }
+        });
+        if let Err(why) = Server::bind(&address).serve(make_service).await {
+            tracing::error!(error = ?why, "The metrics endpoint stopped");
+        }
+    });
+}
+
+async fn scrape(data: Arc<RwLock<TypeMap>>, request: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != "/metrics" {
+        return Ok(status(StatusCode::NOT_FOUND));
+    }
+    let (metrics, shard_manager) = {
+        let data = data.read().await;
+        let metrics = match data.get::<Metrics>() {
+            Some(metrics) => metrics.clone(),
+            None => return Ok(status(StatusCode::SERVICE_UNAVAILABLE)),
+        };
+        #[cfg(feature = "rpg")]
+        if let Some(states) = data.get::<crate::models::RPGState>() {
+            metrics.rpg_lockout.set(states.lock().await.lockout.len() as i64);
+        }
+        (metrics, data.get::<ShardManagerContainer>().cloned())
+    };
+    // Sampled as scraped, rather than on every heartbeat
+    if let Some(shard_manager) = shard_manager {
+        let shard_manager = shard_manager.lock().await;
+        for (shard, runner) in shard_manager.runners.lock().await.iter() {
+            let gauge = metrics.heartbeat_seconds.with_label_values(&[&shard.0.to_string()]);
+            match runner.latency {
+                Some(latency) => gauge.set(latency.as_secs_f64()),
+                None => gauge.set(f64::NAN),
+            }
+        }
+    }
+
+    let encoder = TextEncoder::new();
+    let mut body = Vec::new();
+    if encoder.encode(&metrics.registry.gather(), &mut body).is_err() {
+        return Ok(status(StatusCode::INTERNAL_SERVER_ERROR));
+    }
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(body))
+        .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR)))
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn data() -> Arc<RwLock<TypeMap>> {
+        let mut data = TypeMap::new();
+        let metrics = Arc::new(Metrics::new());
+        metrics.command("ping", Duration::from_millis(5));
+        metrics.database("find", Duration::from_millis(2), false);
+        data.insert::<Metrics>(metrics);
+        Arc::new(RwLock::new(data))
+    }
+
+    fn get(path: &str) -> Request<Body> {
+        Request::get(path)
+            .body(Body::empty())
+            .expect("Valid request")
+    }
+
+    #[test]
+    fn scrapes_report_commands_and_queries() -> Result<(), hyper::Error> {
+        block_on(async {
+            let response = match scrape(data(), get("/metrics")).await {
+                Ok(response) => response,
+                Err(never) => match never {},
+            };
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = hyper::body::to_bytes(response.into_body()).await?;
+            let body = String::from_utf8_lossy(&body);
+            assert!(body.contains(r#"ohg_commands_total{command="ping"} 1"#), "{}", body);
+            assert!(body.contains(r#"ohg_database_duration_seconds_count{command="find"} 1"#), "{}", body);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn other_paths_are_not_found() {
+        block_on(async {
+            for path in &["/", "/metric", "/metrics/extra"] {
+                let response = match scrape(data(), get(path)).await {
+                    Ok(response) => response,
+                    Err(never) => match never {},
+                };
+                assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+            }
+        })
+    }
+}
@@ -22,6 +22,9 @@ pub struct DiscordCredentials {
     pub prefix: String,
     #[serde(with = "shim::Required")]
     pub operator: UserId,
+    /// Socket address for the slash command endpoint; disabled when absent.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub interactions_address: Option<String>,
 }
 
 impl TypeMapKey for DiscordCredentials {
@@ -38,7 +41,7 @@ impl TypeMapKey for RPGState {
     type Value = Mutex<crate::util::RPGStateHolder>;
 }
 
-#[derive(Model, Deserialize, Serialize, Debug)]
+#[derive(Model, Deserialize, Serialize, Debug, Clone)]
 pub struct RoleAssociation {
     #[serde(rename="_id", skip_serializing_if="Option::is_none")]
     pub id: Option<ObjectId>,
@@ -49,7 +52,13 @@ pub struct RoleAssociation {
     #[model(index(index="hashed"))]
     pub server: Option<GuildId>,
     #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
     pub role: RoleId,
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub alias: Option<String>,
+    /// Joining only requests the role, for a moderator to approve.
+    #[serde(default, skip_serializing_if="std::ops::Not::not")]
+    pub approval: bool,
 }
 
 #[derive(Model, Deserialize, Serialize, Debug)]
@@ -58,6 +67,188 @@ pub struct RoleStatus {
     pub id: Option<ObjectId>,
     #[serde(with = "shim::Required")]
     pub role: RoleId,
+    /// Absent on older records, which are matched to a server through the cache.
+    #[serde(default, with = "shim::Optional", skip_serializing_if="Option::is_none")]
+    pub server: Option<GuildId>,
+    #[serde(default)]
+    pub rules: Vec<StatusRule>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum StatusRule {
+    /// Assigned once the member holds at least `count` associated roles.
+    JoinedGroups { count: i32 },
+    /// Assigned once the member has sent a message.
+    FirstMessage,
+    /// Removed once the member has not sent a message for `days`.
+    Inactive { days: i32 },
+}
+
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct RoleMenu {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    pub server: GuildId,
+    #[serde(with = "shim::Required")]
+    pub channel: ChannelId,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub message: MessageId,
+    pub entries: Vec<RoleMenuEntry>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct RoleMenuEntry {
+    pub emoji: String,
+    #[serde(with = "shim::Required")]
+    pub role: RoleId,
+}
+
+impl TypeMapKey for RoleMenu {
+    type Value = std::collections::HashSet<MessageId>;
+}
+
+/// Roles of which a member may hold only one; joining one removes the others.
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct ExclusiveGroup {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub server: GuildId,
+    pub name: String,
+    #[serde(with = "shim::Many")]
+    pub roles: Vec<RoleId>,
+}
+
+/// Settings of a single server; every setting is optional and falls back to the bot's defaults.
+#[derive(Model, Deserialize, Serialize, Debug, Default, Clone)]
+pub struct GuildConfig {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub server: GuildId,
+    /// Replaces the configured prefix in this server.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub prefix: Option<String>,
+    /// Names of the command groups that may be used; every group when absent.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub groups: Option<Vec<String>>,
+    /// Language tag, such as `en-US`, writing the dates the bot shows in this server.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub locale: Option<String>,
+    /// Where every role change is logged.
+    #[serde(default, with = "shim::Optional", skip_serializing_if="Option::is_none")]
+    pub audit_channel: Option<ChannelId>,
+    /// Where requests to join approval-gated roles are posted.
+    #[serde(default, with = "shim::Optional", skip_serializing_if="Option::is_none")]
+    pub moderator_channel: Option<ChannelId>,
+    /// Holders may decide requests, besides members who can manage roles.
+    #[serde(default, with = "shim::Optional", skip_serializing_if="Option::is_none")]
+    pub moderator_role: Option<RoleId>,
+}
+
+impl TypeMapKey for GuildConfig {
+    type Value = std::collections::HashMap<GuildId, GuildConfig>;
+}
+
+/// A change to roles or to their associations, kept to be searched later.
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct AuditEvent {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="asc", with(field("at", index="dsc"))))]
+    pub server: GuildId,
+    /// Who made the change.
+    #[serde(with = "shim::Required")]
+    pub user: UserId,
+    /// Whose roles changed, when that was someone else.
+    #[serde(default, with = "shim::Optional", skip_serializing_if="Option::is_none")]
+    pub member: Option<UserId>,
+    /// Where the change was made.
+    #[serde(with = "shim::Required")]
+    pub channel: ChannelId,
+    pub command: String,
+    #[serde(with = "shim::Many")]
+    pub roles: Vec<RoleId>,
+    /// The association before the change, as shown in the log.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub old: Option<String>,
+    /// The association after the change, as shown in the log.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub new: Option<String>,
+    /// Unix seconds.
+    pub at: i64,
+}
+
+/// A pending request to join approval-gated roles, posted to the moderator channel.
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct RoleRequest {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    pub server: GuildId,
+    #[serde(with = "shim::Required")]
+    pub user: UserId,
+    #[serde(with = "shim::Many")]
+    pub roles: Vec<RoleId>,
+    /// Where the member asked.
+    #[serde(with = "shim::Required")]
+    pub channel: ChannelId,
+    /// The request as posted in the moderator channel.
+    #[serde(with = "shim::Required")]
+    pub moderator_channel: ChannelId,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub message: MessageId,
+    /// Seconds the roles are held once approved, or indefinitely when absent.
+    #[serde(default, skip_serializing_if="Option::is_none")]
+    pub duration: Option<i64>,
+    /// Unix seconds.
+    pub requested_at: i64,
+}
+
+impl TypeMapKey for RoleRequest {
+    type Value = std::collections::HashSet<MessageId>;
+}
+
+/// A role joined for a limited time, removed by the bot once it expires.
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct RoleExpiry {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub server: GuildId,
+    #[serde(with = "shim::Required")]
+    pub user: UserId,
+    #[serde(with = "shim::Required")]
+    pub role: RoleId,
+    /// Unix seconds at which the role is removed.
+    #[model(index(index="asc"))]
+    pub expires_at: i64,
+    /// Unix seconds at which the member is warned by DM.
+    #[model(index(index="asc"))]
+    pub warn_at: i64,
+    #[serde(default)]
+    pub warned: bool,
+}
+
+#[derive(Model, Deserialize, Serialize, Debug)]
+pub struct MemberActivity {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub server: GuildId,
+    #[serde(with = "shim::Required")]
+    pub user: UserId,
+    /// Unix seconds, recorded at a resolution of `status::ACTIVITY_RESOLUTION`.
+    pub last_message: i64,
 }
 
 #[derive(Model, Deserialize, Serialize, Debug)]
@@ -100,6 +291,17 @@ pub struct RPGChannel {
     pub channel: ChannelId,
 }
 
+#[derive(Model, Deserialize, Serialize, Debug)]
+#[cfg(feature = "rpg")]
+pub struct RPGRewindLimit {
+    #[serde(rename="_id", skip_serializing_if="Option::is_none")]
+    pub id: Option<ObjectId>,
+    #[serde(with = "shim::Required")]
+    #[model(index(index="hashed"))]
+    pub server: GuildId,
+    pub limit: i32,
+}
+
 #[derive(Model, Deserialize, Serialize, Debug)]
 #[cfg(feature = "rpg")]
 pub struct RPGState {
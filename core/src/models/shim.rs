@@ -52,6 +52,30 @@ impl Optional {
     }
 }
 
+pub struct Many;
+impl Many {
+    pub fn serialize<S, T>(values: &[T], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Copy + Into<u64>,
+    {
+        serializer.collect_seq(values.iter().map(|value| Required::from(T::into(*value))))
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        u64: Into<T>,
+    {
+        <Vec<Required> as Deserialize>::deserialize(deserializer)
+            .map(|values| values
+                .into_iter()
+                .map(<Required as Into<u64>>::into)
+                .map(<u64 as Into<T>>::into)
+                .collect())
+    }
+}
+
 #[derive(Copy, Clone, Deserialize, Serialize)]
 pub struct Required(i32, i32);
 
@@ -136,3 +160,50 @@ implement!(
     UserId;
     MessageId;
 );
+
+#[cfg(test)]
+mod tests {
+    use serde::{
+        Deserialize,
+        Serialize,
+    };
+    use wither::bson::{
+        self,
+        doc,
+    };
+
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Ids {
+        #[serde(with = "super::Required")]
+        user: UserId,
+        #[serde(default, with = "super::Optional")]
+        channel: Option<ChannelId>,
+        #[serde(with = "super::Many")]
+        roles: Vec<RoleId>,
+    }
+
+    #[test]
+    fn ids_round_trip_through_documents() -> Result<(), Box<dyn std::error::Error>> {
+        for ids in vec![
+            Ids { user: UserId(u64::MAX), channel: Some(ChannelId(1 << 63)), roles: vec![RoleId(0), RoleId(81_384_788_765_712_384)] },
+            Ids { user: UserId(1), channel: None, roles: Vec::new() },
+        ] {
+            let document = bson::to_bson(&ids)?;
+            assert_eq!(bson::from_bson::<Ids>(document)?, ids);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn ids_are_stored_as_signed_halves() -> Result<(), Box<dyn std::error::Error>> {
+        let ids = Ids { user: UserId(u64::MAX), channel: None, roles: vec![RoleId(1), RoleId(1 << 32)] };
+        assert_eq!(bson::to_bson(&ids)?, Bson::Document(doc!{
+            "user": [-1, -1],
+            "channel": null,
+            "roles": [[0, 1], [1, 0]],
+        }));
+        Ok(())
+    }
+}
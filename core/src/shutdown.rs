@@ -0,0 +1,119 @@
+#[cfg(feature = "rpg")]
+use std::time::{
+    Duration,
+    Instant,
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    prelude::*,
+};
+use tokio::sync::mpsc::{
+    UnboundedReceiver,
+    UnboundedSender,
+};
+#[cfg(feature = "rpg")]
+use tokio::time::delay_for;
+
+/// How long in-flight RPG moves have to finish before the bot exits regardless.
+#[cfg(feature = "rpg")]
+const DRAIN_GRACE: Duration = Duration::from_secs(60);
+#[cfg(feature = "rpg")]
+const DRAIN_POLL: Duration = Duration::from_millis(250);
+/// The exit status asking whatever runs the bot to start it again.
+pub const RESTART_STATUS: i32 = 75;
+
+/// Why the bot is stopping.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Exit {
+    Shutdown,
+    Restart,
+    /// The client stopped by itself.
+    Failed,
+}
+
+impl Exit {
+    pub fn status(self) -> i32 {
+        match self {
+            Exit::Shutdown => 0,
+            Exit::Restart => RESTART_STATUS,
+            Exit::Failed => 1,
+        }
+    }
+}
+
+/// Requests the bot stop, from a command.
+pub struct Shutdown;
+
+impl TypeMapKey for Shutdown {
+    type Value = UnboundedSender<Exit>;
+}
+
+/// Waits for SIGINT or SIGTERM, or for a command to ask.
+pub async fn requested(receiver: &mut UnboundedReceiver<Exit>) -> Exit {
+    tokio::select! {
+        exit = receiver.recv() => exit.unwrap_or(Exit::Shutdown),
+        () = terminated() => Exit::Shutdown,
+    }
+}
+
+#[cfg(unix)]
+async fn terminated() {
+    use tokio::signal::unix::{
+        signal,
+        SignalKind,
+    };
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = terminate.recv() => {},
+    }
+}
+
+#[cfg(not(unix))]
+async fn terminated() {
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+/// Asks the bot to stop; it exits once the shutdown is complete.
+pub async fn request(ctx: &Context, exit: Exit) -> CommandResult {
+    ctx.data
+        .read()
+        .await
+        .get::<Shutdown>()
+        .ok_or("Shutdown not present")?
+        .send(exit)
+        .map_err(|_| "Already shutting down")?;
+    Ok(())
+}
+
+/// Refuses new RPG moves, then waits for those in flight to finish.
+///
+/// Moves are saved before they leave the lockout, so once it is empty every state has been saved.
+#[cfg(feature = "rpg")]
+pub async fn drain(data: &RwLock<TypeMap>) -> CommandResult {
+    let data = data.read().await;
+    let states = match data.get::<crate::models::RPGState>() {
+        Some(states) => states,
+        None => return Ok(()),
+    };
+    states.lock().await.closing = true;
+
+    let deadline = Instant::now() + DRAIN_GRACE;
+    loop {
+        let remaining = states.lock().await.lockout.len();
+        if remaining == 0 {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(format!("{} RPG moves still in flight", remaining).into());
+        }
+        delay_for(DRAIN_POLL).await;
+    }
+}
+
+#[cfg(not(feature = "rpg"))]
+pub async fn drain(_data: &RwLock<TypeMap>) -> CommandResult {
+    Ok(())
+}
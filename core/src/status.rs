@@ -0,0 +1,412 @@
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    fmt::{
+        Display,
+        Formatter,
+    },
+    str::FromStr,
+    sync::{
+        atomic::{
+            AtomicBool,
+            Ordering,
+        },
+        Arc,
+        Mutex as StdMutex,
+    },
+    time::{
+        Duration,
+        SystemTime,
+        UNIX_EPOCH,
+    },
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+use tokio::time::delay_for;
+
+use crate::{
+    models::{
+        MemberActivity,
+        RoleStatus,
+        StatusRule,
+    },
+    storage::{
+        Storage,
+        StorageKey,
+    },
+    util::Mentionable,
+};
+
+/// How often every cached member is checked against the status rules.
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Seconds between writes of a member's activity; messages in between are not recorded.
+pub const ACTIVITY_RESOLUTION: i64 = 60 * 60;
+const DAY: i64 = 24 * 60 * 60;
+
+/// Present in the client data only while status roles are enabled.
+#[derive(Default)]
+pub struct StatusRoles {
+    written: StdMutex<HashMap<(GuildId, UserId), i64>>,
+    sweeping: AtomicBool,
+}
+
+impl TypeMapKey for StatusRoles {
+    type Value = Arc<StatusRoles>;
+}
+
+impl StatusRoles {
+    /// Forgets writes old enough that the member's next message is recorded anyway.
+    fn prune(&self, now: i64) {
+        self.written
+            .lock()
+            .unwrap()
+            .retain(|_, last| now.saturating_sub(*last) < ACTIVITY_RESOLUTION);
+    }
+}
+
+/// A role the rules say a member should gain or lose.
+#[derive(Debug, Clone, Copy)]
+pub struct Change {
+    pub user: UserId,
+    pub role: RoleId,
+    pub add: bool,
+}
+
+struct Facts {
+    groups: usize,
+    last_message: Option<i64>,
+    now: i64,
+}
+
+/// Some(true) to assign, Some(false) to remove, None to leave the role alone.
+///
+/// Removal wins, so an inactive member loses the role until they are active again.
+fn evaluate(rules: &[StatusRule], facts: &Facts) -> Option<bool> {
+    let mut verdict = None;
+    for rule in rules {
+        match *rule {
+            StatusRule::JoinedGroups { count } =>
+                if facts.groups as i64 >= count as i64 {
+                    verdict = Some(true);
+                },
+            StatusRule::FirstMessage =>
+                if facts.last_message.is_some() {
+                    verdict = Some(true);
+                },
+            StatusRule::Inactive { days } =>
+                // Members never seen are unknown rather than inactive
+                if let Some(last) = facts.last_message {
+                    if facts.now - last >= days as i64 * DAY {
+                        return Some(false);
+                    }
+                },
+        }
+    }
+    verdict
+}
+
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("<1970 not supported")
+        .as_secs() as i64
+}
+
+/// Status roles of the server, including older records without a server whose role is in it.
+pub async fn guild_statuses(ctx: &Context, storage: &dyn Storage, guild: GuildId) -> CommandResult<Vec<RoleStatus>> {
+    let roles = ctx.cache
+        .guild_field(guild, |cached| cached.roles.keys().copied().collect())
+        .await
+        .unwrap_or_default();
+    storage.guild_role_statuses(guild, roles).await
+}
+
+/// When each member of the server last spoke, for planning the whole server at once.
+pub async fn guild_activity(storage: &dyn Storage, guild: GuildId) -> CommandResult<HashMap<UserId, i64>> {
+    Ok(storage
+        .guild_activity(guild)
+        .await?
+        .into_iter()
+        .map(|activity| (activity.user, activity.last_message))
+        .collect())
+}
+
+/// The changes the rules call for among the members, given when they last spoke, without applying them.
+pub async fn plan(
+    ctx: &Context,
+    storage: &dyn Storage,
+    guild: GuildId,
+    members: &[Member],
+    activity: &HashMap<UserId, i64>,
+) -> CommandResult<Vec<Change>> {
+    let statuses = guild_statuses(ctx, storage, guild).await?;
+    if statuses.iter().all(|status| status.rules.is_empty()) {
+        return Ok(Vec::new());
+    }
+    let channels = ctx.cache
+        .guild_field(guild, |cached| cached.channels.keys().copied().collect())
+        .await
+        .unwrap_or_default();
+    let groups: HashSet<RoleId> = storage
+        .guild_role_associations(channels, guild)
+        .await?
+        .into_iter()
+        .map(|association| association.role)
+        .collect();
+
+    let now = now();
+    let mut changes = Vec::new();
+    for member in members {
+        if member.user.bot {
+            continue;
+        }
+        let facts = Facts {
+            groups: member.roles.iter().filter(|role| groups.contains(role)).count(),
+            last_message: activity.get(&member.user.id).copied(),
+            now,
+        };
+        for status in &statuses {
+            let has = member.roles.contains(&status.role);
+            match evaluate(&status.rules, &facts) {
+                Some(true) if !has => changes.push(Change { user: member.user.id, role: status.role, add: true }),
+                Some(false) if has => changes.push(Change { user: member.user.id, role: status.role, add: false }),
+                _ => {},
+            }
+        }
+    }
+    Ok(changes)
+}
+
+/// Makes every change it can, reporting each one that fails rather than stopping there.
+pub async fn apply(ctx: &Context, guild: GuildId, changes: &[Change]) {
+    for change in changes {
+        let result = if change.add {
+            ctx.http.add_member_role(guild.0, change.user.0, change.role.0).await
+        } else {
+            ctx.http.remove_member_role(guild.0, change.user.0, change.role.0).await
+        };
+        crate::errors::report(
+            ctx,
+            "Status_Apply",
+            result.map_err(|e| format!("{}: {}", change, e).into()),
+        ).await;
+    }
+}
+
+async fn load(ctx: &Context) -> Option<(Arc<StatusRoles>, Arc<dyn Storage>)> {
+    let data = ctx.data.read().await;
+    Some((data.get::<StatusRoles>()?.clone(), data.get::<StorageKey>()?.clone()))
+}
+
+/// Records the message as activity, then re-evaluates its author.
+pub async fn message(ctx: &Context, msg: &Message) -> CommandResult {
+    let guild = if let Some(guild) = msg.guild_id {
+        guild
+    } else {
+        return Ok(());
+    };
+    if msg.author.bot {
+        return Ok(());
+    }
+    let (status_roles, storage) = if let Some(loaded) = load(ctx).await {
+        loaded
+    } else {
+        return Ok(());
+    };
+
+    let now = now();
+    {
+        let mut written = status_roles.written.lock().unwrap();
+        let last = written.entry((guild, msg.author.id)).or_insert(i64::MIN);
+        if now.saturating_sub(*last) < ACTIVITY_RESOLUTION {
+            return Ok(());
+        }
+        *last = now;
+    }
+    let mut activity = storage
+        .member_activity(guild, msg.author.id)
+        .await?
+        .unwrap_or(MemberActivity {
+            id: None,
+            server: guild,
+            user: msg.author.id,
+            last_message: now,
+        });
+    activity.last_message = now;
+    storage.save_member_activity(&mut activity).await?;
+
+    let member = guild.member(ctx, msg.author.id).await?;
+    let activity = std::iter::once((msg.author.id, now)).collect();
+    let changes = plan(ctx, &*storage, guild, &[member], &activity).await?;
+    apply(ctx, guild, &changes).await;
+    Ok(())
+}
+
+/// Re-evaluates a member whose roles may have changed.
+pub async fn member_update(ctx: &Context, member: &Member) -> CommandResult {
+    let (_, storage) = if let Some(loaded) = load(ctx).await {
+        loaded
+    } else {
+        return Ok(());
+    };
+    let activity = storage
+        .member_activity(member.guild_id, member.user.id)
+        .await?
+        .map(|activity| (activity.user, activity.last_message))
+        .into_iter()
+        .collect();
+    let changes = plan(ctx, &*storage, member.guild_id, std::slice::from_ref(member), &activity).await?;
+    apply(ctx, member.guild_id, &changes).await;
+    Ok(())
+}
+
+/// Starts the periodic sweep of every cached member, once.
+pub async fn ready(ctx: &Context) -> CommandResult {
+    let (status_roles, storage) = if let Some(loaded) = load(ctx).await {
+        loaded
+    } else {
+        return Ok(());
+    };
+    if status_roles.sweeping.swap(true, Ordering::SeqCst) {
+        return Ok(());
+    }
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        loop {
+            status_roles.prune(now());
+            for guild in ctx.cache.guilds().await {
+                crate::errors::report(&ctx, "Status_Sweep", sweep(&ctx, &*storage, guild).await).await;
+            }
+            delay_for(SWEEP_INTERVAL).await;
+        }
+    });
+    Ok(())
+}
+
+async fn sweep(ctx: &Context, storage: &dyn Storage, guild: GuildId) -> CommandResult {
+    let members: Vec<Member> = match ctx.cache.guild_field(guild, |guild| guild.members.values().cloned().collect()).await {
+        Some(members) => members,
+        None => return Ok(()),
+    };
+    let activity = guild_activity(storage, guild).await?;
+    let changes = plan(ctx, storage, guild, &members, &activity).await?;
+    apply(ctx, guild, &changes).await;
+    Ok(())
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            Mentionable::from(self.user),
+            if self.add { "gains" } else { "loses" },
+            Mentionable::from(self.role),
+        )
+    }
+}
+
+pub struct RuleDisplay<'a>(pub &'a StatusRule);
+
+impl Display for RuleDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            StatusRule::JoinedGroups { count } =>
+                write!(f, "assign after joining {} groups", count),
+            StatusRule::FirstMessage =>
+                write!(f, "assign on first message"),
+            StatusRule::Inactive { days } =>
+                write!(f, "remove after {} days inactive", days),
+        }
+    }
+}
+
+/// Parses `groups <count>`, `first_message`, or `inactive <days>`.
+pub struct ParsedRule(pub StatusRule);
+
+impl FromStr for ParsedRule {
+    type Err = &'static str;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut words = value.split_whitespace();
+        let rule = match (words.next(), words.next().map(str::parse::<i32>)) {
+            (Some("groups"), Some(Ok(count))) if count > 0 =>
+                StatusRule::JoinedGroups { count },
+            (Some("first_message"), None) =>
+                StatusRule::FirstMessage,
+            (Some("inactive"), Some(Ok(days))) if days > 0 =>
+                StatusRule::Inactive { days },
+            _ => return Err("Unrecognized rule"),
+        };
+        if words.next().is_some() {
+            return Err("Unrecognized rule");
+        }
+        Ok(ParsedRule(rule))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_600_000_000;
+
+    #[test]
+    fn pruning_forgets_only_writes_past_the_resolution() {
+        let status_roles = StatusRoles::default();
+        {
+            let mut written = status_roles.written.lock().unwrap();
+            written.insert((GuildId(1), UserId(1)), NOW - ACTIVITY_RESOLUTION);
+            written.insert((GuildId(1), UserId(2)), NOW - ACTIVITY_RESOLUTION + 1);
+        }
+        status_roles.prune(NOW);
+        let written = status_roles.written.lock().unwrap();
+        assert_eq!(written.keys().collect::<Vec<_>>(), vec![&(GuildId(1), UserId(2))]);
+    }
+
+    fn facts(groups: usize, last_message: Option<i64>) -> Facts {
+        Facts {
+            groups,
+            last_message,
+            now: NOW,
+        }
+    }
+
+    #[test]
+    fn roles_are_assigned_once_a_rule_is_met() {
+        let rules = [StatusRule::JoinedGroups { count: 2 }];
+        assert_eq!(evaluate(&rules, &facts(1, None)), None);
+        assert_eq!(evaluate(&rules, &facts(2, None)), Some(true));
+
+        let rules = [StatusRule::FirstMessage];
+        assert_eq!(evaluate(&rules, &facts(0, None)), None);
+        assert_eq!(evaluate(&rules, &facts(0, Some(NOW))), Some(true));
+    }
+
+    #[test]
+    fn inactivity_removes_the_role_over_other_rules() {
+        let rules = [StatusRule::FirstMessage, StatusRule::Inactive { days: 7 }];
+        assert_eq!(evaluate(&rules, &facts(0, Some(NOW - 6 * DAY))), Some(true));
+        assert_eq!(evaluate(&rules, &facts(0, Some(NOW - 7 * DAY))), Some(false));
+        // Members never seen are left alone
+        assert_eq!(evaluate(&[StatusRule::Inactive { days: 7 }], &facts(0, None)), None);
+    }
+
+    #[test]
+    fn rules_parse_from_their_descriptions() {
+        let parse = |rule: &str| rule.parse::<ParsedRule>().map(|ParsedRule(rule)| RuleDisplay(&rule).to_string());
+        assert_eq!(parse("groups 3"), Ok("assign after joining 3 groups".to_string()));
+        assert_eq!(parse("first_message"), Ok("assign on first message".to_string()));
+        assert_eq!(parse("inactive 30"), Ok("remove after 30 days inactive".to_string()));
+        assert!(parse("groups 0").is_err());
+        assert!(parse("first_message 2").is_err());
+        assert!(parse("inactive").is_err());
+    }
+}
@@ -0,0 +1,1073 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    sync::{
+        Arc,
+        Mutex as StdMutex,
+    },
+};
+
+use futures::TryStreamExt;
+use serenity::{
+    async_trait,
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+use tracing::instrument;
+use wither::{
+    bson::{
+        doc,
+        oid::ObjectId,
+        Document,
+    },
+    mongodb::Database,
+    Model,
+};
+
+use crate::{
+    models::{
+        AuditEvent,
+        DiscordCredentials,
+        ExclusiveGroup,
+        GuildConfig,
+        MemberActivity,
+        RoleAssociation,
+        RoleExpiry,
+        RoleRequest,
+        RoleMenu,
+        RoleStatus,
+        Shim,
+        System,
+    },
+    DatabaseHandle,
+};
+#[cfg(feature = "rpg")]
+use ohg_bot_headers::{
+    Documents,
+    Error as DocumentError,
+    MemoryDocuments,
+};
+#[cfg(feature = "rpg")]
+use crate::models::{
+    RPGChannel,
+    RPGRewindLimit,
+    RPGState,
+};
+
+/// Every query the bot makes, so commands can run against MongoDB or memory alike.
+#[async_trait]
+pub trait Storage: Send + Sync {
+    async fn credentials(&self) -> CommandResult<Option<DiscordCredentials>>;
+
+    async fn save_credentials(&self, credentials: &mut DiscordCredentials) -> CommandResult;
+
+    /// Associations for the channel, and the generic association for the server.
+    async fn role_associations(&self, channel: ChannelId, guild: GuildId) -> CommandResult<Vec<RoleAssociation>>;
+
+    /// Associations for any of the channels, and the generic association for the server.
+    async fn guild_role_associations(&self, channels: Vec<ChannelId>, guild: GuildId) -> CommandResult<Vec<RoleAssociation>>;
+
+    async fn save_role_association(&self, association: &mut RoleAssociation) -> CommandResult;
+
+    /// Every association of the role; role IDs are unique, so these all belong to one server.
+    async fn role_associations_of(&self, role: RoleId) -> CommandResult<Vec<RoleAssociation>>;
+
+    /// Associations of the server, of any of the channels, or of any of the roles.
+    ///
+    /// A channel association carries no server, so one of a deleted channel is only found through its role.
+    async fn server_role_associations(&self, guild: GuildId, channels: Vec<ChannelId>, roles: Vec<RoleId>) -> CommandResult<Vec<RoleAssociation>>;
+
+    async fn delete_role_association(&self, association: &RoleAssociation) -> CommandResult;
+
+    async fn role_statuses(&self) -> CommandResult<Vec<RoleStatus>>;
+
+    /// Status roles of the server, and older ones without a server whose role is among the roles.
+    async fn guild_role_statuses(&self, guild: GuildId, roles: Vec<RoleId>) -> CommandResult<Vec<RoleStatus>>;
+
+    async fn save_role_status(&self, status: &mut RoleStatus) -> CommandResult;
+
+    async fn role_menus(&self) -> CommandResult<Vec<RoleMenu>>;
+
+    async fn role_menu(&self, message: MessageId) -> CommandResult<Option<RoleMenu>>;
+
+    async fn save_role_menu(&self, menu: &mut RoleMenu) -> CommandResult;
+
+    async fn exclusive_groups(&self, guild: GuildId) -> CommandResult<Vec<ExclusiveGroup>>;
+
+    async fn save_exclusive_group(&self, group: &mut ExclusiveGroup) -> CommandResult;
+
+    async fn delete_exclusive_group(&self, group: &ExclusiveGroup) -> CommandResult;
+
+    async fn guild_configs(&self) -> CommandResult<Vec<GuildConfig>>;
+
+    async fn guild_config(&self, guild: GuildId) -> CommandResult<Option<GuildConfig>>;
+
+    async fn save_guild_config(&self, config: &mut GuildConfig) -> CommandResult;
+
+    /// Events of the server made by the user, or changing their roles, newest first from `skip`.
+    ///
+    /// Also returns how many there are in all.
+    async fn user_audit_events(&self, guild: GuildId, user: UserId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)>;
+
+    /// Events of the server involving the role, newest first from `skip`.
+    ///
+    /// Also returns how many there are in all.
+    async fn role_audit_events(&self, guild: GuildId, role: RoleId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)>;
+
+    async fn save_audit_event(&self, event: &mut AuditEvent) -> CommandResult;
+
+    /// Pending requests of the server, or of every server.
+    async fn role_requests(&self, guild: Option<GuildId>) -> CommandResult<Vec<RoleRequest>>;
+
+    async fn role_request(&self, message: MessageId) -> CommandResult<Option<RoleRequest>>;
+
+    async fn save_role_request(&self, request: &mut RoleRequest) -> CommandResult;
+
+    async fn delete_role_request(&self, request: &RoleRequest) -> CommandResult;
+
+    /// Expiries of every server that are due to be removed, or whose members are due a warning.
+    async fn due_role_expiries(&self, now: i64) -> CommandResult<Vec<RoleExpiry>>;
+
+    async fn role_expiry(&self, guild: GuildId, user: UserId, role: RoleId) -> CommandResult<Option<RoleExpiry>>;
+
+    /// Expiries of the role, whoever holds it.
+    async fn role_expiries_of(&self, guild: GuildId, role: RoleId) -> CommandResult<Vec<RoleExpiry>>;
+
+    async fn save_role_expiry(&self, expiry: &mut RoleExpiry) -> CommandResult;
+
+    async fn delete_role_expiry(&self, expiry: &RoleExpiry) -> CommandResult;
+
+    async fn member_activity(&self, guild: GuildId, user: UserId) -> CommandResult<Option<MemberActivity>>;
+
+    async fn guild_activity(&self, guild: GuildId) -> CommandResult<Vec<MemberActivity>>;
+
+    async fn save_member_activity(&self, activity: &mut MemberActivity) -> CommandResult;
+
+    /// Systems of the server, or of every server.
+    async fn systems(&self, guild: Option<GuildId>) -> CommandResult<Vec<System>>;
+
+    async fn system(&self, guild: GuildId, name: &str) -> CommandResult<Option<System>>;
+
+    /// Where game states keep what they load lazily, beside the states themselves.
+    #[cfg(feature = "rpg")]
+    fn rpg_documents(&self) -> &dyn Documents;
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_channels(&self) -> CommandResult<Vec<RPGChannel>>;
+
+    #[cfg(feature = "rpg")]
+    async fn save_rpg_channel(&self, channel: &mut RPGChannel) -> CommandResult;
+
+    /// The state of the game on the message at the iteration, or at its latest iteration.
+    #[cfg(feature = "rpg")]
+    async fn rpg_state(&self, message: MessageId, iteration: Option<i32>) -> CommandResult<Option<RPGState>>;
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_state_by_id(&self, id: &ObjectId) -> CommandResult<Option<RPGState>>;
+
+    /// Every iteration of the game on the message, in no particular order.
+    #[cfg(feature = "rpg")]
+    async fn rpg_states(&self, message: MessageId) -> CommandResult<Vec<RPGState>>;
+
+    #[cfg(feature = "rpg")]
+    async fn save_rpg_state(&self, state: &mut RPGState) -> CommandResult;
+
+    #[cfg(feature = "rpg")]
+    async fn rewind_limit(&self, guild: GuildId) -> CommandResult<Option<RPGRewindLimit>>;
+
+    #[cfg(feature = "rpg")]
+    async fn save_rewind_limit(&self, limit: &mut RPGRewindLimit) -> CommandResult;
+}
+
+pub struct StorageKey;
+
+impl TypeMapKey for StorageKey {
+    type Value = Arc<dyn Storage>;
+}
+
+pub struct MongoStorage {
+    base: Database,
+    #[cfg(feature = "rpg")]
+    rpg: Database,
+    #[cfg(feature = "rpg")]
+    documents: TracedDocuments,
+}
+
+impl MongoStorage {
+    pub fn new(handle: &DatabaseHandle) -> Self {
+        MongoStorage {
+            base: handle.base.clone(),
+            #[cfg(feature = "rpg")]
+            rpg: handle.rpg.clone(),
+            #[cfg(feature = "rpg")]
+            documents: TracedDocuments(handle.rpg.clone()),
+        }
+    }
+}
+
+/// The RPG database as games see it, so their lazy loads and saves are traced like every other query.
+#[cfg(feature = "rpg")]
+struct TracedDocuments(Database);
+
+#[cfg(feature = "rpg")]
+#[async_trait]
+impl Documents for TracedDocuments {
+    #[instrument(level = "debug", skip(self, id))]
+    async fn find_document(&self, collection: &str, id: &ObjectId)
+        -> Result<Option<Document>, DocumentError>
+    {
+        self.0.find_document(collection, id).await
+    }
+
+    #[instrument(level = "debug", skip(self, id, document))]
+    async fn save_document(&self, collection: &str, id: &ObjectId, document: Document)
+        -> Result<(), DocumentError>
+    {
+        self.0.save_document(collection, id, document).await
+    }
+
+    fn database(&self) -> Option<&Database> {
+        Some(&self.0)
+    }
+}
+
+async fn find_all<T: Model>(db: &Database, filter: Option<Document>) -> CommandResult<Vec<T>> {
+    T::find(db, filter, None)
+        .await?
+        .try_collect()
+        .await
+        .map_err(Into::into)
+}
+
+/// Only the page is read, as the audit log grows for as long as the bot runs.
+async fn find_audit_page(db: &Database, filter: Document, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)> {
+    use wither::mongodb::options::FindOptions;
+
+    let total = AuditEvent::collection(db)
+        .count_documents(filter.clone(), None)
+        .await?;
+    let mut options = FindOptions::default();
+    options.sort = Some(doc!{
+        "at": -1,
+    });
+    options.skip = Some(i64::try_from(skip).unwrap_or(i64::MAX));
+    options.limit = Some(i64::try_from(limit).unwrap_or(i64::MAX));
+    let events = AuditEvent::find(db, Some(filter), Some(options))
+        .await?
+        .try_collect()
+        .await?;
+    Ok((events, total as usize))
+}
+
+// Each query runs in a debug span under the command or event making it, timed as it closes
+#[async_trait]
+impl Storage for MongoStorage {
+    #[instrument(level = "debug", skip(self))]
+    async fn credentials(&self) -> CommandResult<Option<DiscordCredentials>> {
+        Ok(DiscordCredentials::find_one(&self.base, None, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, credentials))]
+    async fn save_credentials(&self, credentials: &mut DiscordCredentials) -> CommandResult {
+        Ok(credentials.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_associations(&self, channel: ChannelId, guild: GuildId) -> CommandResult<Vec<RoleAssociation>> {
+        find_all(&self.base, Some(doc!{
+            "$or": [
+                { "channel": doc!{ "$eq": &Shim::from(channel) } },
+                { "server": doc!{ "$eq": &Shim::from(guild) } },
+            ],
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, channels))]
+    async fn guild_role_associations(&self, channels: Vec<ChannelId>, guild: GuildId) -> CommandResult<Vec<RoleAssociation>> {
+        let channels: Vec<Shim> = channels
+            .into_iter()
+            .map(Shim::from)
+            .collect();
+        find_all(&self.base, Some(doc!{
+            "$or": [
+                { "channel": doc!{ "$in": channels } },
+                { "server": doc!{ "$eq": &Shim::from(guild) } },
+            ],
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, association))]
+    async fn save_role_association(&self, association: &mut RoleAssociation) -> CommandResult {
+        Ok(association.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_associations_of(&self, role: RoleId) -> CommandResult<Vec<RoleAssociation>> {
+        find_all(&self.base, Some(doc!{
+            "role": doc!{ "$eq": &Shim::from(role) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, channels, roles))]
+    async fn server_role_associations(&self, guild: GuildId, channels: Vec<ChannelId>, roles: Vec<RoleId>) -> CommandResult<Vec<RoleAssociation>> {
+        let channels: Vec<Shim> = channels
+            .into_iter()
+            .map(Shim::from)
+            .collect();
+        let roles: Vec<Shim> = roles
+            .into_iter()
+            .map(Shim::from)
+            .collect();
+        find_all(&self.base, Some(doc!{
+            "$or": [
+                { "server": doc!{ "$eq": &Shim::from(guild) } },
+                { "channel": doc!{ "$in": channels } },
+                { "role": doc!{ "$in": roles } },
+            ],
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, association))]
+    async fn delete_role_association(&self, association: &RoleAssociation) -> CommandResult {
+        association.delete(&self.base).await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_statuses(&self) -> CommandResult<Vec<RoleStatus>> {
+        find_all(&self.base, None).await
+    }
+
+    #[instrument(level = "debug", skip(self, roles))]
+    async fn guild_role_statuses(&self, guild: GuildId, roles: Vec<RoleId>) -> CommandResult<Vec<RoleStatus>> {
+        let roles: Vec<Shim> = roles
+            .into_iter()
+            .map(Shim::from)
+            .collect();
+        find_all(&self.base, Some(doc!{
+            "$or": [
+                { "server": doc!{ "$eq": &Shim::from(guild) } },
+                {
+                    "server": doc!{ "$exists": false },
+                    "role": doc!{ "$in": roles },
+                },
+            ],
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, status))]
+    async fn save_role_status(&self, status: &mut RoleStatus) -> CommandResult {
+        Ok(status.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_menus(&self) -> CommandResult<Vec<RoleMenu>> {
+        find_all(&self.base, None).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_menu(&self, message: MessageId) -> CommandResult<Option<RoleMenu>> {
+        Ok(RoleMenu::find_one(
+            &self.base,
+            Some(doc!{
+                "message": doc!{ "$eq": &Shim::from(message) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, menu))]
+    async fn save_role_menu(&self, menu: &mut RoleMenu) -> CommandResult {
+        Ok(menu.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn exclusive_groups(&self, guild: GuildId) -> CommandResult<Vec<ExclusiveGroup>> {
+        find_all(&self.base, Some(doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, group))]
+    async fn save_exclusive_group(&self, group: &mut ExclusiveGroup) -> CommandResult {
+        Ok(group.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, group))]
+    async fn delete_exclusive_group(&self, group: &ExclusiveGroup) -> CommandResult {
+        group.delete(&self.base).await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn guild_configs(&self) -> CommandResult<Vec<GuildConfig>> {
+        find_all(&self.base, None).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn guild_config(&self, guild: GuildId) -> CommandResult<Option<GuildConfig>> {
+        Ok(GuildConfig::find_one(
+            &self.base,
+            Some(doc!{
+                "server": doc!{ "$eq": &Shim::from(guild) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, config))]
+    async fn save_guild_config(&self, config: &mut GuildConfig) -> CommandResult {
+        Ok(config.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn user_audit_events(&self, guild: GuildId, user: UserId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)> {
+        find_audit_page(&self.base, doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+            "$or": [
+                { "user": doc!{ "$eq": &Shim::from(user) } },
+                { "member": doc!{ "$eq": &Shim::from(user) } },
+            ],
+        }, skip, limit).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_audit_events(&self, guild: GuildId, role: RoleId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)> {
+        find_audit_page(&self.base, doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+            "roles": doc!{ "$elemMatch": doc!{ "$eq": &Shim::from(role) } },
+        }, skip, limit).await
+    }
+
+    #[instrument(level = "debug", skip(self, event))]
+    async fn save_audit_event(&self, event: &mut AuditEvent) -> CommandResult {
+        Ok(event.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_requests(&self, guild: Option<GuildId>) -> CommandResult<Vec<RoleRequest>> {
+        find_all(&self.base, guild.map(|guild| doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_request(&self, message: MessageId) -> CommandResult<Option<RoleRequest>> {
+        Ok(RoleRequest::find_one(
+            &self.base,
+            Some(doc!{
+                "message": doc!{ "$eq": &Shim::from(message) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, request))]
+    async fn save_role_request(&self, request: &mut RoleRequest) -> CommandResult {
+        Ok(request.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, request))]
+    async fn delete_role_request(&self, request: &RoleRequest) -> CommandResult {
+        request.delete(&self.base).await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn due_role_expiries(&self, now: i64) -> CommandResult<Vec<RoleExpiry>> {
+        find_all(&self.base, Some(doc!{
+            "$or": [
+                { "expires_at": doc!{ "$lte": now } },
+                { "warned": doc!{ "$ne": true }, "warn_at": doc!{ "$lte": now } },
+            ],
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_expiry(&self, guild: GuildId, user: UserId, role: RoleId) -> CommandResult<Option<RoleExpiry>> {
+        Ok(RoleExpiry::find_one(
+            &self.base,
+            Some(doc!{
+                "server": doc!{ "$eq": &Shim::from(guild) },
+                "user": doc!{ "$eq": &Shim::from(user) },
+                "role": doc!{ "$eq": &Shim::from(role) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn role_expiries_of(&self, guild: GuildId, role: RoleId) -> CommandResult<Vec<RoleExpiry>> {
+        find_all(&self.base, Some(doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+            "role": doc!{ "$eq": &Shim::from(role) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, expiry))]
+    async fn save_role_expiry(&self, expiry: &mut RoleExpiry) -> CommandResult {
+        Ok(expiry.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self, expiry))]
+    async fn delete_role_expiry(&self, expiry: &RoleExpiry) -> CommandResult {
+        expiry.delete(&self.base).await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn member_activity(&self, guild: GuildId, user: UserId) -> CommandResult<Option<MemberActivity>> {
+        Ok(MemberActivity::find_one(
+            &self.base,
+            Some(doc!{
+                "server": doc!{ "$eq": &Shim::from(guild) },
+                "user": doc!{ "$eq": &Shim::from(user) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn guild_activity(&self, guild: GuildId) -> CommandResult<Vec<MemberActivity>> {
+        find_all(&self.base, Some(doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self, activity))]
+    async fn save_member_activity(&self, activity: &mut MemberActivity) -> CommandResult {
+        Ok(activity.save(&self.base, None).await?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn systems(&self, guild: Option<GuildId>) -> CommandResult<Vec<System>> {
+        find_all(&self.base, guild.map(|guild| doc!{
+            "server": doc!{ "$eq": &Shim::from(guild) },
+        })).await
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn system(&self, guild: GuildId, name: &str) -> CommandResult<Option<System>> {
+        Ok(System::find_one(
+            &self.base,
+            Some(doc!{
+                "server": doc!{ "$eq": &Shim::from(guild) },
+                "sub_system.name": doc!{ "$eq": name },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    fn rpg_documents(&self) -> &dyn Documents {
+        &self.documents
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self))]
+    async fn rpg_channels(&self) -> CommandResult<Vec<RPGChannel>> {
+        find_all(&self.base, None).await
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self, channel))]
+    async fn save_rpg_channel(&self, channel: &mut RPGChannel) -> CommandResult {
+        Ok(channel.save(&self.base, None).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self))]
+    async fn rpg_state(&self, message: MessageId, iteration: Option<i32>) -> CommandResult<Option<RPGState>> {
+        use wither::mongodb::options::FindOneOptions;
+
+        let mut filter = doc!{
+            "message": doc!{ "$eq": &Shim::from(message) },
+        };
+        if let Some(iteration) = iteration {
+            filter.insert("iteration", doc!{ "$eq": iteration });
+        }
+        let mut options = FindOneOptions::default();
+        options.sort = Some(doc!{
+            "iteration": -1,
+        });
+        Ok(RPGState::find_one(&self.rpg, Some(filter), Some(options)).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self, id))]
+    async fn rpg_state_by_id(&self, id: &ObjectId) -> CommandResult<Option<RPGState>> {
+        Ok(RPGState::find_one(
+            &self.rpg,
+            Some(doc!{
+                "_id": id,
+            }),
+            None,
+        ).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self))]
+    async fn rpg_states(&self, message: MessageId) -> CommandResult<Vec<RPGState>> {
+        find_all(&self.rpg, Some(doc!{
+            "message": doc!{ "$eq": &Shim::from(message) },
+        })).await
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self, state))]
+    async fn save_rpg_state(&self, state: &mut RPGState) -> CommandResult {
+        Ok(state.save(&self.rpg, None).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self))]
+    async fn rewind_limit(&self, guild: GuildId) -> CommandResult<Option<RPGRewindLimit>> {
+        Ok(RPGRewindLimit::find_one(
+            &self.base,
+            Some(doc!{
+                "server": doc!{ "$eq": &Shim::from(guild) },
+            }),
+            None,
+        ).await?)
+    }
+
+    #[cfg(feature = "rpg")]
+    #[instrument(level = "debug", skip(self, limit))]
+    async fn save_rewind_limit(&self, limit: &mut RPGRewindLimit) -> CommandResult {
+        Ok(limit.save(&self.base, None).await?)
+    }
+}
+
+/// Keeps every model as a document in process memory, for running without MongoDB.
+///
+/// Models round-trip through their documents, exactly as they would through MongoDB.
+#[derive(Default)]
+pub struct MemoryStorage {
+    collections: StdMutex<HashMap<&'static str, Vec<Document>>>,
+    #[cfg(feature = "rpg")]
+    documents: MemoryDocuments,
+}
+
+impl MemoryStorage {
+    fn find<T: Model>(&self, filter: impl Fn(&T) -> bool) -> CommandResult<Vec<T>> {
+        let collections = self.collections
+            .lock()
+            .map_err(|_| "Poisoned storage")?;
+        let mut found = Vec::new();
+        for document in collections.get(T::COLLECTION_NAME).into_iter().flatten() {
+            let model = T::instance_from_document(document.clone())?;
+            if filter(&model) {
+                found.push(model);
+            }
+        }
+        Ok(found)
+    }
+
+    fn find_one<T: Model>(&self, filter: impl Fn(&T) -> bool) -> CommandResult<Option<T>> {
+        Ok(self.find(filter)?.into_iter().next())
+    }
+
+    fn save<T: Model>(&self, model: &mut T) -> CommandResult {
+        let id = if let Some(id) = model.id() {
+            id
+        } else {
+            let id = ObjectId::new();
+            model.set_id(id.clone());
+            id
+        };
+        let document = model.document_from_instance()?;
+        let mut collections = self.collections
+            .lock()
+            .map_err(|_| "Poisoned storage")?;
+        let documents = collections
+            .entry(T::COLLECTION_NAME)
+            .or_default();
+        match documents
+            .iter_mut()
+            .find(|existing| existing.get_object_id("_id").ok() == Some(&id))
+        {
+            Some(existing) => *existing = document,
+            None => documents.push(document),
+        }
+        Ok(())
+    }
+
+    fn delete<T: Model>(&self, model: &T) -> CommandResult {
+        let id = model.id().ok_or("Model was never saved")?;
+        self.collections
+            .lock()
+            .map_err(|_| "Poisoned storage")?
+            .entry(T::COLLECTION_NAME)
+            .or_default()
+            .retain(|existing| existing.get_object_id("_id").ok() != Some(&id));
+        Ok(())
+    }
+}
+
+/// The page of the events, newest first, as `find_audit_page` reads it.
+fn audit_page(mut events: Vec<AuditEvent>, skip: usize, limit: usize) -> (Vec<AuditEvent>, usize) {
+    events.sort_by_key(|event| std::cmp::Reverse(event.at));
+    let total = events.len();
+    (events.into_iter().skip(skip).take(limit).collect(), total)
+}
+
+#[async_trait]
+impl Storage for MemoryStorage {
+    async fn credentials(&self) -> CommandResult<Option<DiscordCredentials>> {
+        self.find_one(|_: &DiscordCredentials| true)
+    }
+
+    async fn save_credentials(&self, credentials: &mut DiscordCredentials) -> CommandResult {
+        self.save(credentials)
+    }
+
+    async fn role_associations(&self, channel: ChannelId, guild: GuildId) -> CommandResult<Vec<RoleAssociation>> {
+        self.find(|association: &RoleAssociation|
+            association.channel == Some(channel)
+            || association.server == Some(guild)
+        )
+    }
+
+    async fn guild_role_associations(&self, channels: Vec<ChannelId>, guild: GuildId) -> CommandResult<Vec<RoleAssociation>> {
+        self.find(|association: &RoleAssociation|
+            association.channel.map_or(false, |channel| channels.contains(&channel))
+            || association.server == Some(guild)
+        )
+    }
+
+    async fn save_role_association(&self, association: &mut RoleAssociation) -> CommandResult {
+        self.save(association)
+    }
+
+    async fn role_associations_of(&self, role: RoleId) -> CommandResult<Vec<RoleAssociation>> {
+        self.find(|association: &RoleAssociation| association.role == role)
+    }
+
+    async fn server_role_associations(&self, guild: GuildId, channels: Vec<ChannelId>, roles: Vec<RoleId>) -> CommandResult<Vec<RoleAssociation>> {
+        self.find(|association: &RoleAssociation|
+            association.server == Some(guild)
+            || association.channel.map_or(false, |channel| channels.contains(&channel))
+            || roles.contains(&association.role)
+        )
+    }
+
+    async fn delete_role_association(&self, association: &RoleAssociation) -> CommandResult {
+        self.delete(association)
+    }
+
+    async fn role_statuses(&self) -> CommandResult<Vec<RoleStatus>> {
+        self.find(|_: &RoleStatus| true)
+    }
+
+    async fn guild_role_statuses(&self, guild: GuildId, roles: Vec<RoleId>) -> CommandResult<Vec<RoleStatus>> {
+        self.find(|status: &RoleStatus| match status.server {
+            Some(server) => server == guild,
+            None => roles.contains(&status.role),
+        })
+    }
+
+    async fn save_role_status(&self, status: &mut RoleStatus) -> CommandResult {
+        self.save(status)
+    }
+
+    async fn role_menus(&self) -> CommandResult<Vec<RoleMenu>> {
+        self.find(|_: &RoleMenu| true)
+    }
+
+    async fn role_menu(&self, message: MessageId) -> CommandResult<Option<RoleMenu>> {
+        self.find_one(|menu: &RoleMenu| menu.message == message)
+    }
+
+    async fn save_role_menu(&self, menu: &mut RoleMenu) -> CommandResult {
+        self.save(menu)
+    }
+
+    async fn exclusive_groups(&self, guild: GuildId) -> CommandResult<Vec<ExclusiveGroup>> {
+        self.find(|group: &ExclusiveGroup| group.server == guild)
+    }
+
+    async fn save_exclusive_group(&self, group: &mut ExclusiveGroup) -> CommandResult {
+        self.save(group)
+    }
+
+    async fn delete_exclusive_group(&self, group: &ExclusiveGroup) -> CommandResult {
+        self.delete(group)
+    }
+
+    async fn guild_configs(&self) -> CommandResult<Vec<GuildConfig>> {
+        self.find(|_: &GuildConfig| true)
+    }
+
+    async fn guild_config(&self, guild: GuildId) -> CommandResult<Option<GuildConfig>> {
+        self.find_one(|config: &GuildConfig| config.server == guild)
+    }
+
+    async fn save_guild_config(&self, config: &mut GuildConfig) -> CommandResult {
+        self.save(config)
+    }
+
+    async fn user_audit_events(&self, guild: GuildId, user: UserId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)> {
+        let events = self.find(|event: &AuditEvent| event.server == guild && (event.user == user || event.member == Some(user)))?;
+        Ok(audit_page(events, skip, limit))
+    }
+
+    async fn role_audit_events(&self, guild: GuildId, role: RoleId, skip: usize, limit: usize) -> CommandResult<(Vec<AuditEvent>, usize)> {
+        let events = self.find(|event: &AuditEvent| event.server == guild && event.roles.contains(&role))?;
+        Ok(audit_page(events, skip, limit))
+    }
+
+    async fn save_audit_event(&self, event: &mut AuditEvent) -> CommandResult {
+        self.save(event)
+    }
+
+    async fn role_requests(&self, guild: Option<GuildId>) -> CommandResult<Vec<RoleRequest>> {
+        self.find(|request: &RoleRequest| guild.map_or(true, |guild| request.server == guild))
+    }
+
+    async fn role_request(&self, message: MessageId) -> CommandResult<Option<RoleRequest>> {
+        self.find_one(|request: &RoleRequest| request.message == message)
+    }
+
+    async fn save_role_request(&self, request: &mut RoleRequest) -> CommandResult {
+        self.save(request)
+    }
+
+    async fn delete_role_request(&self, request: &RoleRequest) -> CommandResult {
+        self.delete(request)
+    }
+
+    async fn due_role_expiries(&self, now: i64) -> CommandResult<Vec<RoleExpiry>> {
+        self.find(|expiry: &RoleExpiry| expiry.expires_at <= now || (!expiry.warned && expiry.warn_at <= now))
+    }
+
+    async fn role_expiry(&self, guild: GuildId, user: UserId, role: RoleId) -> CommandResult<Option<RoleExpiry>> {
+        self.find_one(|expiry: &RoleExpiry| expiry.server == guild && expiry.user == user && expiry.role == role)
+    }
+
+    async fn role_expiries_of(&self, guild: GuildId, role: RoleId) -> CommandResult<Vec<RoleExpiry>> {
+        self.find(|expiry: &RoleExpiry| expiry.server == guild && expiry.role == role)
+    }
+
+    async fn save_role_expiry(&self, expiry: &mut RoleExpiry) -> CommandResult {
+        self.save(expiry)
+    }
+
+    async fn delete_role_expiry(&self, expiry: &RoleExpiry) -> CommandResult {
+        self.delete(expiry)
+    }
+
+    async fn member_activity(&self, guild: GuildId, user: UserId) -> CommandResult<Option<MemberActivity>> {
+        self.find_one(|activity: &MemberActivity| activity.server == guild && activity.user == user)
+    }
+
+    async fn guild_activity(&self, guild: GuildId) -> CommandResult<Vec<MemberActivity>> {
+        self.find(|activity: &MemberActivity| activity.server == guild)
+    }
+
+    async fn save_member_activity(&self, activity: &mut MemberActivity) -> CommandResult {
+        self.save(activity)
+    }
+
+    async fn systems(&self, guild: Option<GuildId>) -> CommandResult<Vec<System>> {
+        self.find(|system: &System| guild.map_or(true, |guild| system.server == guild))
+    }
+
+    async fn system(&self, guild: GuildId, name: &str) -> CommandResult<Option<System>> {
+        self.find_one(|system: &System| system.server == guild && system.sub_system.name == name)
+    }
+
+    #[cfg(feature = "rpg")]
+    fn rpg_documents(&self) -> &dyn Documents {
+        &self.documents
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_channels(&self) -> CommandResult<Vec<RPGChannel>> {
+        self.find(|_: &RPGChannel| true)
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn save_rpg_channel(&self, channel: &mut RPGChannel) -> CommandResult {
+        self.save(channel)
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_state(&self, message: MessageId, iteration: Option<i32>) -> CommandResult<Option<RPGState>> {
+        Ok(self
+            .find(|state: &RPGState|
+                state.message == message
+                && iteration.map_or(true, |iteration| state.iteration == iteration)
+            )?
+            .into_iter()
+            .max_by_key(|state| state.iteration))
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_state_by_id(&self, id: &ObjectId) -> CommandResult<Option<RPGState>> {
+        self.find_one(|state: &RPGState| state.id.as_ref() == Some(id))
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn rpg_states(&self, message: MessageId) -> CommandResult<Vec<RPGState>> {
+        self.find(|state: &RPGState| state.message == message)
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn save_rpg_state(&self, state: &mut RPGState) -> CommandResult {
+        self.save(state)
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn rewind_limit(&self, guild: GuildId) -> CommandResult<Option<RPGRewindLimit>> {
+        self.find_one(|limit: &RPGRewindLimit| limit.server == guild)
+    }
+
+    #[cfg(feature = "rpg")]
+    async fn save_rewind_limit(&self, limit: &mut RPGRewindLimit) -> CommandResult {
+        self.save(limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    fn association(channel: Option<u64>, server: Option<u64>, role: u64) -> RoleAssociation {
+        RoleAssociation {
+            id: None,
+            channel: channel.map(ChannelId),
+            server: server.map(GuildId),
+            role: RoleId(role),
+            alias: None,
+            approval: false,
+        }
+    }
+
+    fn roles(associations: &[RoleAssociation]) -> Vec<RoleId> {
+        let mut roles: Vec<RoleId> = associations
+            .iter()
+            .map(|association| association.role)
+            .collect();
+        roles.sort();
+        roles
+    }
+
+    #[test]
+    fn role_associations_of_the_channel_and_server() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            storage.save_role_association(&mut association(Some(10), None, 1)).await?;
+            storage.save_role_association(&mut association(Some(10), None, 2)).await?;
+            storage.save_role_association(&mut association(Some(11), None, 3)).await?;
+            storage.save_role_association(&mut association(None, Some(100), 4)).await?;
+            storage.save_role_association(&mut association(None, Some(200), 5)).await?;
+
+            let found = storage.role_associations(ChannelId(10), GuildId(100)).await?;
+            assert_eq!(roles(&found), vec![RoleId(1), RoleId(2), RoleId(4)]);
+            let found = storage.guild_role_associations(vec![ChannelId(10), ChannelId(11)], GuildId(100)).await?;
+            assert_eq!(roles(&found), vec![RoleId(1), RoleId(2), RoleId(3), RoleId(4)]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn saving_again_replaces_and_deleting_removes() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            let mut saved = association(Some(10), None, 1);
+            storage.save_role_association(&mut saved).await?;
+            assert!(saved.id.is_some());
+
+            saved.alias = Some("artists".into());
+            storage.save_role_association(&mut saved).await?;
+            let found = storage.role_associations_of(RoleId(1)).await?;
+            assert_eq!(found.len(), 1);
+            assert_eq!(found[0].alias.as_deref(), Some("artists"));
+
+            storage.delete_role_association(&saved).await?;
+            assert!(storage.role_associations_of(RoleId(1)).await?.is_empty());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn role_associations_of_every_channel() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            storage.save_role_association(&mut association(Some(10), None, 1)).await?;
+            storage.save_role_association(&mut association(Some(11), None, 1)).await?;
+            storage.save_role_association(&mut association(None, Some(100), 1)).await?;
+            storage.save_role_association(&mut association(Some(10), None, 2)).await?;
+
+            assert_eq!(storage.role_associations_of(RoleId(1)).await?.len(), 3);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn role_associations_of_the_server_its_channels_and_roles() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            storage.save_role_association(&mut association(None, Some(100), 1)).await?;
+            storage.save_role_association(&mut association(Some(10), None, 2)).await?;
+            // A deleted channel, traced back through its role
+            storage.save_role_association(&mut association(Some(12), None, 3)).await?;
+            storage.save_role_association(&mut association(Some(20), None, 4)).await?;
+            storage.save_role_association(&mut association(None, Some(200), 5)).await?;
+
+            let found = storage.server_role_associations(GuildId(100), vec![ChannelId(10)], vec![RoleId(3)]).await?;
+            assert_eq!(roles(&found), vec![RoleId(1), RoleId(2), RoleId(3)]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn role_statuses_of_the_server_and_older_ones_of_its_roles() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            for (role, server) in &[(1, Some(100)), (2, None), (3, None), (4, Some(200))] {
+                storage.save_role_status(&mut RoleStatus {
+                    id: None,
+                    role: RoleId(*role),
+                    server: server.map(GuildId),
+                    rules: Vec::new(),
+                }).await?;
+            }
+
+            let mut found: Vec<RoleId> = storage
+                .guild_role_statuses(GuildId(100), vec![RoleId(2), RoleId(4)])
+                .await?
+                .into_iter()
+                .map(|status| status.role)
+                .collect();
+            found.sort();
+            assert_eq!(found, vec![RoleId(1), RoleId(2)]);
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn credentials_load_once_saved() -> CommandResult {
+        let storage = MemoryStorage::default();
+        block_on(async {
+            assert!(storage.credentials().await?.is_none());
+            storage.save_credentials(&mut DiscordCredentials {
+                id: None,
+                private: String::new(),
+                public: String::new(),
+                token: "token".into(),
+                bot_id: String::new(),
+                prefix: "!".into(),
+                operator: UserId(1),
+                interactions_address: None,
+            }).await?;
+            let credentials = storage.credentials().await?.ok_or("Credentials not saved")?;
+            assert_eq!(credentials.token, "token");
+            assert_eq!(credentials.operator, UserId(1));
+            Ok(())
+        })
+    }
+}
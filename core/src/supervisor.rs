@@ -0,0 +1,377 @@
+use std::{
+    collections::{
+        HashMap,
+        VecDeque,
+    },
+    process::{
+        ExitStatus,
+        Stdio,
+    },
+    sync::{
+        Arc,
+        Mutex as StdMutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use serenity::{
+    framework::standard::CommandResult,
+    model::prelude::*,
+    prelude::*,
+};
+use tokio::{
+    io::{
+        AsyncBufReadExt,
+        AsyncRead,
+        AsyncWriteExt,
+        BufReader,
+    },
+    process::{
+        Child,
+        Command,
+    },
+    sync::oneshot,
+    time::delay_for,
+};
+
+use crate::models::{
+    Runner,
+    SubSystem,
+    System,
+};
+
+/// How long the `stop` runners have to bring a sub-system down before it is killed.
+const STOP_GRACE: Duration = Duration::from_secs(30);
+const STOP_POLL: Duration = Duration::from_millis(250);
+/// Lines of output retained for each supervised sub-system.
+pub const LOG_LINES: usize = 1000;
+
+type Log = Arc<StdMutex<VecDeque<String>>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    Starting,
+    Running,
+    Stopping,
+    Stopped,
+    Failed,
+}
+
+#[derive(Clone, Debug)]
+pub struct Status {
+    pub state: State,
+    pub pid: Option<u32>,
+    pub since: Instant,
+    pub last_exit: Option<ExitStatus>,
+}
+
+impl Status {
+    fn is_active(&self) -> bool {
+        match self.state {
+            State::Starting | State::Running | State::Stopping => true,
+            State::Stopped | State::Failed => false,
+        }
+    }
+
+    fn enter(&mut self, state: State) {
+        self.state = state;
+        self.since = Instant::now();
+    }
+}
+
+struct Supervised {
+    status: Arc<StdMutex<Status>>,
+    log: Log,
+    kill: Option<oneshot::Sender<()>>,
+}
+
+#[derive(Default)]
+pub struct Supervisor {
+    processes: Mutex<HashMap<(GuildId, String), Supervised>>,
+}
+
+impl TypeMapKey for Supervisor {
+    type Value = Arc<Supervisor>;
+}
+
+impl Supervisor {
+    /// Runs the `boot` sequence of every system, continuing past failures.
+    pub async fn boot(&self, systems: &[System]) -> CommandResult {
+        let mut errors = Vec::new();
+        for system in systems {
+            if let Err(e) = run_sequence(&system.boot).await {
+                errors.push(format!("{} boot: {}", system.sub_system.name, e));
+            }
+        }
+        collected(errors)
+    }
+
+    /// Stops every running sub-system, then runs the `shutdown` sequence of every system.
+    pub async fn shutdown(&self, systems: &[System]) -> CommandResult {
+        let mut errors = Vec::new();
+        for system in systems {
+            if let Err(e) = self.stop(system.server, &system.sub_system).await {
+                errors.push(format!("{} stop: {}", system.sub_system.name, e));
+            }
+            if let Err(e) = run_sequence(&system.shutdown).await {
+                errors.push(format!("{} shutdown: {}", system.sub_system.name, e));
+            }
+        }
+        collected(errors)
+    }
+
+    /// Runs the `start` runners of a sub-system, supervising the last one.
+    ///
+    /// Returns false if the sub-system is already active.
+    pub async fn start(&self, guild: GuildId, sub_system: &SubSystem) -> CommandResult<bool> {
+        let (supervised, preparation) = match sub_system.start.split_last() {
+            Some(split) => split,
+            None => return Err(format!("{} has no start runners", sub_system.name).into()),
+        };
+
+        let (status, log) = {
+            let mut processes = self.processes.lock().await;
+            let entry = processes
+                .entry((guild, sub_system.name.clone()))
+                .or_insert_with(|| Supervised {
+                    status: Arc::new(StdMutex::new(Status {
+                        state: State::Stopped,
+                        pid: None,
+                        since: Instant::now(),
+                        last_exit: None,
+                    })),
+                    log: Default::default(),
+                    kill: None,
+                });
+            let mut status = entry.status.lock().unwrap();
+            if status.is_active() {
+                return Ok(false);
+            }
+            status.enter(State::Starting);
+            drop(status);
+            (entry.status.clone(), entry.log.clone())
+        };
+
+        let child = match run_sequence(preparation).await {
+            Ok(()) => spawn(supervised, true),
+            Err(e) => Err(e),
+        };
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                status.lock().unwrap().enter(State::Failed);
+                return Err(e);
+            },
+        };
+
+        // The handle is stored before the sub-system is seen running, so `stop` always has it
+        let (kill, mut killed) = oneshot::channel();
+        {
+            let mut processes = self.processes.lock().await;
+            if let Some(entry) = processes.get_mut(&(guild, sub_system.name.clone())) {
+                entry.kill = Some(kill);
+            }
+            let mut status = status.lock().unwrap();
+            status.enter(State::Running);
+            status.pid = Some(child.id());
+        }
+        if let Some(stdout) = child.stdout.take() {
+            tokio::spawn(capture(stdout, log.clone()));
+        }
+        if let Some(stderr) = child.stderr.take() {
+            tokio::spawn(capture(stderr, log));
+        }
+
+        tokio::spawn(async move {
+            let exit = tokio::select! {
+                exit = &mut child => exit,
+                _ = &mut killed => {
+                    let _ = child.kill();
+                    child.await
+                },
+            };
+            let mut status = status.lock().unwrap();
+            status.pid = None;
+            match exit {
+                Ok(exit) => {
+                    let state = if exit.success() || status.state == State::Stopping {
+                        State::Stopped
+                    } else {
+                        State::Failed
+                    };
+                    status.last_exit = Some(exit);
+                    status.enter(state);
+                },
+                Err(_) => status.enter(State::Failed),
+            }
+        });
+
+        Ok(true)
+    }
+
+    /// Runs the `stop` runners of a sub-system, killing it if it outlives them.
+    ///
+    /// Returns false if the sub-system is not running.
+    pub async fn stop(&self, guild: GuildId, sub_system: &SubSystem) -> CommandResult<bool> {
+        let (status, kill) = {
+            let mut processes = self.processes.lock().await;
+            let entry = match processes.get_mut(&(guild, sub_system.name.clone())) {
+                Some(entry) => entry,
+                None => return Ok(false),
+            };
+            let mut status = entry.status.lock().unwrap();
+            if status.state != State::Running {
+                return Ok(false);
+            }
+            status.enter(State::Stopping);
+            drop(status);
+            (entry.status.clone(), entry.kill.take())
+        };
+
+        let result = run_sequence(&sub_system.stop).await;
+
+        let deadline = Instant::now() + STOP_GRACE;
+        while state(&status) == State::Stopping && Instant::now() < deadline {
+            delay_for(STOP_POLL).await;
+        }
+        if let Some(kill) = kill {
+            let _ = kill.send(());
+        }
+        while state(&status) == State::Stopping {
+            delay_for(STOP_POLL).await;
+        }
+
+        result.map(|()| true)
+    }
+
+    pub async fn status(&self, guild: GuildId, name: &str) -> Option<Status> {
+        self.processes
+            .lock()
+            .await
+            .get(&(guild, name.to_string()))
+            .map(|entry| entry.status.lock().unwrap().clone())
+    }
+
+    /// The last `count` captured lines of a sub-system's output, oldest first.
+    pub async fn logs(&self, guild: GuildId, name: &str, count: usize) -> Option<Vec<String>> {
+        self.processes
+            .lock()
+            .await
+            .get(&(guild, name.to_string()))
+            .map(|entry| {
+                let log = entry.log.lock().unwrap();
+                log
+                    .iter()
+                    .skip(log.len().saturating_sub(count))
+                    .cloned()
+                    .collect()
+            })
+    }
+}
+
+/// Retains each line of output, decoding what isn't UTF-8 lossily rather than stopping there.
+async fn capture(output: impl AsyncRead + Unpin + Send + 'static, log: Log) {
+    let mut output = BufReader::new(output);
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+        match output.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {},
+        }
+        let text = String::from_utf8_lossy(&line);
+        let text = text.trim_end_matches(|c| c == '\n' || c == '\r');
+        let mut log = log.lock().unwrap();
+        if log.len() >= LOG_LINES {
+            log.pop_front();
+        }
+        log.push_back(text.to_string());
+    }
+}
+
+/// Every error on its own line, so one failing system doesn't hide the others.
+fn collected(errors: Vec<String>) -> CommandResult {
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join("\n").into())
+    }
+}
+
+fn state(status: &StdMutex<Status>) -> State {
+    status.lock().unwrap().state
+}
+
+fn spawn(runner: &Runner, captured: bool) -> CommandResult<Child> {
+    let (program, args) = runner.command
+        .split_first()
+        .ok_or("Runner has an empty command")?;
+    let mut command = Command::new(program);
+    command
+        .args(args)
+        .stdin(if runner.payload.is_some() { Stdio::piped() } else { Stdio::null() });
+    if captured {
+        command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+    }
+    let mut child = command.spawn()?;
+
+    if let Some(payload) = &runner.payload {
+        let mut stdin = child.stdin.take().ok_or("Runner stdin missing")?;
+        let payload = payload.clone();
+        // Writing is detached so a child that never reads cannot stall the caller.
+        tokio::spawn(async move {
+            let _ = stdin.write_all(payload.as_bytes()).await;
+        });
+    }
+
+    Ok(child)
+}
+
+async fn run_sequence(runners: &[Runner]) -> CommandResult {
+    for runner in runners {
+        let exit = spawn(runner, false)?.await?;
+        if !exit.success() {
+            return Err(format!("{:?} exited with {}", runner.command, exit).into());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn every_error_is_kept() {
+        assert!(collected(Vec::new()).is_ok());
+        let error = collected(vec!["a boot: failed".to_string(), "b boot: failed".to_string()])
+            .unwrap_err()
+            .to_string();
+        assert_eq!(error, "a boot: failed\nb boot: failed");
+    }
+
+    #[test]
+    fn capture_keeps_reading_past_invalid_output() {
+        let log = Log::default();
+        block_on(capture(&b"one\n\xff two\r\nthree"[..], log.clone()));
+        let log: Vec<String> = log.lock().unwrap().iter().cloned().collect();
+        assert_eq!(log, vec!["one", "\u{fffd} two", "three"]);
+    }
+
+    #[test]
+    fn capture_retains_only_the_latest_lines() {
+        let log = Log::default();
+        let output = (0..LOG_LINES + 2).map(|line| format!("{}\n", line)).collect::<String>();
+        block_on(capture(std::io::Cursor::new(output.into_bytes()), log.clone()));
+        let log = log.lock().unwrap();
+        assert_eq!(log.len(), LOG_LINES);
+        assert_eq!(log.front().map(String::as_str), Some("2"));
+    }
+}
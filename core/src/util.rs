@@ -1,30 +1,8 @@
 use serenity::model::prelude::*;
-use std::fmt::{Display, Formatter};
-use serenity::framework::standard::CommandResult;
-use crate::models::{RoleAssociation, Shim};
-use wither::{
-    mongodb::Database,
-    bson::doc,
-    Model,
+use std::{
+    fmt::{Display, Formatter},
+    time::Duration,
 };
-use futures::TryStreamExt;
-
-pub async fn get_role_associations(db: &Database, channel: ChannelId, guild: GuildId) -> CommandResult<Vec<RoleAssociation>> {
-    RoleAssociation::find(
-        db,
-        Some(doc!{
-                "$or": [
-                    { "channel": doc!{ "$eq": &Shim::from(channel) } },
-                    { "server": doc!{ "$eq": &Shim::from(guild) } },
-                ],
-            }),
-        None,
-    )
-        .await?
-        .try_collect()
-        .await
-        .map_err(Into::into)
-}
 
 #[derive(Debug, Clone, Copy)]
 pub struct Mentionable(MentionableImpl);
@@ -91,8 +69,178 @@ impl<T: Display> Display for OptionalDisplay<T> {
     }
 }
 
+/// Parses a message link, or a bare message ID in the given channel.
+pub fn parse_message_link(value: &str, channel: ChannelId) -> Option<(ChannelId, MessageId)> {
+    let value = value.trim_start_matches('<').trim_end_matches('>');
+    if !value.contains('/') {
+        return value.parse().ok().map(|message| (channel, MessageId(message)));
+    }
+    let mut parts = value.rsplit('/');
+    let message = parts.next()?.parse().ok()?;
+    let channel = parts.next()?.parse().ok()?;
+    Some((ChannelId(channel), MessageId(message)))
+}
+
+pub struct DurationDisplay(pub Duration);
+
+impl Display for DurationDisplay {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let seconds = self.0.as_secs();
+        let (days, hours, minutes, seconds) = (
+            seconds / 86400,
+            seconds / 3600 % 24,
+            seconds / 60 % 60,
+            seconds % 60,
+        );
+        if days > 0 {
+            write!(f, "{}d ", days)?;
+        }
+        if days > 0 || hours > 0 {
+            write!(f, "{}h ", hours)?;
+        }
+        if days > 0 || hours > 0 || minutes > 0 {
+            write!(f, "{}m ", minutes)?;
+        }
+        write!(f, "{}s", seconds)
+    }
+}
+
+/// A moment as the server's locale writes dates, with the time in UTC; ISO 8601 without a locale.
+pub struct DateDisplay<'a> {
+    /// Seconds since the Unix epoch.
+    pub at: i64,
+    pub locale: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DateOrder {
+    YearFirst,
+    MonthFirst,
+    DayFirst,
+}
+
+/// The order of a language tag's dates, from its language and region.
+fn date_order(locale: &str) -> DateOrder {
+    let mut subtags = locale.split(|c| c == '-' || c == '_');
+    let language = subtags.next().unwrap_or("");
+    let region = subtags.find(|subtag| subtag.len() == 2);
+    if region.map_or(false, |region| ["US", "PH", "FM"].iter().any(|us| region.eq_ignore_ascii_case(us))) {
+        return DateOrder::MonthFirst;
+    }
+    if ["zh", "ja", "ko", "hu", "lt", "mn", "sv"].iter().any(|first| language.eq_ignore_ascii_case(first)) {
+        return DateOrder::YearFirst;
+    }
+    DateOrder::DayFirst
+}
+
+/// The year, month and day of the days since the Unix epoch, in the proleptic Gregorian calendar.
+fn civil_date(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // Months count from March, so the leap day ends the year
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+impl Display for DateDisplay<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let (year, month, day) = civil_date(self.at.div_euclid(86400));
+        let seconds = self.at.rem_euclid(86400);
+        let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+        match self.locale.map_or(DateOrder::YearFirst, date_order) {
+            DateOrder::YearFirst => write!(f, "{}-{:02}-{:02}", year, month, day)?,
+            DateOrder::MonthFirst => write!(f, "{}/{}/{}", month, day, year)?,
+            DateOrder::DayFirst => write!(f, "{}/{}/{}", day, month, year)?,
+        }
+        write!(f, " {:02}:{:02} UTC", hours, minutes)
+    }
+}
+
+/// The longest duration `parse_duration` accepts, about five years.
+pub const MAX_DURATION: Duration = Duration::from_secs(5 * 365 * 24 * 60 * 60);
+
+/// Parses a count followed by a unit: `m`inutes, `h`ours, `d`ays, or `w`eeks, such as `7d`, up to `MAX_DURATION`.
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let split = value.len().checked_sub(1)?;
+    if !value.is_char_boundary(split) {
+        return None;
+    }
+    let (count, unit) = value.split_at(split);
+    let count: u64 = count.parse().ok().filter(|count| *count > 0)?;
+    let unit = match unit {
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+        .filter(|duration| *duration <= MAX_DURATION)
+}
+
 #[cfg(feature = "rpg")]
 pub struct RPGStateHolder {
     pub cache: cache_2q::Cache<MessageId, Option<crate::models::RPGState>>,
     pub lockout: std::collections::HashSet<MessageId>,
+    /// Render options as message components instead of reactions.
+    pub components: bool,
+    /// Set while shutting down, so no new moves start.
+    pub closing: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 2021-03-04 05:06:07 UTC.
+    const AT: i64 = 1_614_834_367;
+
+    fn date(at: i64, locale: Option<&str>) -> String {
+        DateDisplay { at, locale }.to_string()
+    }
+
+    #[test]
+    fn durations_parse_with_their_unit() {
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2h"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("7d"), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("1w"), Some(Duration::from_secs(7 * 24 * 60 * 60)));
+        assert_eq!(parse_duration("1825d"), Some(MAX_DURATION));
+    }
+
+    #[test]
+    fn durations_without_a_count_and_unit_are_rejected() {
+        for value in &["", "d", "0d", "-1d", "7", "7x", "7D", "1é", "18446744073709551615w", "15250284452471w", "261w"] {
+            assert_eq!(parse_duration(value), None, "{:?}", value);
+        }
+    }
+
+    #[test]
+    fn durations_show_their_largest_unit_down() {
+        assert_eq!(DurationDisplay(Duration::from_secs(5)).to_string(), "5s");
+        assert_eq!(DurationDisplay(Duration::from_secs(60 * 60 + 5)).to_string(), "1h 0m 5s");
+        assert_eq!(DurationDisplay(Duration::from_secs(2 * 86400)).to_string(), "2d 0h 0m 0s");
+    }
+
+    #[test]
+    fn dates_follow_the_locale() {
+        assert_eq!(date(AT, None), "2021-03-04 05:06 UTC");
+        assert_eq!(date(AT, Some("en-US")), "3/4/2021 05:06 UTC");
+        assert_eq!(date(AT, Some("en-GB")), "4/3/2021 05:06 UTC");
+        assert_eq!(date(AT, Some("fr")), "4/3/2021 05:06 UTC");
+        assert_eq!(date(AT, Some("zh-Hant-TW")), "2021-03-04 05:06 UTC");
+    }
+
+    #[test]
+    fn dates_cover_the_calendar() {
+        assert_eq!(date(0, None), "1970-01-01 00:00 UTC");
+        assert_eq!(date(951_782_400, None), "2000-02-29 00:00 UTC");
+        assert_eq!(date(-1, None), "1969-12-31 23:59 UTC");
+    }
 }
@@ -0,0 +1,93 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+};
+
+use async_trait::async_trait;
+use wither::{
+    bson::{
+        doc,
+        oid::ObjectId,
+        Document,
+    },
+    mongodb::{
+        Database,
+        options::ReplaceOptions,
+    },
+};
+use crate::Error;
+
+/// Storage of whole documents by ID, as needed by `LazyDB`.
+#[async_trait]
+pub trait Documents: Send + Sync {
+    async fn find_document(&self, collection: &str, id: &ObjectId)
+        -> Result<Option<Document>, Error>;
+
+    /// Inserts or replaces the document with the same `_id`.
+    async fn save_document(&self, collection: &str, id: &ObjectId, document: Document)
+        -> Result<(), Error>;
+
+    /// The MongoDB database behind these documents, for games that still take one.
+    fn database(&self) -> Option<&Database> {
+        None
+    }
+}
+
+#[async_trait]
+impl Documents for Database {
+    async fn find_document(&self, collection: &str, id: &ObjectId)
+        -> Result<Option<Document>, Error>
+    {
+        self.collection(collection)
+            .find_one(doc!{ "_id": id }, None)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn save_document(&self, collection: &str, id: &ObjectId, document: Document)
+        -> Result<(), Error>
+    {
+        let mut options = ReplaceOptions::default();
+        options.upsert = Some(true);
+        self.collection(collection)
+            .replace_one(doc!{ "_id": id }, document, options)
+            .await?;
+        Ok(())
+    }
+
+    fn database(&self) -> Option<&Database> {
+        Some(self)
+    }
+}
+
+/// Keeps documents in process memory, for running without MongoDB.
+#[derive(Default)]
+pub struct MemoryDocuments {
+    collections: Mutex<HashMap<String, HashMap<ObjectId, Document>>>,
+}
+
+#[async_trait]
+impl Documents for MemoryDocuments {
+    async fn find_document(&self, collection: &str, id: &ObjectId)
+        -> Result<Option<Document>, Error>
+    {
+        Ok(self.collections
+            .lock()
+            .map_err(|_| "Poisoned documents")?
+            .get(collection)
+            .and_then(|documents| documents.get(id))
+            .cloned())
+    }
+
+    async fn save_document(&self, collection: &str, id: &ObjectId, document: Document)
+        -> Result<(), Error>
+    {
+        self.collections
+            .lock()
+            .map_err(|_| "Poisoned documents")?
+            .entry(collection.to_string())
+            .or_default()
+            .insert(id.clone(), document);
+        Ok(())
+    }
+}
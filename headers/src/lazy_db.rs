@@ -2,18 +2,17 @@ use std::future::Future;
 
 use once_cell::sync::OnceCell;
 use wither::{
-    mongodb::Database,
     Model,
-    bson::{
-        doc,
-        oid::ObjectId,
-    },
+    bson::oid::ObjectId,
 };
 use serde::{
     Serialize,
     Deserialize,
 };
-use crate::Error;
+use crate::{
+    Documents,
+    Error,
+};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(transparent)]
@@ -23,20 +22,31 @@ pub struct LazyDB<T> {
     contents: OnceCell<T>,
 }
 
+async fn find<T: Model, D: Documents + ?Sized>(db: &D, id: &ObjectId) -> Result<T, Error> {
+    match db.find_document(T::COLLECTION_NAME, id).await? {
+        Some(document) =>
+            T::instance_from_document(document).map_err(Into::into),
+        None =>
+            Err("Value missing".into()),
+    }
+}
+
 impl<T: Model + Send + Sync> LazyDB<T> {
-    pub async fn make(value: T, db: &Database) -> Result<Self, Error> {
+    pub async fn make<D: Documents + ?Sized>(value: T, db: &D) -> Result<Self, Error> {
         let mut value = match Self::try_from(value) {
             Ok(value) => return Ok(value),
             Err(value) => value,
         };
-        value.save(db, None).await?;
+        let id = ObjectId::new();
+        value.set_id(id.clone());
+        db.save_document(T::COLLECTION_NAME, &id, value.document_from_instance()?).await?;
         Self::try_from(value)
             .map_err(|_| "Id not saved".into())
     }
 
-    pub fn take<'d>(
+    pub fn take<'d, D: Documents + ?Sized>(
         self,
-        db: &'d Database,
+        db: &'d D,
     ) -> Result<
         T,
         impl 'd + Future<Output=Result<T, Error>>
@@ -50,26 +60,13 @@ impl<T: Model + Send + Sync> LazyDB<T> {
         }
 
         Err(async move {
-            match T::find_one(
-                db,
-                Some(doc! {
-                    "_id": &id,
-                }),
-                None,
-            ).await {
-                Ok(Some(v)) =>
-                    Ok(v),
-                Err(e) =>
-                    Err(e.into()),
-                _ =>
-                    Err("Value missing".into()),
-            }
+            find(db, &id).await
         })
     }
 
-    pub fn get<'d: 'f, 's: 'f, 'f>(
+    pub fn get<'d: 'f, 's: 'f, 'f, D: Documents + ?Sized>(
         &'s self,
-        db: &'d Database,
+        db: &'d D,
     ) -> Result<
         &'s T,
         impl 'f + Future<Output=Result<&'s T, Error>>,
@@ -79,26 +76,14 @@ impl<T: Model + Send + Sync> LazyDB<T> {
         }
 
         Err(async move {
-            match T::find_one(
-                db,
-                Some(doc! {
-                    "_id": &self.id,
-                }),
-                None,
-            ).await {
-                Ok(Some(v)) =>
-                    Ok(self.contents.get_or_init(|| v)),
-                Err(e) =>
-                    Err(e.into()),
-                _ =>
-                    Err("Value missing".into()),
-            }
+            let value = find(db, &self.id).await?;
+            Ok(self.contents.get_or_init(|| value))
         })
     }
 
-    pub fn get_mut<'d: 'f, 's: 'f, 'f>(
+    pub fn get_mut<'d: 'f, 's: 'f, 'f, D: Documents + ?Sized>(
         &'s mut self,
-        db: &'d Database,
+        db: &'d D,
     ) -> Result<
         &'s mut T,
         impl 'f + Future<Output=Result<&'s mut T, Error>>,
@@ -111,36 +96,23 @@ impl<T: Model + Send + Sync> LazyDB<T> {
             }
         } else {
             Err(async move {
-                let value = T::find_one(
-                    db,
-                    Some(doc! {
-                        "_id": &self.id,
-                    }),
-                    None,
-                ).await;
-                match value {
-                    Ok(Some(v)) => {
-                        self.contents.get_or_init(|| v);
-                        Ok(self.contents.get_mut().unwrap())
-                    },
-                    Err(e) =>
-                        Err(e.into()),
-                    _ =>
-                        Err("Value missing".into()),
-                }
+                let value = find(db, &self.id).await?;
+                self.contents.get_or_init(|| value);
+                Ok(self.contents.get_mut().unwrap())
             })
         }
     }
 
-    pub async fn save_inner(&mut self, db: &Database) -> Result<(), Error> {
+    pub async fn save_inner<D: Documents + ?Sized>(&mut self, db: &D) -> Result<(), Error> {
         let id = ObjectId::new();
-        self.id = id.clone();
         let inner = match self.get_mut(db) {
             Ok(inner) => inner,
             Err(future) => future.await?,
         };
-        inner.set_id(id);
-        inner.save(db, None).await?;
+        inner.set_id(id.clone());
+        let document = inner.document_from_instance()?;
+        db.save_document(T::COLLECTION_NAME, &id, document).await?;
+        self.id = id;
         Ok(())
     }
 }
@@ -17,7 +17,12 @@ use serde::{
 pub use serenity::builder::CreateEmbed;
 pub use once_cell::sync::OnceCell;
 
+mod documents;
 mod lazy_db;
+pub use documents::{
+    Documents,
+    MemoryDocuments,
+};
 pub use lazy_db::LazyDB;
 
 type State = Box<dyn CharacterState>;
@@ -55,17 +60,54 @@ impl Action {
     }
 }
 
+/// A game, loading whatever it keeps behind a `LazyDB` from the documents it is given.
+///
+/// The bot calls the `_in` methods, with MongoDB or with documents held in memory to run without it.
+/// Games implement either those or the older methods taking a `Database`; each defaults to the other,
+/// so a game implementing neither recurses forever.
 #[typetag::serde(tag = "state")]
 #[async_trait]
 pub trait CharacterState: Debug + Send + Sync {
     async fn action(self: Box<Self>, database: &Database, reaction: &str)
-        -> Result<Action, Error>;
+        -> Result<Action, Error>
+    {
+        self.action_in(database, reaction).await
+    }
 
     async fn reactions(&self, database: &Database)
-        -> Result<Reactions, Error>;
+        -> Result<Reactions, Error>
+    {
+        self.reactions_in(database).await
+    }
 
     async fn display(&self, database: &Database)
-        -> Result<(Reactions, CreateEmbed), Error>;
+        -> Result<(Reactions, CreateEmbed), Error>
+    {
+        self.display_in(database).await
+    }
+
+    async fn action_in(self: Box<Self>, documents: &dyn Documents, reaction: &str)
+        -> Result<Action, Error>
+    {
+        self.action(required_database(documents)?, reaction).await
+    }
+
+    async fn reactions_in(&self, documents: &dyn Documents)
+        -> Result<Reactions, Error>
+    {
+        self.reactions(required_database(documents)?).await
+    }
+
+    async fn display_in(&self, documents: &dyn Documents)
+        -> Result<(Reactions, CreateEmbed), Error>
+    {
+        self.display(required_database(documents)?).await
+    }
+}
+
+fn required_database(documents: &dyn Documents) -> Result<&Database, Error> {
+    documents.database()
+        .ok_or_else(|| "This game needs MongoDB".into())
 }
 
 pub fn add_reactions(embed: &mut CreateEmbed, reactions: &Reactions) {